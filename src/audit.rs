@@ -0,0 +1,114 @@
+//! Append-only log of every verification result, see `Setting::audit_log`
+
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Format of `Setting::audit_log`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum AuditFormat {
+    /// A JSON object per line
+    #[default]
+    Json,
+    /// A row per result, with a header when the file is created
+    Csv,
+}
+
+/// Columns of `AuditFormat::Csv`, in the order of the fields of `AuditEntry`
+const CSV_HEADER: &str = "timestamp,pid,user,file,expected,actual,result,duration_ms";
+
+/// A verification result as written to the audit log
+#[derive(Debug, Serialize)]
+pub struct AuditEntry<'a> {
+    /// When the result was written, in RFC 3339 UTC
+    pub timestamp: String,
+    /// Process that verified the file
+    pub pid: u32,
+    /// User that ran the process, from `$USER` or `%USERNAME%`
+    pub user: String,
+    /// Path as written in the hash list
+    pub file: &'a str,
+    /// Hash from the hash list
+    pub expected: &'a str,
+    /// Hash of the file, empty if it couldn't be hashed
+    pub actual: &'a str,
+    /// `ok`, `fail` or `error`
+    pub result: &'static str,
+    /// Time spent on the file
+    pub duration_ms: u128,
+}
+
+impl<'a> AuditEntry<'a> {
+    /// Entry for the current process and time
+    pub fn new(
+        file: &'a str,
+        expected: &'a str,
+        actual: &'a str,
+        result: &'static str,
+        duration: Duration,
+    ) -> Self {
+        Self {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            pid: std::process::id(),
+            user: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_default(),
+            file,
+            expected,
+            actual,
+            result,
+            duration_ms: duration.as_millis(),
+        }
+    }
+
+    fn line(&self, format: AuditFormat) -> String {
+        match format {
+            AuditFormat::Json => {
+                serde_json::to_string(self).expect("Audit entries are serializable")
+            }
+            AuditFormat::Csv => [
+                csv_field(&self.timestamp),
+                self.pid.to_string(),
+                csv_field(&self.user),
+                csv_field(self.file),
+                csv_field(self.expected),
+                csv_field(self.actual),
+                self.result.to_string(),
+                self.duration_ms.to_string(),
+            ]
+            .join(","),
+        }
+    }
+}
+
+/// Quoted if it contains a comma, quote or line break
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Existing entries are never truncated or rewritten. Every write is a single `write_all` of
+/// whole lines, so processes logging to the same file don't interleave within a line
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Write the CSV header if the audit log at `path` is new or empty. Called once before a run
+pub fn start_audit_log(path: &Path, format: AuditFormat) -> io::Result<()> {
+    let mut file = open_append(path)?;
+    if format == AuditFormat::Csv && file.metadata()?.len() == 0 {
+        file.write_all(format!("{CSV_HEADER}\n").as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Append `entry` to the audit log at `path`
+pub fn append_audit_entry(path: &Path, format: AuditFormat, entry: &AuditEntry) -> io::Result<()> {
+    open_append(path)?.write_all(format!("{}\n", entry.line(format)).as_bytes())
+}
@@ -0,0 +1,201 @@
+//! Per-file timing of a verification run, see `Setting::benchmark`
+
+use crate::hash::{hash_file, HashAlgorithm, HashError, StatusWrapper, BYTES_PER_MB};
+use crate::Setting;
+use indicatif::ProgressBar;
+use serde::{Serialize, Serializer};
+use size::Size;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Files listed in each table of `BenchmarkSummary`
+const TABLE_LEN: usize = 10;
+
+/// Block sizes tried by `profile_block_sizes`, 4 KiB to 256 KiB
+pub const PROFILE_BLOCK_SIZES: [usize; 7] = [
+    4 * 1024,
+    8 * 1024,
+    16 * 1024,
+    32 * 1024,
+    64 * 1024,
+    128 * 1024,
+    256 * 1024,
+];
+
+/// Times each block size is tried, keeping the fastest to leave out noise
+const PROFILE_RUNS: usize = 3;
+
+/// Block sizes this close to the fastest are as good, so the smallest of them is recommended
+const PROFILE_TOLERANCE: f64 = 0.05;
+
+/// Timing of a hashed file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileStats {
+    /// Path as written in the hash list
+    pub path: String,
+    /// Bytes read, 0 for files from the `Setting::incremental` cache
+    pub bytes: u64,
+    /// Time from opening the file to comparing its hash
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
+    pub duration: Duration,
+}
+
+impl FileStats {
+    /// MB/s, or `None` if nothing was read
+    pub fn throughput(&self) -> Option<f64> {
+        let secs = self.duration.as_secs_f64();
+        (self.bytes > 0 && secs > 0.0).then(|| self.bytes as f64 / secs / BYTES_PER_MB)
+    }
+}
+
+fn serialize_millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+/// Throughput statistics of a run, printed as a table with `Display`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchmarkSummary {
+    /// Files that were hashed
+    pub file_num: usize,
+    /// Bytes read in total
+    pub total_bytes: u64,
+    /// Median MB/s of the files that were read
+    pub median_mb_per_s: f64,
+    /// 95th percentile MB/s of the files that were read
+    pub p95_mb_per_s: f64,
+    /// Up to 10 files with the longest duration, slowest first
+    pub slowest: Vec<FileStats>,
+    /// Up to 10 files with the highest throughput, fastest first
+    pub fastest: Vec<FileStats>,
+}
+
+impl BenchmarkSummary {
+    /// Summarize `file_stats`, in any order
+    pub fn new(file_stats: &[FileStats]) -> Self {
+        let mut throughputs: Vec<f64> = file_stats.iter().filter_map(|x| x.throughput()).collect();
+        throughputs.sort_by(f64::total_cmp);
+
+        let mut slowest = file_stats.to_vec();
+        slowest.sort_by_key(|x| std::cmp::Reverse(x.duration));
+        slowest.truncate(TABLE_LEN);
+
+        let mut fastest: Vec<_> = file_stats
+            .iter()
+            .filter(|x| x.throughput().is_some())
+            .cloned()
+            .collect();
+        fastest.sort_by(|a, b| {
+            b.throughput()
+                .unwrap_or(0.0)
+                .total_cmp(&a.throughput().unwrap_or(0.0))
+        });
+        fastest.truncate(TABLE_LEN);
+
+        Self {
+            file_num: file_stats.len(),
+            total_bytes: file_stats.iter().map(|x| x.bytes).sum(),
+            median_mb_per_s: percentile(&throughputs, 0.5),
+            p95_mb_per_s: percentile(&throughputs, 0.95),
+            slowest,
+            fastest,
+        }
+    }
+}
+
+/// Nearest-rank percentile of ascending `sorted`, 0 if empty
+fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+    let rank = (fraction * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.saturating_sub(1)).copied().unwrap_or(0.0)
+}
+
+fn write_table(f: &mut Formatter<'_>, title: &str, files: &[FileStats]) -> std::fmt::Result {
+    writeln!(f, "{title}:")?;
+    writeln!(
+        f,
+        "  {:>12}  {:>10}  {:>12}  Path",
+        "Duration", "Size", "Throughput"
+    )?;
+    for file in files {
+        let throughput = file
+            .throughput()
+            .map(|x| format!("{x:.1} MB/s"))
+            .unwrap_or_else(|| "-".to_string());
+        writeln!(
+            f,
+            "  {:>12}  {:>10}  {:>12}  {}",
+            format!("{:.1} ms", file.duration.as_secs_f64() * 1000.0),
+            Size::from_bytes(file.bytes).to_string(),
+            throughput,
+            file.path
+        )?;
+    }
+    Ok(())
+}
+
+impl Display for BenchmarkSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Files: {}, Read: {}",
+            self.file_num,
+            Size::from_bytes(self.total_bytes)
+        )?;
+        writeln!(
+            f,
+            "Median: {:.1} MB/s, 95th percentile: {:.1} MB/s",
+            self.median_mb_per_s, self.p95_mb_per_s
+        )?;
+        writeln!(f)?;
+        write_table(f, "Slowest files", &self.slowest)?;
+        writeln!(f)?;
+        write_table(f, "Fastest files", &self.fastest)
+    }
+}
+
+/// MB/s of hashing `file_path` with each of `PROFILE_BLOCK_SIZES`, in that order. The file is
+/// read once beforehand so every block size reads it from the page cache. Reads are never
+/// memory-mapped or limited by `Setting::rate_limit` and `Setting::cpu_limit`
+pub fn profile_block_sizes(
+    file_path: &Path,
+    algorithm: HashAlgorithm,
+    setting: &Setting,
+) -> Result<Vec<(usize, f64)>, HashError> {
+    let status = StatusWrapper::ProgressBar(ProgressBar::hidden(), Default::default());
+    let mut setting = Setting {
+        mmap: false,
+        mmap_threshold: u64::MAX,
+        rate_limit: None,
+        cpu_limit: None,
+        ..setting.clone()
+    };
+    hash_file(file_path, algorithm, &setting, &status)?;
+    let len = std::fs::metadata(file_path)
+        .map_err(|e| HashError::from_read(file_path, e))?
+        .len();
+
+    let mut results = Vec::with_capacity(PROFILE_BLOCK_SIZES.len());
+    for block_size in PROFILE_BLOCK_SIZES {
+        setting.block_size = block_size;
+        let mut fastest = Duration::MAX;
+        for _ in 0..PROFILE_RUNS {
+            let start = Instant::now();
+            hash_file(file_path, algorithm, &setting, &status)?;
+            fastest = fastest.min(start.elapsed());
+        }
+        let throughput = len as f64 / fastest.as_secs_f64().max(f64::EPSILON) / BYTES_PER_MB;
+        results.push((block_size, throughput));
+    }
+    Ok(results)
+}
+
+/// Smallest block size of `profile` within `PROFILE_TOLERANCE` of the fastest, as larger blocks
+/// only cost memory past that point. `None` if `profile` is empty
+pub fn recommend_block_size(profile: &[(usize, f64)]) -> Option<usize> {
+    let fastest = profile.iter().map(|x| x.1).max_by(f64::total_cmp)?;
+    profile
+        .iter()
+        .filter(|x| x.1 >= fastest * (1.0 - PROFILE_TOLERANCE))
+        .map(|x| x.0)
+        .min()
+}
@@ -0,0 +1,168 @@
+//! Cache of unchanged files for incremental runs, and of hard links within a run
+
+use crate::hash::HashAlgorithm;
+use color_eyre::Result;
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Stored next to the hash list
+const CACHE_FILE_NAME: &str = ".md5check_cache";
+
+/// Cache file used for the hash list at `hash_list`
+pub fn cache_path(hash_list: &Path) -> PathBuf {
+    hash_list
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(CACHE_FILE_NAME)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheEntry {
+    path: String,
+    /// Nanoseconds since the Unix epoch
+    mtime: u64,
+    size: u64,
+    /// Algorithms with the same digest length can't be told apart from the hash
+    #[serde(serialize_with = "serialize_algorithm")]
+    #[serde(deserialize_with = "deserialize_algorithm")]
+    algorithm: HashAlgorithm,
+    last_verified_hash: String,
+}
+
+impl CacheEntry {
+    fn new(path: &str, algorithm: HashAlgorithm, hash: String) -> Option<Self> {
+        let (mtime, size) = file_stamp(path)?;
+        Some(Self {
+            path: path.to_string(),
+            mtime,
+            size,
+            algorithm,
+            last_verified_hash: hash,
+        })
+    }
+}
+
+/// Same names as `--algorithm`
+fn serialize_algorithm<S: Serializer>(
+    algorithm: &HashAlgorithm,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&algorithm.name())
+}
+
+fn deserialize_algorithm<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashAlgorithm, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse().map_err(D::Error::custom)
+}
+
+fn file_stamp(path: &str) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((mtime.as_nanos() as u64, metadata.len()))
+}
+
+/// Hashes of files from the last run, for `--incremental`
+#[derive(Debug)]
+pub struct IncrementalCache {
+    path: PathBuf,
+    /// Empty with `--ignore-cache`
+    previous: HashMap<String, CacheEntry>,
+    /// Previous entries updated with this run
+    current: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl IncrementalCache {
+    /// A missing or unreadable cache file is treated as empty
+    pub fn open(path: PathBuf, ignore_cache: bool) -> Self {
+        let entries: HashMap<_, _> = fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<Vec<CacheEntry>>(&text).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|x| (x.path.clone(), x))
+            .collect();
+
+        Self {
+            path,
+            previous: if ignore_cache {
+                HashMap::new()
+            } else {
+                entries.clone()
+            },
+            current: Mutex::new(entries),
+        }
+    }
+
+    /// Last `algorithm` hash of `file_path` if its modification time and size are unchanged
+    pub fn lookup(&self, file_path: &str, algorithm: HashAlgorithm) -> Option<String> {
+        let entry = self.previous.get(file_path)?;
+        let unchanged = file_stamp(file_path) == Some((entry.mtime, entry.size));
+        (unchanged && entry.algorithm == algorithm).then(|| entry.last_verified_hash.clone())
+    }
+
+    /// Record the `algorithm` hash of `file_path`, or forget it if it couldn't be hashed
+    pub fn update(&self, file_path: &str, algorithm: HashAlgorithm, hash: Option<&str>) {
+        let mut current = self.current.lock();
+        match hash.and_then(|x| CacheEntry::new(file_path, algorithm, x.to_string())) {
+            Some(entry) => current.insert(file_path.to_string(), entry),
+            None => current.remove(file_path),
+        };
+    }
+
+    /// Written to a temporary file first, then renamed over the old cache
+    pub fn save(&self) -> Result<()> {
+        let mut entries = self.current.lock().values().cloned().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let temp_path = self.path.with_extension("tmp");
+        fs::write(&temp_path, serde_json::to_string(&entries)?)?;
+        fs::rename(&temp_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+/// `(device, inode)` of a file with other hard links to it
+#[cfg(unix)]
+fn inode_key(path: &str) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path).ok()?;
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+/// Hard links can't be detected
+#[cfg(not(unix))]
+fn inode_key(_path: &str) -> Option<(u64, u64)> {
+    None
+}
+
+/// Hashes of hard linked files already hashed in this run, so every inode is only read once per
+/// algorithm
+#[derive(Debug, Default)]
+pub struct InodeCache {
+    hashes: DashMap<(u64, u64, HashAlgorithm), String>,
+}
+
+impl InodeCache {
+    /// `algorithm` hash of another link to the same file
+    pub fn lookup(&self, file_path: &str, algorithm: HashAlgorithm) -> Option<String> {
+        let (dev, ino) = inode_key(file_path)?;
+        self.hashes.get(&(dev, ino, algorithm)).map(|x| x.clone())
+    }
+
+    /// Record the `algorithm` hash of `file_path`, if it has other hard links
+    pub fn insert(&self, file_path: &str, algorithm: HashAlgorithm, hash: &str) {
+        if let Some((dev, ino)) = inode_key(file_path) {
+            self.hashes.insert((dev, ino, algorithm), hash.to_string());
+        }
+    }
+}
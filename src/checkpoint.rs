@@ -0,0 +1,116 @@
+//! Results of a run saved every few seconds, so an interrupted run can be resumed
+
+use crate::hash::CheckResult;
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CheckpointEntry {
+    path: String,
+    result: CheckResult,
+}
+
+/// Files verified so far, see `Setting::checkpoint_file`
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: PathBuf,
+    interval: Duration,
+    /// Results loaded with `Checkpoint::resume` are kept, so they are saved again
+    results: Mutex<HashMap<String, CheckResult>>,
+    last_saved: Mutex<Instant>,
+}
+
+impl Checkpoint {
+    /// Empty checkpoint saved to `path` every `interval`
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        Self {
+            path,
+            interval,
+            results: Mutex::new(HashMap::new()),
+            last_saved: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Checkpoint with the results saved at `path` by an earlier run. A missing file is treated
+    /// as empty, but a corrupted one is an error
+    pub fn resume(path: PathBuf, interval: Duration) -> Result<Self> {
+        let text = match fs::read_to_string(&path) {
+            Ok(x) => x,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::new(path, interval)),
+            Err(e) => return Err(eyre!("Couldn't read checkpoint file {path:?}: {e}")),
+        };
+        let entries = serde_json::from_str::<Vec<CheckpointEntry>>(&text)
+            .map_err(|e| eyre!("Corrupted checkpoint file {path:?}: {e}"))?;
+
+        let checkpoint = Self::new(path, interval);
+        *checkpoint.results.lock() = entries.into_iter().map(|x| (x.path, x.result)).collect();
+        Ok(checkpoint)
+    }
+
+    /// Result of `file_path` if it was already verified
+    pub(crate) fn get(&self, file_path: &str) -> Option<CheckResult> {
+        self.results.lock().get(file_path).copied()
+    }
+
+    /// Record the result of `file_path`, and save if the interval has passed since the last save,
+    /// or right away with `flush`. Only one thread saves at a time, the others carry on unless
+    /// they flush
+    pub(crate) fn record(&self, file_path: &str, result: CheckResult, flush: bool) -> Result<()> {
+        self.results.lock().insert(file_path.to_string(), result);
+
+        let last_saved = if flush {
+            Some(self.last_saved.lock())
+        } else {
+            self.last_saved
+                .try_lock()
+                .filter(|x| x.elapsed() >= self.interval)
+        };
+        let Some(mut last_saved) = last_saved else {
+            return Ok(());
+        };
+        *last_saved = Instant::now();
+        self.save()
+    }
+
+    /// Save right away, waiting for a save in progress in another thread
+    pub fn flush(&self) -> Result<()> {
+        let mut last_saved = self.last_saved.lock();
+        *last_saved = Instant::now();
+        self.save()
+    }
+
+    /// Written to a temporary file first, then renamed over the old checkpoint
+    pub fn save(&self) -> Result<()> {
+        let mut entries: Vec<_> = self
+            .results
+            .lock()
+            .iter()
+            .map(|(path, &result)| CheckpointEntry {
+                path: path.clone(),
+                result,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let temp_path = self.path.with_extension("tmp");
+        fs::write(&temp_path, serde_json::to_string(&entries)?)?;
+        fs::rename(&temp_path, &self.path)?;
+
+        Ok(())
+    }
+
+    /// Delete the checkpoint file once the run is complete. It may never have been saved
+    pub fn remove(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,16 +1,536 @@
-use crate::hash::{hash_list_parser, prepare_hashing, StatusWrapper};
-use crate::{Message, Setting};
-use color_eyre::eyre::eyre;
+use color_eyre::eyre::{eyre, Context};
 use color_eyre::Result;
 use crossbeam::channel;
-use indicatif::{ProgressBar, ProgressStyle};
-use std::path::PathBuf;
+use crossterm::style::{Color, Stylize};
+use glob::Pattern;
+use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+use md5check::benchmark::{profile_block_sizes, recommend_block_size, BenchmarkSummary, FileStats};
+use md5check::checkpoint::Checkpoint;
+use md5check::compression::Compression;
+use md5check::config::Config;
+use md5check::hash::{
+    append_to_hash_list, cross_check_hash_lists, detect_renames, diff_hash_lists,
+    dry_run_hash_list, extract_hash_list, find_duplicate_files, find_missing_files,
+    find_unlisted_files, generate_hash_list, hash_list_parser, list_files, merge_hash_lists,
+    open_checkpoint, prepare_hashing, prepare_hashing_with_checkpoint, update_hash_list,
+    write_bytes_atomic, write_formatted_hash_list_atomic, DiffEntry, DiffKind, DryRunReport,
+    HashError, HashListFormat, Pause, StatusWrapper, ThreadProgress, STDIN_PATH,
+};
+use md5check::manifest::manifest_parser;
+use md5check::notification::notify_completed;
+use md5check::signature::{read_key, sign_hash_list, verify_signature};
+use md5check::writer::HashListWriter;
+use md5check::{
+    ColorMode, HashPair, MergePolicy, Message, ParseWarning, Setting, Verbosity,
+    VerificationOutcome, VerificationSummary, DRAIN_TIMEOUT,
+};
+use parking_lot::Mutex;
+use rayon::prelude::*;
+use serde_json::json;
+use size::Size;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn cli_mode(file_path: PathBuf, setting: Setting) -> Result<()> {
+/// Exit status after Ctrl+C or SIGTERM, as shells report SIGINT
+const INTERRUPTED_EXIT_CODE: u8 = 130;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    #[default]
+    Plain,
+    Json,
+    /// A row per file with `path,expected,actual,status,error_message` columns
+    Csv,
+    /// JUnit XML for CI systems, written at the end of the run
+    Junit,
+    /// With `--generate`, a JSON array with the size, modification time and permissions of each
+    /// file. When verifying, reads the hash list as such a manifest and reports in plain text
+    JsonManifest,
+}
+
+/// Receives the results of a verification run and writes them somewhere
+trait Reporter {
+    /// Only called with `Verbosity::Verbose`, or for JUnit reports
+    fn correct(&mut self, file_path: &str, expected_hash: &str, file_hash: &str) -> Result<()>;
+
+    fn incorrect(&mut self, file_path: &str, expected_hash: &str, file_hash: &str) -> Result<()>;
+
+    fn missing(&mut self, file_path: &str) -> Result<()>;
+
+    fn skipped(&mut self, file_path: &str) -> Result<()>;
+
+    /// Only called with `Setting::check_extra`
+    fn extra(&mut self, file_path: &str) -> Result<()>;
+
+    fn error(&mut self, file_path: &str, error: &HashError) -> Result<()>;
+
+    /// Called once at the end of a run. Should flush the writer
+    fn summary(&mut self, outcome: &VerificationOutcome) -> Result<()>;
+}
+
+struct PlainTextReporter {
+    writer: Box<dyn Write>,
+}
+
+impl Reporter for PlainTextReporter {
+    fn correct(&mut self, file_path: &str, _: &str, _: &str) -> Result<()> {
+        writeln!(self.writer, "Correct: {file_path}")?;
+        Ok(())
+    }
+
+    fn incorrect(&mut self, file_path: &str, _: &str, _: &str) -> Result<()> {
+        writeln!(self.writer, "Incorrect: {file_path}")?;
+        Ok(())
+    }
+
+    fn missing(&mut self, file_path: &str) -> Result<()> {
+        writeln!(self.writer, "Missing: {file_path}")?;
+        Ok(())
+    }
+
+    fn skipped(&mut self, file_path: &str) -> Result<()> {
+        writeln!(self.writer, "Skipped: {file_path}")?;
+        Ok(())
+    }
+
+    fn extra(&mut self, file_path: &str) -> Result<()> {
+        writeln!(self.writer, "Extra: {file_path}")?;
+        Ok(())
+    }
+
+    fn error(&mut self, file_path: &str, error: &HashError) -> Result<()> {
+        writeln!(self.writer, "Error: {file_path}: {error}")?;
+        Ok(())
+    }
+
+    fn summary(&mut self, outcome: &VerificationOutcome) -> Result<()> {
+        writeln!(self.writer, "{outcome}")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes newline-delimited JSON
+struct JsonReporter {
+    writer: Box<dyn Write>,
+}
+
+impl JsonReporter {
+    fn timestamp_ms() -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis()
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn correct(&mut self, file_path: &str, expected_hash: &str, file_hash: &str) -> Result<()> {
+        let entry = json!({
+            "type": "correct",
+            "file": file_path,
+            "expected_hash": expected_hash,
+            "actual_hash": file_hash,
+            "timestamp_ms": Self::timestamp_ms(),
+        });
+        writeln!(self.writer, "{entry}")?;
+        Ok(())
+    }
+
+    fn incorrect(&mut self, file_path: &str, expected_hash: &str, file_hash: &str) -> Result<()> {
+        let entry = json!({
+            "type": "incorrect",
+            "file": file_path,
+            "expected_hash": expected_hash,
+            "actual_hash": file_hash,
+            "timestamp_ms": Self::timestamp_ms(),
+        });
+        writeln!(self.writer, "{entry}")?;
+        Ok(())
+    }
+
+    fn missing(&mut self, file_path: &str) -> Result<()> {
+        let entry = json!({
+            "type": "missing",
+            "file": file_path,
+            "timestamp_ms": Self::timestamp_ms(),
+        });
+        writeln!(self.writer, "{entry}")?;
+        Ok(())
+    }
+
+    fn skipped(&mut self, file_path: &str) -> Result<()> {
+        let entry = json!({
+            "type": "skipped",
+            "file": file_path,
+            "timestamp_ms": Self::timestamp_ms(),
+        });
+        writeln!(self.writer, "{entry}")?;
+        Ok(())
+    }
+
+    fn extra(&mut self, file_path: &str) -> Result<()> {
+        let entry = json!({
+            "type": "extra",
+            "file": file_path,
+            "timestamp_ms": Self::timestamp_ms(),
+        });
+        writeln!(self.writer, "{entry}")?;
+        Ok(())
+    }
+
+    fn error(&mut self, file_path: &str, error: &HashError) -> Result<()> {
+        let entry = json!({
+            "type": "error",
+            "file": file_path,
+            "message": error.to_string(),
+            "timestamp_ms": Self::timestamp_ms(),
+        });
+        writeln!(self.writer, "{entry}")?;
+        Ok(())
+    }
+
+    fn summary(&mut self, outcome: &VerificationOutcome) -> Result<()> {
+        let entry = json!({
+            "type": "summary",
+            "correct": outcome.correct_num,
+            "incorrect": outcome.incorrect_num,
+            "missing": outcome.missing_num,
+            "error": outcome.error_num,
+            "skipped": outcome.skipped_num,
+            "extra": outcome.extra_num,
+            "aborted": outcome.aborted,
+            "elapsed_ms": outcome.elapsed.as_millis(),
+            "timestamp_ms": Self::timestamp_ms(),
+        });
+        writeln!(self.writer, "{entry}")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes a row per file, for spreadsheets and `awk`/`cut`. There's no summary row so every row
+/// has the same columns
+struct CsvReporter {
+    writer: Box<dyn Write>,
+}
+
+impl CsvReporter {
+    fn new(mut writer: Box<dyn Write>) -> Result<Self> {
+        writeln!(writer, "path,expected,actual,status,error_message")?;
+        Ok(Self { writer })
+    }
+
+    fn row(
+        &mut self,
+        file_path: &str,
+        expected_hash: &str,
+        file_hash: &str,
+        status: &str,
+        error_message: &str,
+    ) -> Result<()> {
+        writeln!(
+            self.writer,
+            "{},{},{},{status},{}",
+            csv_field(file_path),
+            csv_field(expected_hash),
+            csv_field(file_hash),
+            csv_field(error_message)
+        )?;
+        Ok(())
+    }
+}
+
+impl Reporter for CsvReporter {
+    fn correct(&mut self, file_path: &str, expected_hash: &str, file_hash: &str) -> Result<()> {
+        self.row(file_path, expected_hash, file_hash, "ok", "")
+    }
+
+    fn incorrect(&mut self, file_path: &str, expected_hash: &str, file_hash: &str) -> Result<()> {
+        self.row(file_path, expected_hash, file_hash, "mismatch", "")
+    }
+
+    fn missing(&mut self, file_path: &str) -> Result<()> {
+        self.row(file_path, "", "", "missing", "")
+    }
+
+    fn skipped(&mut self, file_path: &str) -> Result<()> {
+        self.row(file_path, "", "", "skipped", "")
+    }
+
+    fn extra(&mut self, file_path: &str) -> Result<()> {
+        self.row(file_path, "", "", "extra", "")
+    }
+
+    fn error(&mut self, file_path: &str, error: &HashError) -> Result<()> {
+        self.row(file_path, "", "", "error", &error.to_string())
+    }
+
+    fn summary(&mut self, _: &VerificationOutcome) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Quote a CSV field if needed, as in RFC 4180
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes a JUnit XML report with a test case per file. Test cases are kept until the summary,
+/// since the test suite counts come first
+struct JunitReporter {
+    writer: Box<dyn Write>,
+    test_cases: Vec<String>,
+}
+
+impl JunitReporter {
+    fn add_test_case(&mut self, file_path: &str, body: Option<String>) {
+        let name = xml_escape(file_path);
+        self.test_cases.push(match body {
+            Some(body) => format!(
+                "    <testcase name=\"{name}\" classname=\"md5check\">\n      {body}\n    </testcase>"
+            ),
+            None => format!("    <testcase name=\"{name}\" classname=\"md5check\"/>"),
+        });
+    }
+}
+
+impl Reporter for JunitReporter {
+    fn correct(&mut self, file_path: &str, _: &str, _: &str) -> Result<()> {
+        self.add_test_case(file_path, None);
+        Ok(())
+    }
+
+    fn incorrect(&mut self, file_path: &str, expected_hash: &str, file_hash: &str) -> Result<()> {
+        let body = format!(
+            "<failure message=\"Hash mismatch\">Expected {}, got {}</failure>",
+            xml_escape(expected_hash),
+            xml_escape(file_hash)
+        );
+        self.add_test_case(file_path, Some(body));
+        Ok(())
+    }
+
+    fn missing(&mut self, file_path: &str) -> Result<()> {
+        let body = "<error message=\"File not found\"/>".to_string();
+        self.add_test_case(file_path, Some(body));
+        Ok(())
+    }
+
+    fn skipped(&mut self, file_path: &str) -> Result<()> {
+        self.add_test_case(file_path, Some("<skipped/>".to_string()));
+        Ok(())
+    }
+
+    fn extra(&mut self, file_path: &str) -> Result<()> {
+        let body = "<failure message=\"Not in the hash list\"/>".to_string();
+        self.add_test_case(file_path, Some(body));
+        Ok(())
+    }
+
+    fn error(&mut self, file_path: &str, error: &HashError) -> Result<()> {
+        let body = format!("<error message=\"{}\"/>", xml_escape(&error.to_string()));
+        self.add_test_case(file_path, Some(body));
+        Ok(())
+    }
+
+    fn summary(&mut self, outcome: &VerificationOutcome) -> Result<()> {
+        let tests = outcome.correct_num
+            + outcome.incorrect_num
+            + outcome.missing_num
+            + outcome.error_num
+            + outcome.skipped_num
+            + outcome.extra_num;
+
+        writeln!(self.writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(self.writer, "<testsuites>")?;
+        writeln!(
+            self.writer,
+            r#"  <testsuite name="md5check" tests="{tests}" failures="{}" errors="{}" skipped="{}" time="{:.3}">"#,
+            outcome.incorrect_num + outcome.extra_num,
+            outcome.missing_num + outcome.error_num,
+            outcome.skipped_num,
+            outcome.elapsed.as_secs_f64()
+        )?;
+        for test_case in self.test_cases.drain(..) {
+            writeln!(self.writer, "{test_case}")?;
+        }
+        writeln!(self.writer, "  </testsuite>")?;
+        writeln!(self.writer, "</testsuites>")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Escape text for XML attributes and elements
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Reports go to `output` if set. Otherwise, plain text reports are disabled as the progress bar
+/// already shows them, and the other formats go to stdout
+fn reporter(output: Option<&Path>, format: ReportFormat) -> Result<Option<Box<dyn Reporter>>> {
+    let writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None if matches!(format, ReportFormat::Plain | ReportFormat::JsonManifest) => {
+            return Ok(None)
+        }
+        None => Box::new(std::io::stdout()),
+    };
+
+    Ok(Some(match format {
+        ReportFormat::Plain | ReportFormat::JsonManifest => Box::new(PlainTextReporter { writer }),
+        ReportFormat::Json => Box::new(JsonReporter { writer }),
+        ReportFormat::Csv => Box::new(CsvReporter::new(writer)?),
+        ReportFormat::Junit => Box::new(JunitReporter {
+            writer,
+            test_cases: Vec::new(),
+        }),
+    }))
+}
+
+/// Progress bar of `len` files, with the bytes read out of `total_bytes` and the average
+/// throughput. Returns the counter of bytes read, updated while hashing through `progress_status`
+fn progress_bar(
+    len: usize,
+    total_bytes: u64,
+    verbosity: Verbosity,
+) -> (ProgressBar, Arc<AtomicU64>) {
+    let bytes_read = Arc::new(AtomicU64::new(0));
+    if matches!(verbosity, Verbosity::Quiet | Verbosity::Silent) {
+        return (ProgressBar::hidden(), bytes_read);
+    }
+
+    let read = Arc::clone(&bytes_read);
+    let rate = Arc::clone(&bytes_read);
+    let style = ProgressStyle::with_template(
+        r"[{elapsed_precise}] [ETA:{eta_precise}] {wide_bar} {pos}/{len} {read}/{total} @ {rate}/s {msg}",
+    )
+    .expect("How can this fail?")
+    .with_key("read", move |_: &ProgressState, w: &mut dyn fmt::Write| {
+        let _ = write!(w, "{}", Size::from_bytes(read.load(Ordering::Relaxed)));
+    })
+    .with_key("total", move |_: &ProgressState, w: &mut dyn fmt::Write| {
+        let _ = write!(w, "{}", Size::from_bytes(total_bytes));
+    })
+    .with_key("rate", move |state: &ProgressState, w: &mut dyn fmt::Write| {
+        let secs = state.elapsed().as_secs_f64();
+        let bytes = rate.load(Ordering::Relaxed) as f64;
+        let rate = if secs > 0.0 { bytes / secs } else { 0.0 };
+        let _ = write!(w, "{}", Size::from_bytes(rate as u64));
+    });
+    (ProgressBar::new(len as u64).with_style(style), bytes_read)
+}
+
+/// Size of the files at `paths` together, for `progress_bar`. Files that can't be read count as
+/// empty, their error is reported once hashed
+fn total_len<P: AsRef<Path> + Sync>(paths: &[P]) -> u64 {
+    paths
+        .par_iter()
+        .map(|x| fs::metadata(x).map_or(0, |x| x.len()))
+        .sum()
+}
+
+/// A bar per hashing thread in parallel mode, unless there's no progress bar. The thread bars
+/// are returned to be removed at the end of the run
+fn progress_status(
+    progress: &ProgressBar,
+    bytes_read: Arc<AtomicU64>,
+    setting: &Setting,
+) -> (StatusWrapper, Option<Arc<ThreadProgress>>) {
+    // Watching hashes files on a single thread
+    let hidden = matches!(setting.verbosity, Verbosity::Quiet | Verbosity::Silent);
+    if !setting.parallel || hidden || setting.watch {
+        return (
+            StatusWrapper::ProgressBar(progress.clone(), bytes_read),
+            None,
+        );
+    }
+
+    let thread_progress = Arc::new(ThreadProgress::new(progress.clone(), bytes_read));
+    (
+        StatusWrapper::ThreadProgress(Arc::clone(&thread_progress)),
+        Some(thread_progress),
+    )
+}
+
+/// Show a result line. In normal mode it replaces the progress bar message, otherwise it's
+/// printed on its own line to stderr. Silent mode drops it
+fn log_line(progress: &ProgressBar, verbosity: Verbosity, line: String) {
+    match verbosity {
+        Verbosity::Normal => progress.set_message(line),
+        Verbosity::Quiet | Verbosity::Verbose => progress.suspend(|| eprintln!("{line}")),
+        Verbosity::Silent => {}
+    }
+}
+
+/// Orange for permission errors, yellow for missing files and red for the rest, see
+/// `ColorMode::enabled`
+fn error_line(file_path: &str, error: &HashError, color: ColorMode) -> String {
+    let line_color = match error {
+        HashError::PermissionDenied { .. } => Color::Rgb {
+            r: 255,
+            g: 165,
+            b: 0,
+        },
+        HashError::FileNotFound { .. } => Color::Yellow,
+        _ => Color::Red,
+    };
+    paint(format!("Error: {file_path}: {error}"), line_color, color)
+}
+
+/// Yellow like the missing file errors
+fn missing_line(file_path: &str, color: ColorMode) -> String {
+    paint(format!("Missing: {file_path}"), Color::Yellow, color)
+}
+
+/// `line` in `line_color` if `color` is enabled
+fn paint(line: String, line_color: Color, color: ColorMode) -> String {
+    if !color.enabled() {
+        return line;
+    }
+    // crossterm checks `NO_COLOR` on its own, which `ColorMode` already took into account
+    crossterm::style::force_color_output(true);
+    line.with(line_color).to_string()
+}
+
+/// Listed files are relative to `Setting::base_dir` if set, to the directory of an absolute hash
+/// list, and to the current directory otherwise. Returns `file_path`, absolute when the current
+/// directory changed under a relative one
+fn enter_hash_list_dir(file_path: &Path, setting: &Setting) -> Result<PathBuf> {
     if !file_path.is_file() {
         return Err(eyre!("Path is not file: {file_path:?}"));
     }
 
+    if let Some(base_dir) = &setting.base_dir {
+        // Relative to the directory it was run from, which is left
+        let file_path = std::path::absolute(file_path)?;
+        enter_base_dir(base_dir)?;
+        return Ok(file_path);
+    }
     if file_path.is_absolute() {
         std::env::set_current_dir(
             file_path
@@ -18,36 +538,1413 @@ pub fn cli_mode(file_path: PathBuf, setting: Setting) -> Result<()> {
                 .expect("If path is absolute and is a file, then a parent exists"),
         )?;
     }
+    Ok(file_path.to_path_buf())
+}
 
-    let hash_list = hash_list_parser(&file_path)?;
+fn enter_base_dir(base_dir: &Path) -> Result<()> {
+    if !base_dir.is_dir() {
+        return Err(eyre!("Path is not directory: {base_dir:?}"));
+    }
+    Ok(std::env::set_current_dir(base_dir)?)
+}
 
-    let style = ProgressStyle::with_template(
-        r"[{elapsed_precise}] [ETA:{eta_precise}] {wide_bar} {pos}/{len} {msg}",
-    )
-    .expect("How can this fail?");
-    let progress = ProgressBar::new(hash_list.len() as u64).with_style(style);
-    let status = StatusWrapper::ProgressBar(progress.clone());
+/// Compressed hash lists are only decompressed to be read, so changing one would corrupt it
+fn ensure_uncompressed(file_path: &Path) -> Result<()> {
+    if Compression::of_file(file_path)?.is_some() {
+        return Err(eyre!(
+            "Compressed hash lists can't be changed: {file_path:?}"
+        ));
+    }
+    Ok(())
+}
 
-    let (tx, rx) = channel::unbounded();
+/// JSON manifests can't be changed, see `HashError::ManifestNotEditable`. Checked before hashing
+/// anything, as the hash list is only written at the end
+fn ensure_editable(format: HashListFormat) -> Result<(), HashError> {
+    if format == HashListFormat::JsonManifest {
+        return Err(HashError::ManifestNotEditable);
+    }
+    Ok(())
+}
+
+/// Every warning when verbose, otherwise only how many there are, unless silent
+fn print_warnings(warnings: &[ParseWarning], verbosity: Verbosity) {
+    if verbosity == Verbosity::Silent {
+        return;
+    }
+    if verbosity == Verbosity::Verbose {
+        for warning in warnings {
+            eprintln!("Warning: {warning}");
+        }
+    } else if !warnings.is_empty() {
+        eprintln!(
+            "Warning: {} problems in the hash list, use --verbose to list them",
+            warnings.len()
+        );
+    }
+}
+
+/// Verify each hash list of `file_paths` and write a single report to `output` or stdout. The
+/// hash lists are verified one after the other, as the files of each are already hashed in
+/// parallel. Exits with the worst result of all of them
+pub fn cli_mode(
+    file_paths: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    format: ReportFormat,
+    setting: Setting,
+) -> Result<ExitCode> {
+    if setting.watch && file_paths.len() > 1 {
+        return Err(eyre!("Only a single hash list can be watched"));
+    }
+    // With `--benchmark`, `output` and `format` are for the benchmark summary instead
+    let benchmark_output = match (setting.benchmark, format) {
+        (false, _) => None,
+        (true, ReportFormat::Plain | ReportFormat::Json) => {
+            Some(output.as_deref().map(std::path::absolute).transpose()?)
+        }
+        (true, _) => {
+            return Err(eyre!(
+                "Only plain and JSON formats are supported with --benchmark"
+            ))
+        }
+    };
+    // Created before changing the current directory so relative paths are from where it was run
+    let mut reporter = match benchmark_output {
+        Some(_) => None,
+        None => reporter(output.as_deref(), format)?,
+    };
 
-    std::thread::spawn(move || prepare_hashing(hash_list, &setting, status, tx));
+    // Every hash list changes the current directory, so the next ones are made absolute first
+    let mut hash_lists = Vec::with_capacity(file_paths.len());
+    for file_path in &file_paths {
+        let absolute = if file_path == Path::new(STDIN_PATH) {
+            file_path.clone()
+        } else {
+            std::path::absolute(file_path)?
+        };
+        hash_lists.push((file_path.display().to_string(), absolute));
+    }
+    // All of them, before verifying any file
+    if let Some(key) = &setting.signature_key {
+        let key = read_key(key).wrap_err_with(|| format!("Couldn't read key file {key:?}"))?;
+        for (name, file_path) in &hash_lists {
+            if file_path == Path::new(STDIN_PATH) {
+                return Err(eyre!(
+                    "Signatures of hash lists from stdin can't be checked"
+                ));
+            }
+            verify_signature(file_path, &key).wrap_err_with(|| format!("{name:?}"))?;
+        }
+    }
 
-    loop {
-        let res = rx.recv();
-        let mess = match res {
-            Ok(mess) => mess,
-            Err(e) => return Err(e.into()),
+    let interrupt = interrupt_flag()?;
+    let mut total = VerificationSummary::default();
+    let several = hash_lists.len() > 1;
+    for (name, file_path) in hash_lists {
+        let label = several.then_some(name.as_str());
+        let Some(summary) = verify_hash_list(
+            file_path,
+            &mut reporter,
+            format,
+            &setting,
+            label,
+            &interrupt,
+        )?
+        else {
+            return Ok(ExitCode::from(2));
         };
+        // Fail fast applies to the whole run
+        let stop = summary.outcome.aborted || interrupt.flag.load(Ordering::Relaxed);
+        total += summary;
+        if stop {
+            break;
+        }
+    }
+    let VerificationSummary {
+        outcome,
+        file_stats,
+        ..
+    } = total;
+
+    // Already written before watching
+    if let (Some(reporter), false) = (&mut reporter, setting.watch) {
+        reporter.summary(&outcome)?;
+    }
+    if let Some(output) = benchmark_output {
+        write_benchmark(&file_stats, output.as_deref(), format)?;
+    }
+    // Watching only ends with Ctrl+C
+    if setting.notify && !interrupt.flag.load(Ordering::Relaxed) {
+        notify_completed(&outcome);
+    }
+    Ok(verification_exit_code(&outcome, &setting, &interrupt.flag))
+}
+
+/// Set by `interrupt_flag`, with the results so far of the hash list being verified
+#[derive(Default)]
+struct Interrupt {
+    flag: Arc<AtomicBool>,
+    run: Mutex<PartialRun>,
+}
+
+/// Results so far of the hash list being verified, reported if the process exits before the
+/// files being hashed finish
+#[derive(Default)]
+struct PartialRun {
+    /// Counts of the results received, except the correct files which are counted by `progress`
+    outcome: VerificationOutcome,
+    total: usize,
+    label: Option<String>,
+    progress: Option<ProgressBar>,
+    checkpoint: Option<Arc<Checkpoint>>,
+}
+
+impl PartialRun {
+    fn count(&mut self, message: &Message) {
+        match message {
+            Message::Incorrect { .. } => self.outcome.incorrect_num += 1,
+            Message::Missing(_) => self.outcome.missing_num += 1,
+            Message::Error { .. } => self.outcome.error_num += 1,
+            Message::Skipped(_) => self.outcome.skipped_num += 1,
+            _ => {}
+        }
+    }
+
+    /// Show the same summary as an interrupted run and save the checkpoint
+    fn report(&self) {
+        if let Some(progress) = &self.progress {
+            let mut outcome = self.outcome;
+            let counted = outcome.incorrect_num
+                + outcome.missing_num
+                + outcome.error_num
+                + outcome.skipped_num;
+            outcome.correct_num = (progress.position() as usize).saturating_sub(counted);
+            let summary = interrupted_summary(&outcome, self.total, self.label.as_deref());
+            progress.abandon_with_message(format!("Interrupted! {summary}"));
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            if let Err(e) = checkpoint.flush() {
+                eprintln!("Couldn't save the checkpoint: {e}");
+            }
+        }
+    }
+}
+
+/// Set on Ctrl+C or SIGTERM, which stops the run. Files being hashed get `DRAIN_TIMEOUT` to
+/// finish before the process exits with `INTERRUPTED_EXIT_CODE`, with the summary so far and the
+/// checkpoint saved. A second signal exits right away
+fn interrupt_flag() -> Result<Arc<Interrupt>> {
+    let interrupt = Arc::new(Interrupt::default());
+    let handler_interrupt = Arc::clone(&interrupt);
+    ctrlc::set_handler(move || {
+        if handler_interrupt.flag.swap(true, Ordering::Relaxed) {
+            std::process::exit(INTERRUPTED_EXIT_CODE.into());
+        }
+        let interrupt = Arc::clone(&handler_interrupt);
+        std::thread::spawn(move || {
+            std::thread::sleep(DRAIN_TIMEOUT);
+            eprintln!(
+                "Files still being hashed after {}s, exiting",
+                DRAIN_TIMEOUT.as_secs()
+            );
+            interrupt.run.lock().report();
+            std::process::exit(INTERRUPTED_EXIT_CODE.into());
+        });
+    })?;
+    Ok(interrupt)
+}
+
+/// `INTERRUPTED_EXIT_CODE` if the run was stopped by `interrupt_flag`, except when watching,
+/// which only stops that way
+fn verification_exit_code(
+    outcome: &VerificationOutcome,
+    setting: &Setting,
+    interrupted: &AtomicBool,
+) -> ExitCode {
+    if interrupted.load(Ordering::Relaxed) && !setting.watch {
+        ExitCode::from(INTERRUPTED_EXIT_CODE)
+    } else {
+        outcome.exit_code(setting.ignore_missing)
+    }
+}
+
+/// Progress bar summary of a run stopped by Ctrl+C, out of the `total` files to verify. Starts
+/// with `label` if it's set
+fn interrupted_summary(outcome: &VerificationOutcome, total: usize, label: Option<&str>) -> String {
+    let errors = outcome.missing_num + outcome.error_num;
+    let verified = outcome.correct_num + outcome.incorrect_num + errors;
+    let summary = format!(
+        "Verified {verified}/{total} files; {} correct, {} incorrect, {errors} errors",
+        outcome.correct_num, outcome.incorrect_num
+    );
+    match label {
+        Some(label) => format!("{label}: {summary}"),
+        None => summary,
+    }
+}
+
+/// Verify the hash list at `file_path`, sending the results to `reporter`. The progress bar
+/// summary starts with `label` if it's set. Returns `None` if the preflight found missing files
+/// and the user chose not to continue. `interrupt` stops the run, see `interrupt_flag`. With
+/// `Setting::watch`, the report summary is written before watching, which only returns once Ctrl+C
+/// is pressed
+fn verify_hash_list(
+    file_path: PathBuf,
+    reporter: &mut Option<Box<dyn Reporter>>,
+    format: ReportFormat,
+    setting: &Setting,
+    label: Option<&str>,
+    interrupt: &Interrupt,
+) -> Result<Option<VerificationSummary>> {
+    let file_path = enter_files_dir(file_path, setting)?;
+    let hash_list = if format == ReportFormat::JsonManifest {
+        manifest_parser(&file_path)?
+    } else {
+        hash_list_parser(
+            &file_path,
+            setting.algorithm,
+            setting.duplicate_policy,
+            setting.strict,
+        )?
+    };
+    print_warnings(&hash_list.warnings, setting.verbosity);
+
+    let malformed_num = hash_list.malformed_num();
+    let hash_list = hash_list.pairs;
+    let mut summary = verify_entries(
+        hash_list, file_path, reporter, format, setting, label, interrupt,
+    )?;
+    if let Some(summary) = &mut summary {
+        summary.malformed_num = malformed_num;
+    }
+    // After the progress bar summary, even when quiet
+    if setting.warn && malformed_num > 0 {
+        eprintln!("Warning: {malformed_num} malformed lines ignored");
+    }
+    Ok(summary)
+}
+
+/// Move to the directory the files of the hash list at `file_path` are relative to. Returns the
+/// path to the hash list from there
+fn enter_files_dir(file_path: PathBuf, setting: &Setting) -> Result<PathBuf> {
+    // Files in a hash list from stdin are relative to the current directory, or the base directory
+    if file_path == Path::new(STDIN_PATH) {
+        if let Some(base_dir) = &setting.base_dir {
+            enter_base_dir(base_dir)?;
+        }
+        Ok(file_path)
+    } else {
+        enter_hash_list_dir(&file_path, setting)
+    }
+}
+
+/// Verify `hash_list`, the entries of the hash list at `file_path`, from the current directory.
+/// See `verify_hash_list`
+fn verify_entries(
+    hash_list: Vec<HashPair>,
+    file_path: PathBuf,
+    reporter: &mut Option<Box<dyn Reporter>>,
+    format: ReportFormat,
+    setting: &Setting,
+    label: Option<&str>,
+    interrupt: &Interrupt,
+) -> Result<Option<VerificationSummary>> {
+    if setting.preflight {
+        let missing = find_missing_files(&hash_list, setting);
+        for file_path in &missing {
+            eprintln!("{}", missing_line(file_path, setting.color));
+        }
+        if !missing.is_empty() && !setting.ignore_missing && !confirm_continue(missing.len())? {
+            return Ok(None);
+        }
+    }
+
+    let verbosity = setting.verbosity;
+    let total = hash_list.len();
+    let paths: Vec<_> = hash_list.iter().map(|x| x.file_path()).collect();
+    let (progress, bytes_read) = progress_bar(total, total_len(&paths), verbosity);
+    let (status, thread_progress) = progress_status(&progress, bytes_read, setting);
+
+    let (tx, rx) = channel::unbounded();
+    let checkpoint = setting
+        .checkpoint_file
+        .as_ref()
+        .map(|x| Arc::new(open_checkpoint(x, setting, &tx)));
+    *interrupt.run.lock() = PartialRun {
+        total,
+        label: label.map(str::to_string),
+        progress: Some(progress.clone()),
+        checkpoint: checkpoint.clone(),
+        ..Default::default()
+    };
+
+    // Ctrl+C also stops watching
+    let cancelled = Arc::clone(&interrupt.flag);
+
+    let mut hashing_setting = setting.clone();
+    // JUnit and CSV reports list every file, including the correct ones
+    if matches!(format, ReportFormat::Junit | ReportFormat::Csv) {
+        hashing_setting.verbosity = Verbosity::Verbose;
+    }
+    let hashing = std::thread::spawn(move || {
+        prepare_hashing_with_checkpoint(
+            hash_list,
+            &hashing_setting,
+            status,
+            tx,
+            &Pause::default(),
+            &cancelled,
+            &file_path,
+            checkpoint,
+        )
+    });
+
+    let outcome = loop {
+        let mess = rx.recv()?;
+        interrupt.run.lock().count(&mess);
+        if let Some(outcome) = report_message(mess, reporter, &progress, setting)? {
+            break outcome;
+        }
+    };
+
+    if let Some(thread_progress) = &thread_progress {
+        thread_progress.finish();
+    }
+    let summary = match label {
+        Some(label) => format!("{label}: {outcome}"),
+        None => outcome.to_string(),
+    };
+    if interrupt.flag.load(Ordering::Relaxed) {
+        let summary = interrupted_summary(&outcome, total, label);
+        progress.abandon_with_message(format!("Interrupted! {summary}"));
+    } else if outcome.aborted {
+        progress.abandon_with_message(format!("Aborted! {summary}"));
+    } else {
+        progress.finish_with_message(summary);
+    }
+
+    if setting.watch {
+        if let Some(reporter) = reporter {
+            reporter.summary(&outcome)?;
+        }
+        eprintln!("Watching for changes, press Ctrl+C to stop");
+        // Ends when the watcher is stopped
+        while let Ok(mess) = rx.recv() {
+            report_message(mess, reporter, &progress, setting)?;
+        }
+    }
+
+    // Returned once watching is stopped, if watching
+    let summary = hashing
+        .join()
+        .map_err(|_| eyre!("Hashing thread panicked"))?;
+    Ok(Some(summary))
+}
+
+/// Check that the files listed in both hash lists have the same hash in each, then verify the
+/// files of both. Files with conflicting hashes aren't verified, and make the run fail. Listed
+/// files are relative to the directory of `first`, like with `cli_mode`
+pub fn cross_check_mode(
+    first: PathBuf,
+    second: PathBuf,
+    output: Option<PathBuf>,
+    format: ReportFormat,
+    setting: Setting,
+) -> Result<ExitCode> {
+    let mut reporter = reporter(output.as_deref(), format)?;
+    let second = std::path::absolute(&second)?;
+    let first = enter_hash_list_dir(&first, &setting)?;
+
+    let mut hash_lists = vec![];
+    for file_path in [&first, &second] {
+        let hash_list = hash_list_parser(
+            file_path,
+            setting.algorithm,
+            setting.duplicate_policy,
+            setting.strict,
+        )?;
+        if !hash_list.warnings.is_empty() {
+            eprintln!("{file_path:?}:");
+            print_warnings(&hash_list.warnings, setting.verbosity);
+        }
+        hash_lists.push(hash_list);
+    }
+
+    let conflicts = cross_check_hash_lists(&hash_lists[0], &hash_lists[1]);
+    for conflict in &conflicts {
+        eprintln!("Error: {conflict}");
+    }
+    let conflicting: HashSet<_> = conflicts.iter().map(|x| x.path.as_str()).collect();
+    // Conflicting files are left out below, so the kept entry doesn't matter
+    let mut merged = merge_hash_lists(hash_lists, MergePolicy::First);
+    merged
+        .pairs
+        .retain(|x| !conflicting.contains(x.file_path()));
+    if !conflicts.is_empty() {
+        eprintln!(
+            "{} conflicting files, verifying the other {}",
+            conflicts.len(),
+            merged.pairs.len()
+        );
+    }
+
+    let interrupt = interrupt_flag()?;
+    let hash_list = merged.pairs;
+    let Some(summary) = verify_entries(
+        hash_list,
+        first,
+        &mut reporter,
+        format,
+        &setting,
+        None,
+        &interrupt,
+    )?
+    else {
+        return Ok(ExitCode::from(2));
+    };
+    if let (Some(reporter), false) = (&mut reporter, setting.watch) {
+        reporter.summary(&summary.outcome)?;
+    }
+
+    let exit_code = verification_exit_code(&summary.outcome, &setting, &interrupt.flag);
+    Ok(if !conflicts.is_empty() && exit_code == ExitCode::SUCCESS {
+        ExitCode::FAILURE
+    } else {
+        exit_code
+    })
+}
+
+/// Write a `BenchmarkSummary` of `file_stats` to `output` or stderr, as a table or as JSON
+fn write_benchmark(
+    file_stats: &[FileStats],
+    output: Option<&Path>,
+    format: ReportFormat,
+) -> Result<()> {
+    let summary = BenchmarkSummary::new(file_stats);
+    let text = match format {
+        ReportFormat::Json => format!("{}\n", serde_json::to_string_pretty(&summary)?),
+        _ => summary.to_string(),
+    };
+    match output {
+        Some(path) => fs::write(path, text)?,
+        None => eprint!("{text}"),
+    }
+    Ok(())
+}
+
+/// Ask on stderr whether to continue after the preflight found missing files. Without a
+/// terminal to answer from, the run is aborted
+fn confirm_continue(missing_num: usize) -> Result<bool> {
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        eprintln!("{missing_num} files are missing, aborting");
+        return Ok(false);
+    }
+
+    eprint!("{missing_num} files are missing, continue anyway? [y/N] ");
+    let mut answer = String::new();
+    stdin.lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Log a message from the hashing thread and pass it to the reporter. Returns the outcome at the
+/// end of a run
+fn report_message(
+    mess: Message,
+    reporter: &mut Option<Box<dyn Reporter>>,
+    progress: &ProgressBar,
+    setting: &Setting,
+) -> Result<Option<VerificationOutcome>> {
+    let verbosity = setting.verbosity;
+    match mess {
+        Message::Correct {
+            file_path,
+            expected_hash,
+            file_hash,
+        } => {
+            if let Some(reporter) = reporter {
+                reporter.correct(&file_path, &expected_hash, &file_hash)?;
+            }
+            if verbosity == Verbosity::Verbose {
+                log_line(progress, verbosity, format!("Correct: {file_path}"))
+            }
+        }
+        Message::Incorrect {
+            file_path,
+            expected_hash,
+            file_hash,
+        } => {
+            if let Some(reporter) = reporter {
+                reporter.incorrect(&file_path, &expected_hash, &file_hash)?;
+            }
+            log_line(progress, verbosity, format!("Incorrect: {file_path}"))
+        }
+        Message::Warning(s) => log_line(progress, verbosity, format!("Warning: {s}")),
+        Message::Error { file_path, error } => {
+            if let Some(reporter) = reporter {
+                reporter.error(&file_path, &error)?;
+            }
+            log_line(
+                progress,
+                verbosity,
+                error_line(&file_path, &error, setting.color),
+            )
+        }
+        Message::Missing(file_path) => {
+            if let Some(reporter) = reporter {
+                reporter.missing(&file_path)?;
+            }
+            log_line(progress, verbosity, missing_line(&file_path, setting.color))
+        }
+        Message::Skipped(file_path) => {
+            if let Some(reporter) = reporter {
+                reporter.skipped(&file_path)?;
+            }
+            // Only listed in verbose mode, like correct files
+            if verbosity == Verbosity::Verbose {
+                log_line(progress, verbosity, format!("Skipped: {file_path}"))
+            }
+        }
+        Message::Extra(file_path) => {
+            if let Some(reporter) = reporter {
+                reporter.extra(&file_path)?;
+            }
+            log_line(progress, verbosity, format!("Extra: {file_path}"))
+        }
+        Message::CaseCorrected { listed, found } => log_line(
+            progress,
+            verbosity,
+            format!("Case corrected: {listed} -> {found}"),
+        ),
+        Message::Modified(file_path) => {
+            log_line(progress, verbosity, format!("Modified: {file_path}"))
+        }
+        Message::Retrying(file_path, attempt) => log_line(
+            progress,
+            verbosity,
+            format!("Retrying ({attempt}): {file_path}"),
+        ),
+        Message::Completed(outcome) | Message::Cancelled(outcome) => return Ok(Some(outcome)),
+        Message::Generated(..) | Message::DryRun { .. } | Message::Empty => {}
+    }
+
+    Ok(None)
+}
+
+/// Hash the files under `dir` into a hash list, a JSON manifest with `ReportFormat::JsonManifest`
+/// or CSV with `ReportFormat::Csv`, written to `output` or stdout. With `compression`, the hash
+/// list is compressed and only written once complete, even to stdout
+pub fn generate_mode(
+    dir: PathBuf,
+    output: Option<PathBuf>,
+    format: ReportFormat,
+    compression: Option<Compression>,
+    setting: Setting,
+) -> Result<ExitCode> {
+    if !dir.is_dir() {
+        return Err(eyre!("Path is not directory: {dir:?}"));
+    }
+    if compression.is_some() && format == ReportFormat::JsonManifest {
+        return Err(eyre!("JSON manifests can't be compressed"));
+    }
+
+    // The output file shouldn't be in its own hash list. It may not exist yet, so only its
+    // directory is canonicalized
+    let canonical_output = output
+        .as_deref()
+        .map(|x| -> Result<PathBuf> {
+            let parent = match x.parent() {
+                Some(x) if !x.as_os_str().is_empty() => x,
+                _ => Path::new("."),
+            };
+            let file_name = x
+                .file_name()
+                .ok_or_else(|| eyre!("Output is not a file: {x:?}"))?;
+            Ok(parent.canonicalize()?.join(file_name))
+        })
+        .transpose()?;
+
+    let dir = dir.canonicalize()?;
+    let file_list: Vec<_> = list_files(&dir)?
+        .into_iter()
+        .filter(|x| Some(x) != canonical_output.as_ref())
+        .collect();
+
+    let total_bytes = total_len(&file_list);
+    let (progress, bytes_read) = progress_bar(file_list.len(), total_bytes, setting.verbosity);
+    let (status, thread_progress) = progress_status(&progress, bytes_read, &setting);
+
+    let (tx, rx) = channel::unbounded();
+
+    let hashing_setting = setting.clone();
+    let root = dir.clone();
+    std::thread::spawn(move || {
+        generate_hash_list(
+            &root,
+            file_list,
+            &hashing_setting,
+            status,
+            tx,
+            &Pause::default(),
+            &AtomicBool::new(false),
+        )
+    });
+
+    let hash_list_format = match format {
+        ReportFormat::JsonManifest => HashListFormat::JsonManifest,
+        ReportFormat::Csv => HashListFormat::Csv,
+        _ => setting.hash_list_format,
+    };
+    let algorithm = setting.generation_algorithm();
+    // Streamed to stdout, but only written to the output file or compressed once complete
+    let mut stream = (output.is_none() && compression.is_none()).then(|| {
+        let stdout = std::io::stdout().lock();
+        HashListWriter::new(stdout, hash_list_format, algorithm, &dir)
+    });
+    let mut buffer = HashListWriter::new(Vec::new(), hash_list_format, algorithm, &dir);
+    let outcome = loop {
+        let mess = rx.recv()?;
 
         match mess {
-            Message::Incorrect(s) => progress.set_message(format!("Incorrect: {s}")),
-            Message::Error(e) => progress.set_message(format!("Error: {e}")),
-            Message::Completed(_) => break,
-            Message::Empty => {}
+            Message::Generated(path, hash) => match &mut stream {
+                Some(stream) => progress.suspend(|| stream.write_entry(&path, &hash))?,
+                None => buffer.write_entry(&path, &hash)?,
+            },
+            Message::Error { file_path, error } => log_line(
+                &progress,
+                setting.verbosity,
+                error_line(&file_path, &error, setting.color),
+            ),
+            Message::Completed(outcome) | Message::Cancelled(outcome) => break outcome,
+            _ => {}
         }
+    };
+
+    if let Some(stream) = &mut stream {
+        progress.suspend(|| stream.finish())?;
+    } else {
+        buffer.finish()?;
+        let mut bytes = buffer.into_inner();
+        if let Some(compression) = compression {
+            bytes = compression.compress(&bytes)?;
+        }
+        match &output {
+            Some(path) => write_bytes_atomic(path, &bytes)?,
+            None => progress.suspend(|| {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(&bytes).and_then(|_| stdout.flush())
+            })?,
+        }
+    }
+    if let Some(thread_progress) = &thread_progress {
+        thread_progress.finish();
+    }
+    progress.finish();
+    if setting.notify {
+        notify_completed(&outcome);
     }
 
+    Ok(outcome.exit_code(false))
+}
+
+/// Hash the files under `dir` that aren't in the hash list at `file_path` and add them to its
+/// end, with the format and algorithm of the hash list
+pub fn append_mode(file_path: PathBuf, dir: PathBuf, mut setting: Setting) -> Result<ExitCode> {
+    if !dir.is_dir() {
+        return Err(eyre!("Path is not directory: {dir:?}"));
+    }
+    // Before changing the current directory, which relative paths are from
+    let dir = dir.canonicalize()?;
+    let file_path = enter_hash_list_dir(&file_path, &setting)?;
+    ensure_uncompressed(&file_path)?;
+
+    let hash_list = hash_list_parser(
+        &file_path,
+        setting.algorithm,
+        setting.duplicate_policy,
+        setting.strict,
+    )?;
+    print_warnings(&hash_list.warnings, setting.verbosity);
+    ensure_editable(hash_list.format)?;
+    setting.algorithm = Some(hash_list.algorithm);
+    let file_list = find_unlisted_files(&hash_list.pairs, &file_path, &dir)?;
+
+    let total_bytes = total_len(&file_list);
+    let (progress, bytes_read) = progress_bar(file_list.len(), total_bytes, setting.verbosity);
+    let (status, thread_progress) = progress_status(&progress, bytes_read, &setting);
+
+    let (tx, rx) = channel::unbounded();
+
+    // New entries are relative to the current directory, like the listed ones
+    let root = std::env::current_dir()?.canonicalize()?;
+    let hashing_setting = setting.clone();
+    std::thread::spawn(move || {
+        generate_hash_list(
+            &root,
+            file_list,
+            &hashing_setting,
+            status,
+            tx,
+            &Pause::default(),
+            &AtomicBool::new(false),
+        )
+    });
+
+    let mut entries = vec![];
+    let outcome = loop {
+        match rx.recv()? {
+            Message::Generated(path, hash) => entries.push((path, hash)),
+            Message::Error { file_path, error } => log_line(
+                &progress,
+                setting.verbosity,
+                error_line(&file_path, &error, setting.color),
+            ),
+            Message::Completed(outcome) | Message::Cancelled(outcome) => break outcome,
+            _ => {}
+        }
+    };
+
+    // Hashed in parallel, so in any order
+    entries.sort();
+    append_to_hash_list(&file_path, hash_list.format, hash_list.algorithm, &entries)?;
+    if let Some(thread_progress) = &thread_progress {
+        thread_progress.finish();
+    }
     progress.finish();
+    eprintln!("Appended {} entries to {file_path:?}", entries.len());
 
-    Ok(())
+    Ok(outcome.exit_code(false))
+}
+
+/// Entries of `hash_list` for the files in `deleted`, printed with `dry_run` instead of being
+/// removed
+fn deleted_entry_num(hash_list: &[HashPair], deleted: &HashSet<String>, dry_run: bool) -> usize {
+    let mut deleted_num = 0;
+    for pair in hash_list.iter().filter(|x| deleted.contains(x.file_path())) {
+        if dry_run {
+            println!("Would delete: {}", pair.file_path());
+        }
+        deleted_num += 1;
+    }
+    deleted_num
+}
+
+fn dry_run_note(dry_run: bool) -> &'static str {
+    if dry_run {
+        " (dry run, the hash list is unchanged)"
+    } else {
+        ""
+    }
+}
+
+/// Remove the entries of files that don't exist from the hash list at `file_path`, keeping the
+/// order of the others. With `dry_run`, they are only printed
+pub fn delete_mode(file_path: PathBuf, setting: Setting, dry_run: bool) -> Result<ExitCode> {
+    let file_path = enter_hash_list_dir(&file_path, &setting)?;
+    ensure_uncompressed(&file_path)?;
+
+    let hash_list = hash_list_parser(
+        &file_path,
+        setting.algorithm,
+        setting.duplicate_policy,
+        setting.strict,
+    )?;
+    print_warnings(&hash_list.warnings, setting.verbosity);
+    ensure_editable(hash_list.format)?;
+    let missing: HashSet<_> = find_missing_files(&hash_list.pairs, &setting)
+        .into_iter()
+        .collect();
+
+    let deleted_num = deleted_entry_num(&hash_list.pairs, &missing, dry_run);
+    if !dry_run && deleted_num > 0 {
+        update_hash_list(&file_path, &hash_list.pairs, &HashMap::new(), &missing)?;
+    }
+    eprintln!("Deleted: {deleted_num}{}", dry_run_note(dry_run));
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Hash every file in the hash list at `file_path` and replace the hashes of the files that
+/// changed. Missing files are kept in the hash list, unless `delete` is set. With `dry_run`, the
+/// hash list isn't changed and the entries that would be deleted are printed
+pub fn update_mode(
+    file_path: PathBuf,
+    setting: Setting,
+    delete: bool,
+    dry_run: bool,
+) -> Result<ExitCode> {
+    let file_path = enter_hash_list_dir(&file_path, &setting)?;
+    ensure_uncompressed(&file_path)?;
+
+    let hash_list = hash_list_parser(
+        &file_path,
+        setting.algorithm,
+        setting.duplicate_policy,
+        setting.strict,
+    )?;
+    print_warnings(&hash_list.warnings, setting.verbosity);
+    ensure_editable(hash_list.format)?;
+    let pairs = hash_list.pairs;
+
+    let paths: Vec<_> = pairs.iter().map(|x| x.file_path()).collect();
+    let (progress, bytes_read) = progress_bar(pairs.len(), total_len(&paths), setting.verbosity);
+    let (status, thread_progress) = progress_status(&progress, bytes_read, &setting);
+
+    let (tx, rx) = channel::unbounded();
+
+    let hashing_pairs = pairs.clone();
+    let hashing_setting = setting.clone();
+    let hashing_file_path = file_path.clone();
+    std::thread::spawn(move || {
+        prepare_hashing(
+            hashing_pairs,
+            &hashing_setting,
+            status,
+            tx,
+            &Pause::default(),
+            &AtomicBool::new(false),
+            &hashing_file_path,
+        )
+    });
+
+    let verbosity = setting.verbosity;
+    let mut new_hashes = HashMap::new();
+    let mut missing = HashSet::new();
+    let outcome = loop {
+        match rx.recv()? {
+            Message::Incorrect {
+                file_path,
+                file_hash,
+                ..
+            } => {
+                if verbosity == Verbosity::Verbose {
+                    log_line(&progress, verbosity, format!("Updated: {file_path}"));
+                }
+                new_hashes.insert(file_path, file_hash);
+            }
+            Message::Missing(file_path) => {
+                log_line(
+                    &progress,
+                    verbosity,
+                    missing_line(&file_path, setting.color),
+                );
+                missing.insert(file_path);
+            }
+            Message::Error { file_path, error } => log_line(
+                &progress,
+                verbosity,
+                error_line(&file_path, &error, setting.color),
+            ),
+            Message::Completed(outcome) | Message::Cancelled(outcome) => break outcome,
+            _ => {}
+        }
+    };
+
+    if let Some(thread_progress) = &thread_progress {
+        thread_progress.finish();
+    }
+    if !delete {
+        missing.clear();
+    }
+    let deleted_num = progress.suspend(|| deleted_entry_num(&pairs, &missing, dry_run));
+    // Nothing to rewrite if every hash is the same
+    if !dry_run && (!new_hashes.is_empty() || deleted_num > 0) {
+        update_hash_list(&file_path, &pairs, &new_hashes, &missing)?;
+    }
+
+    let mut summary = format!(
+        "Updated: {}, Unchanged: {}, Missing: {}",
+        new_hashes.len(),
+        outcome.correct_num,
+        outcome.missing_num
+    );
+    if delete {
+        summary.push_str(&format!(", Deleted: {deleted_num}"));
+        summary.push_str(dry_run_note(dry_run));
+    }
+    if outcome.error_num > 0 {
+        summary.push_str(&format!(", Error: {}", outcome.error_num));
+    }
+    progress.finish();
+    eprintln!("{summary}");
+
+    // Files that couldn't be read still have their old hash
+    Ok(if outcome.error_num > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Combine the hash lists at `file_paths` into one at `output`, with an entry per file. Entries
+/// are kept as written, so relative paths should be from the same directory
+pub fn merge_mode(
+    file_paths: Vec<PathBuf>,
+    output: PathBuf,
+    setting: Setting,
+    policy: MergePolicy,
+) -> Result<ExitCode> {
+    let mut hash_lists = vec![];
+    for file_path in &file_paths {
+        let hash_list = hash_list_parser(
+            file_path,
+            setting.algorithm,
+            setting.duplicate_policy,
+            setting.strict,
+        )?;
+        if !hash_list.warnings.is_empty() {
+            eprintln!("{file_path:?}:");
+            print_warnings(&hash_list.warnings, setting.verbosity);
+        }
+        hash_lists.push(hash_list);
+    }
+
+    let merged = merge_hash_lists(hash_lists, policy);
+    // Always listed, as one of the hashes is dropped
+    for warning in &merged.warnings {
+        eprintln!("Warning: {warning}");
+    }
+    write_formatted_hash_list_atomic(&output, &merged)?;
+    eprintln!(
+        "Merged {} entries from {} hash lists into {output:?}",
+        merged.pairs.len(),
+        file_paths.len()
+    );
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn diff_line(entry: &DiffEntry) -> String {
+    let symbol = match entry.kind {
+        DiffKind::Added => '+',
+        DiffKind::Removed => '-',
+        DiffKind::Changed => '~',
+        DiffKind::Unchanged => '=',
+        DiffKind::Renamed { .. } => 'R',
+        DiffKind::Copied { .. } => 'C',
+    };
+    match entry.kind.source() {
+        Some(from) => format!("{symbol} {from} -> {}", entry.file_path),
+        None => format!("{symbol} {}", entry.file_path),
+    }
+}
+
+fn diff_status(kind: &DiffKind) -> &'static str {
+    match kind {
+        DiffKind::Added => "added",
+        DiffKind::Removed => "removed",
+        DiffKind::Changed => "changed",
+        DiffKind::Unchanged => "unchanged",
+        DiffKind::Renamed { .. } => "renamed",
+        DiffKind::Copied { .. } => "copied",
+    }
+}
+
+/// Compare the hash lists at `old_path` and `new_path`, writing the files that differ to `output`
+/// or stdout. Unchanged files are only written when verbose. With `renames`, see `detect_renames`.
+/// Returns 1 if they differ, like `diff`
+pub fn diff_mode(
+    old_path: PathBuf,
+    new_path: PathBuf,
+    output: Option<PathBuf>,
+    format: ReportFormat,
+    setting: Setting,
+    renames: bool,
+) -> Result<ExitCode> {
+    match format {
+        ReportFormat::Junit => return Err(eyre!("JUnit reports aren't supported with --diff")),
+        ReportFormat::JsonManifest => return Err(eyre!("Manifests aren't supported with --diff")),
+        _ => {}
+    }
+
+    let mut pairs = vec![];
+    for file_path in [&old_path, &new_path] {
+        let hash_list = hash_list_parser(
+            file_path,
+            setting.algorithm,
+            setting.duplicate_policy,
+            setting.strict,
+        )?;
+        if !hash_list.warnings.is_empty() {
+            eprintln!("{file_path:?}:");
+            print_warnings(&hash_list.warnings, setting.verbosity);
+        }
+        pairs.push(hash_list.pairs);
+    }
+    let mut entries = diff_hash_lists(&pairs[0], &pairs[1]);
+    if renames {
+        entries = detect_renames(entries);
+    }
+
+    let mut writer: Box<dyn Write> = match &output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    if format == ReportFormat::Csv {
+        writeln!(writer, "path,status,old_hash,new_hash,old_path")?;
+    }
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for entry in &entries {
+        let status = diff_status(&entry.kind);
+        *counts.entry(status).or_default() += 1;
+        if entry.kind == DiffKind::Unchanged && setting.verbosity != Verbosity::Verbose {
+            continue;
+        }
+
+        let old_hash = entry.old_hash.as_deref().unwrap_or_default();
+        let new_hash = entry.new_hash.as_deref().unwrap_or_default();
+        match format {
+            ReportFormat::Plain => writeln!(writer, "{}", diff_line(entry))?,
+            ReportFormat::Json => {
+                let entry = json!({
+                    "type": status,
+                    "file": entry.file_path,
+                    "old_hash": entry.old_hash,
+                    "new_hash": entry.new_hash,
+                    "old_path": entry.kind.source(),
+                });
+                writeln!(writer, "{entry}")?
+            }
+            ReportFormat::Csv => writeln!(
+                writer,
+                "{},{status},{old_hash},{new_hash},{}",
+                csv_field(&entry.file_path),
+                csv_field(entry.kind.source().unwrap_or_default())
+            )?,
+            ReportFormat::Junit | ReportFormat::JsonManifest => unreachable!("Checked above"),
+        }
+    }
+    writer.flush()?;
+
+    let count = |status| counts.get(status).copied().unwrap_or_default();
+    let mut summary = format!(
+        "Added: {}, Removed: {}, Changed: {}, Unchanged: {}",
+        count("added"),
+        count("removed"),
+        count("changed"),
+        count("unchanged")
+    );
+    if renames {
+        summary.push_str(&format!(
+            ", Renamed: {}, Copied: {}",
+            count("renamed"),
+            count("copied")
+        ));
+    }
+    eprintln!("{summary}");
+
+    Ok(if entries.iter().all(|x| x.kind == DiffKind::Unchanged) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Print the throughput of hashing a file with each block size of `PROFILE_BLOCK_SIZES` and the
+/// recommended one. The file is `sample_file`, or the first file of the hash list at `file_path`.
+/// With `save_to`, the recommendation is written to that config file, or the default one
+pub fn profile_mode(
+    file_path: Option<PathBuf>,
+    sample_file: Option<PathBuf>,
+    save_to: Option<Option<PathBuf>>,
+    setting: Setting,
+) -> Result<ExitCode> {
+    // Before changing the current directory, like the report
+    let save_to = save_to
+        .map(|x| x.as_deref().map(std::path::absolute).transpose())
+        .transpose()?;
+
+    let (sample_file, algorithm) = match (sample_file, file_path) {
+        (Some(x), _) => (x, setting.algorithm.unwrap_or_default()),
+        (None, Some(file_path)) => {
+            let file_path = enter_hash_list_dir(&file_path, &setting)?;
+            let hash_list = hash_list_parser(
+                &file_path,
+                setting.algorithm,
+                setting.duplicate_policy,
+                setting.strict,
+            )?;
+            print_warnings(&hash_list.warnings, setting.verbosity);
+            // Parsing fails on empty hash lists
+            let first = PathBuf::from(hash_list.pairs[0].file_path());
+            (first, hash_list.algorithm)
+        }
+        (None, None) => {
+            return Err(eyre!(
+                "--profile-block-size needs a hash list or --sample-file"
+            ))
+        }
+    };
+
+    let len = fs::metadata(&sample_file)
+        .map_err(|e| eyre!("Couldn't read {sample_file:?}: {e}"))?
+        .len();
+    eprintln!(
+        "Profiling {sample_file:?} ({})",
+        size::Size::from_bytes(len)
+    );
+    let profile = profile_block_sizes(&sample_file, algorithm, &setting)?;
+    for (block_size, throughput) in &profile {
+        eprintln!(
+            "{:>12}  {throughput:.1} MB/s",
+            size::Size::from_bytes(*block_size).to_string()
+        );
+    }
+
+    let block_size = recommend_block_size(&profile).expect("Every block size is profiled");
+    eprintln!(
+        "Recommended block size: {block_size} ({})",
+        size::Size::from_bytes(block_size)
+    );
+    if let Some(path) = save_to {
+        let path = Config::save_block_size(path.as_deref(), block_size)?;
+        eprintln!("Saved to {path:?}");
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Print the files of the hash list at `file_path` that have the same hash, a group per hash.
+/// Hash lists don't have file sizes, so `min_size` is checked against the files on disk and
+/// leaves out missing files
+pub fn duplicates_mode(
+    file_path: PathBuf,
+    min_size: Option<u64>,
+    setting: Setting,
+) -> Result<ExitCode> {
+    let file_path = enter_hash_list_dir(&file_path, &setting)?;
+
+    let hash_list = hash_list_parser(
+        &file_path,
+        setting.algorithm,
+        setting.duplicate_policy,
+        setting.strict,
+    )?;
+    print_warnings(&hash_list.warnings, setting.verbosity);
+    let mut groups = find_duplicate_files(&hash_list.pairs);
+    if let Some(min_size) = min_size {
+        for (_, files) in &mut groups {
+            files.retain(|x| fs::metadata(x).is_ok_and(|x| x.len() >= min_size));
+        }
+        groups.retain(|(_, files)| files.len() > 1);
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    for (idx, (hash, files)) in groups.iter().enumerate() {
+        if idx > 0 {
+            writeln!(stdout)?;
+        }
+        writeln!(stdout, "{hash}")?;
+        for file in files {
+            writeln!(stdout, "  {file}")?;
+        }
+    }
+    stdout.flush()?;
+
+    let file_num: usize = groups.iter().map(|(_, files)| files.len()).sum();
+    eprintln!("Groups: {}, Files: {file_num}", groups.len());
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Check that the files of the hash lists at `file_paths` exist and can be read, without hashing
+/// them. The missing and unreadable files are printed, then the counts and the bytes a run would
+/// read. Fails like `cli_mode` would if any file is missing or unreadable
+pub fn dry_run_mode(file_paths: Vec<PathBuf>, setting: Setting) -> Result<ExitCode> {
+    // Every hash list changes the current directory, like with `cli_mode`
+    let mut absolute_paths = Vec::with_capacity(file_paths.len());
+    for file_path in file_paths {
+        absolute_paths.push(if file_path == Path::new(STDIN_PATH) {
+            file_path
+        } else {
+            std::path::absolute(file_path)?
+        });
+    }
+
+    let mut report = DryRunReport::default();
+    for file_path in absolute_paths {
+        let file_path = enter_files_dir(file_path, &setting)?;
+        let hash_list = hash_list_parser(
+            &file_path,
+            setting.algorithm,
+            setting.duplicate_policy,
+            setting.strict,
+        )?;
+        print_warnings(&hash_list.warnings, setting.verbosity);
+
+        let list_report = dry_run_hash_list(&hash_list.pairs, &setting);
+        for file_path in &list_report.missing {
+            eprintln!("{}", missing_line(file_path, setting.color));
+        }
+        for (file_path, error) in &list_report.unreadable {
+            eprintln!("{}", error_line(file_path, error, setting.color));
+        }
+        report.verifiable_num += list_report.verifiable_num;
+        report.total_bytes += list_report.total_bytes;
+        report.missing.extend(list_report.missing);
+        report.unreadable.extend(list_report.unreadable);
+    }
+
+    println!(
+        "Verifiable: {}, Missing: {}, Unreadable: {}, Total size: {}",
+        report.verifiable_num,
+        report.missing.len(),
+        report.unreadable.len(),
+        Size::from_bytes(report.total_bytes)
+    );
+
+    let missing = !report.missing.is_empty() && !setting.ignore_missing;
+    Ok(if missing || !report.unreadable.is_empty() {
+        ExitCode::from(2)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Print the number of entries and skipped lines of the hash list at `file_path` and its
+/// algorithm, without hashing anything. As JSON with `ReportFormat::Json`
+pub fn count_mode(file_path: PathBuf, format: ReportFormat, setting: Setting) -> Result<ExitCode> {
+    let hash_list = match format {
+        ReportFormat::Plain | ReportFormat::Json => hash_list_parser(
+            &file_path,
+            setting.algorithm,
+            setting.duplicate_policy,
+            setting.strict,
+        )?,
+        ReportFormat::JsonManifest => manifest_parser(&file_path)?,
+        _ => {
+            return Err(eyre!(
+                "Only plain and JSON formats are supported with --count"
+            ))
+        }
+    };
+    let skipped_num = hash_list.malformed_num();
+
+    if format == ReportFormat::Json {
+        let count = json!({
+            "entries": hash_list.pairs.len(),
+            "skipped": skipped_num,
+            "algorithm": hash_list.algorithm.name(),
+        });
+        println!("{count}");
+    } else {
+        let detected = if setting.algorithm.is_none() {
+            " (auto-detected)"
+        } else {
+            ""
+        };
+        println!(
+            "Entries: {}, Skipped lines: {skipped_num}, Algorithm: {}{detected}",
+            hash_list.pairs.len(),
+            hash_list.algorithm
+        );
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Sign the hash list at `file_path` with the key file at `key`, see `signature`
+pub fn sign_mode(file_path: PathBuf, key: PathBuf) -> Result<ExitCode> {
+    if file_path == Path::new(STDIN_PATH) {
+        return Err(eyre!("Hash lists from stdin can't be signed"));
+    }
+    ensure_uncompressed(&file_path)?;
+    let key = read_key(&key).wrap_err_with(|| format!("Couldn't read key file {key:?}"))?;
+
+    let signature = sign_hash_list(&file_path, &key)?;
+    eprintln!("Signed {file_path:?}: {signature}");
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Write the entries of the hash list at `file_path` whose path matches `pattern` to `output` or
+/// stdout, in the format of the hash list. Returns 1 if none match
+pub fn extract_mode(
+    file_path: PathBuf,
+    pattern: Pattern,
+    output: Option<PathBuf>,
+    setting: Setting,
+) -> Result<ExitCode> {
+    let hash_list = hash_list_parser(
+        &file_path,
+        setting.algorithm,
+        setting.duplicate_policy,
+        setting.strict,
+    )?;
+    print_warnings(&hash_list.warnings, setting.verbosity);
+    ensure_editable(hash_list.format)?;
+    let entry_num = hash_list.pairs.len();
+
+    let extracted = extract_hash_list(hash_list, &pattern);
+    if extracted.pairs.is_empty() {
+        eprintln!("Warning: No entries match {:?}", pattern.as_str());
+        return Ok(ExitCode::FAILURE);
+    }
+    match &output {
+        Some(output) => write_formatted_hash_list_atomic(output, &extracted)?,
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            for line in extracted.lines() {
+                writeln!(stdout, "{line}")?;
+            }
+            stdout.flush()?;
+        }
+    }
+    if !matches!(setting.verbosity, Verbosity::Quiet | Verbosity::Silent) {
+        eprintln!("Extracted {} of {entry_num} entries", extracted.pairs.len());
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Print the paths of the hash list at `file_path`, one per line, without touching the files.
+/// With `Verbosity::Verbose`, as GNU hash list lines, and with `ReportFormat::Json`, as a JSON
+/// array of objects
+pub fn list_mode(file_path: PathBuf, format: ReportFormat, setting: Setting) -> Result<ExitCode> {
+    let hash_list = match format {
+        ReportFormat::Plain | ReportFormat::Json => hash_list_parser(
+            &file_path,
+            setting.algorithm,
+            setting.duplicate_policy,
+            setting.strict,
+        )?,
+        ReportFormat::JsonManifest => manifest_parser(&file_path)?,
+        _ => {
+            return Err(eyre!(
+                "Only plain and JSON formats are supported with --list"
+            ))
+        }
+    };
+    print_warnings(&hash_list.warnings, setting.verbosity);
+
+    let mut stdout = std::io::stdout().lock();
+    if format == ReportFormat::Json {
+        let entries: Vec<_> = hash_list
+            .pairs
+            .iter()
+            .map(|x| {
+                json!({
+                    "path": x.file_path(),
+                    "hash": x.expected_hash(),
+                    "algorithm": x.algorithm().name(),
+                })
+            })
+            .collect();
+        writeln!(stdout, "{}", serde_json::to_string_pretty(&entries)?)?;
+    } else {
+        for pair in &hash_list.pairs {
+            if setting.verbosity == Verbosity::Verbose {
+                let line = HashListFormat::Gnu.entry(
+                    pair.algorithm(),
+                    pair.file_path(),
+                    pair.expected_hash(),
+                );
+                writeln!(stdout, "{line}")?;
+            } else {
+                writeln!(stdout, "{}", pair.file_path())?;
+            }
+        }
+    }
+    stdout.flush()?;
+
+    Ok(ExitCode::SUCCESS)
 }
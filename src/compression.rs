@@ -0,0 +1,74 @@
+//! Gzip and zstd compressed hash lists, detected from the extension or the magic bytes
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression of a hash list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compression {
+    /// `.gz` or `.gzip`
+    Gzip,
+    /// `.zst` or `.zstd`
+    Zstd,
+}
+
+impl Compression {
+    /// From the extension of `path`, ignoring case
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gz" | "gzip" => Some(Self::Gzip),
+            "zst" | "zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    /// From the magic bytes at the start of a file
+    pub fn from_magic(start: &[u8]) -> Option<Self> {
+        if start.starts_with(GZIP_MAGIC) {
+            Some(Self::Gzip)
+        } else if start.starts_with(ZSTD_MAGIC) {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Compression of the file at `path`, from its extension or its first bytes
+    pub fn of_file(path: &Path) -> io::Result<Option<Self>> {
+        if let Some(compression) = Self::from_extension(path) {
+            return Ok(Some(compression));
+        }
+        let mut start = Vec::with_capacity(ZSTD_MAGIC.len());
+        File::open(path)?
+            .take(ZSTD_MAGIC.len() as u64)
+            .read_to_end(&mut start)?;
+        Ok(Self::from_magic(&start))
+    }
+
+    /// Decompress `reader` on the fly
+    pub fn decoder<'a>(self, reader: impl BufRead + 'a) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Self::Gzip => Box::new(BufReader::new(GzDecoder::new(reader))),
+            Self::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+        })
+    }
+
+    /// `bytes` compressed at the default level
+    pub fn compress(self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+            Self::Zstd => zstd::encode_all(bytes, 0),
+        }
+    }
+}
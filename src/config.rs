@@ -0,0 +1,169 @@
+//! Default settings from a TOML config file
+
+use crate::hash::{HashAlgorithm, HashListFormat};
+use crate::{ColorMode, Setting, Verbosity};
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Overrides the default config path
+const CONFIG_ENV: &str = "MD5CHECK_CONFIG";
+
+/// Defaults read from `config.toml`. Every field is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    parallel: Option<bool>,
+    sort: Option<bool>,
+    fail_fast: Option<bool>,
+    block_size: Option<usize>,
+    min_block_size: Option<usize>,
+    #[serde(default, deserialize_with = "deserialize_algorithm")]
+    algorithm: Option<HashAlgorithm>,
+    threads: Option<usize>,
+    parallel_auto: Option<bool>,
+    verbosity: Option<Verbosity>,
+    mmap: Option<bool>,
+    mmap_threshold: Option<u64>,
+    rate_limit: Option<f64>,
+    cpu_limit: Option<f32>,
+    incremental: Option<bool>,
+    retries: Option<u32>,
+    check_extra: Option<bool>,
+    preflight: Option<bool>,
+    case_sensitive: Option<bool>,
+    case_insensitive_paths: Option<bool>,
+    strict: Option<bool>,
+    notify: Option<bool>,
+    color: Option<ColorMode>,
+    hash_list_format: Option<HashListFormat>,
+    /// Capture the mouse in the TUI
+    pub mouse: Option<bool>,
+}
+
+/// Whether `line` sets `key`
+fn is_key(line: &str, key: &str) -> bool {
+    line.trim_start()
+        .strip_prefix(key)
+        .is_some_and(|x| x.trim_start().starts_with('='))
+}
+
+/// Same names as `--algorithm`
+fn deserialize_algorithm<'de, D>(deserializer: D) -> Result<Option<HashAlgorithm>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(deserializer)?;
+    name.parse().map(Some).map_err(D::Error::custom)
+}
+
+impl Config {
+    fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|x| x.join("md5check").join("config.toml"))
+    }
+
+    /// `path`, `$MD5CHECK_CONFIG` or `<config dir>/md5check/config.toml`, whether or not it exists
+    pub fn path(path: Option<&Path>) -> Option<PathBuf> {
+        match path {
+            Some(x) => Some(x.to_path_buf()),
+            None => match std::env::var_os(CONFIG_ENV) {
+                Some(x) => Some(PathBuf::from(x)),
+                None => Self::default_path(),
+            },
+        }
+    }
+
+    /// Read `path`, `$MD5CHECK_CONFIG` or `<config dir>/md5check/config.toml`.
+    /// Only a missing default config file is allowed
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let explicit = path.is_some() || std::env::var_os(CONFIG_ENV).is_some();
+        let path = match Self::path(path) {
+            Some(x) if explicit || x.is_file() => x,
+            _ => return Ok(Self::default()),
+        };
+
+        let text = fs::read_to_string(&path)
+            .map_err(|e| eyre!("Couldn't read config file {path:?}: {e}"))?;
+        toml::from_str(&text).map_err(|e| eyre!("Invalid config file {path:?}: {e}"))
+    }
+
+    /// Set `block-size` in the config file at `path`, see `Config::path`. Other lines, including
+    /// comments, are kept. Returns the path of the config file
+    pub fn save_block_size(path: Option<&Path>, block_size: usize) -> Result<PathBuf> {
+        let path = Self::path(path).ok_or_else(|| eyre!("Couldn't find the config directory"))?;
+        let text = match fs::read_to_string(&path) {
+            Ok(x) => x,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(eyre!("Couldn't read config file {path:?}: {e}")),
+        };
+
+        let line = format!("block-size = {block_size}");
+        let mut lines: Vec<_> = text.lines().map(str::to_string).collect();
+        // Every key is top-level, so a new one can go at the end
+        match lines.iter_mut().find(|x| is_key(x, "block-size")) {
+            Some(x) => *x = line,
+            None => lines.push(line),
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, lines.join("\n") + "\n")
+            .map_err(|e| eyre!("Couldn't write config file {path:?}: {e}"))?;
+        Ok(path)
+    }
+
+    /// Replace the fields of `setting` that are set in the config
+    pub fn apply(&self, setting: Setting) -> Setting {
+        Setting {
+            parallel: self.parallel.unwrap_or(setting.parallel),
+            sort: self.sort.unwrap_or(setting.sort),
+            fail_fast: self.fail_fast.unwrap_or(setting.fail_fast),
+            block_size: self.block_size.unwrap_or(setting.block_size),
+            min_block_size: self.min_block_size.unwrap_or(setting.min_block_size),
+            algorithm: self.algorithm.or(setting.algorithm),
+            threads: self.threads.or(setting.threads),
+            parallel_auto: self.parallel_auto.unwrap_or(setting.parallel_auto),
+            verbosity: self.verbosity.unwrap_or(setting.verbosity),
+            mmap: self.mmap.unwrap_or(setting.mmap),
+            mmap_threshold: self.mmap_threshold.unwrap_or(setting.mmap_threshold),
+            rate_limit: self.rate_limit.or(setting.rate_limit),
+            cpu_limit: self.cpu_limit.or(setting.cpu_limit),
+            incremental: self.incremental.unwrap_or(setting.incremental),
+            ignore_cache: setting.ignore_cache,
+            watch: setting.watch,
+            ignore_missing: setting.ignore_missing,
+            ignore_zero: setting.ignore_zero,
+            strict_zero: setting.strict_zero,
+            follow_symlinks: setting.follow_symlinks,
+            hash_symlink_target: setting.hash_symlink_target,
+            include: setting.include,
+            exclude: setting.exclude,
+            duplicate_policy: setting.duplicate_policy,
+            strict: self.strict.unwrap_or(setting.strict),
+            warn: setting.warn,
+            retries: self.retries.unwrap_or(setting.retries),
+            check_extra: self.check_extra.unwrap_or(setting.check_extra),
+            preflight: self.preflight.unwrap_or(setting.preflight),
+            case_sensitive: self.case_sensitive.unwrap_or(setting.case_sensitive),
+            base_dir: setting.base_dir,
+            case_insensitive_paths: self
+                .case_insensitive_paths
+                .unwrap_or(setting.case_insensitive_paths),
+            audit_log: setting.audit_log,
+            audit_format: setting.audit_format,
+            benchmark: setting.benchmark,
+            signature_key: setting.signature_key,
+            checkpoint_file: setting.checkpoint_file,
+            checkpoint_interval: setting.checkpoint_interval,
+            resume: setting.resume,
+            dry_run: setting.dry_run,
+            notify: self.notify.unwrap_or(setting.notify),
+            color: self.color.unwrap_or(setting.color),
+            hash_list_format: self.hash_list_format.unwrap_or(setting.hash_list_format),
+        }
+    }
+}
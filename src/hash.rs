@@ -1,31 +1,741 @@
-use crate::{Message, Setting, Status};
-use color_eyre::eyre::eyre;
+//! Parsing hash lists, hashing files and running verification and generation
+
+use crate::audit::{append_audit_entry, csv_field, start_audit_log, AuditEntry};
+use crate::benchmark::FileStats;
+use crate::cache::{cache_path, IncrementalCache, InodeCache};
+use crate::checkpoint::Checkpoint;
+use crate::compression::Compression;
+use crate::reader::{HashListReader, ParseError};
+use crate::writer::HashListWriter;
+use crate::{
+    Message, Setting, Status, Verbosity, VerificationOutcome, VerificationResult,
+    VerificationStatus, VerificationSummary,
+};
+use blake2::digest::{Update, VariableOutput};
+use blake2::Blake2bVar;
 use color_eyre::Result;
 use crossbeam::channel::Sender;
-use indicatif::ProgressBar;
+use encoding_rs::WINDOWS_1252;
+use glob::Pattern;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use itertools::Itertools;
 use md5::{Digest, Md5};
-use parking_lot::RwLock;
+use memmap2::Mmap;
+use notify::{EventKind, RecursiveMode, Watcher};
+use parking_lot::{Mutex, RwLock};
 use rayon::prelude::*;
-use regex::Regex;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Sha224, Sha256, Sha384, Sha512};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::Arc;
-use std::time::Instant;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::NamedTempFile;
+use thiserror::Error;
+use walkdir::WalkDir;
+
+/// Hash list path meaning stdin
+pub const STDIN_PATH: &str = "-";
+
+/// Usual hash list names looked for by `find_hash_lists`, most common first
+pub const DEFAULT_HASH_LIST_NAMES: &[&str] = &[
+    "MD5SUMS",
+    "md5sums",
+    "md5sums.txt",
+    "checksums.md5",
+    "CHECKSUMS",
+    "checksums.txt",
+    "SHA1SUMS",
+    "sha1sums",
+    "SHA256SUMS",
+    "sha256sums",
+    "checksums.sha256",
+    "SHA512SUMS",
+    "sha512sums",
+    "B2SUMS",
+    "b2sums",
+];
+
+/// Byte order mark at the start of UTF-8 files saved by some Windows editors
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Changes are handled once a file hasn't been modified for this long
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Delay before the first retry, doubled for every later one
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+
+/// Bytes in a MB of `Setting::rate_limit` and of benchmark throughputs
+pub(crate) const BYTES_PER_MB: f64 = 1_000_000.0;
+
+/// Shared by every hashing thread in parallel mode, as they read from the same disks
+static TOKEN_BUCKET: Mutex<Option<TokenBucket>> = Mutex::new(None);
+
+/// Threads run for `Setting::cpu_limit` of this, then sleep for the rest
+const CPU_LIMIT_PERIOD: Duration = Duration::from_millis(100);
+
+thread_local! {
+    /// Start of the current `duty_cycle` period of this thread, kept across files
+    static PERIOD_START: Cell<Instant> = Cell::new(Instant::now());
+    /// Bytes hashed by this thread since it was last reset, for `FileStats::bytes`
+    static BYTES_READ: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Errors from reading hash lists and hashing files. The path is shown separately
+#[derive(Debug, Error)]
+pub enum HashError {
+    /// The file doesn't exist
+    #[error("File not found")]
+    FileNotFound {
+        /// File that was opened
+        path: PathBuf,
+    },
+    /// The file exists but can't be opened
+    #[error("Permission denied")]
+    PermissionDenied {
+        /// File that was opened
+        path: PathBuf,
+    },
+    /// Any other error while reading
+    #[error("Read error: {source}")]
+    ReadError {
+        /// File that was read
+        path: PathBuf,
+        /// Underlying error
+        source: io::Error,
+    },
+    /// Writing a generated hash list failed
+    #[error("Write error: {source}")]
+    WriteError {
+        /// File that was written
+        path: PathBuf,
+        /// Underlying error
+        source: io::Error,
+    },
+    /// A hash list line that can't be parsed, where it can't be skipped
+    #[error("Malformed hash list at line {line}: {content}")]
+    MalformedHashList {
+        /// 1-based line number
+        line: usize,
+        /// The line itself
+        content: String,
+    },
+    /// A file appears more than once in the hash list, with `DuplicatePolicy::Error`
+    #[error("Duplicate entry at line {line}: {file_path}")]
+    DuplicateEntry {
+        /// 1-based line number of the later entry
+        line: usize,
+        /// Path as written in the hash list
+        file_path: String,
+    },
+    /// The hash list has no valid lines
+    #[error("Empty hash list")]
+    EmptyHashList,
+    /// The file is a symlink, without `Setting::follow_symlinks`
+    #[error("Path is a symlink")]
+    IsSymlink {
+        /// Path of the symlink
+        path: PathBuf,
+    },
+    /// The expected hash is the hash of an empty input, with `Setting::strict_zero`
+    #[error("Expected hash is the hash of an empty file")]
+    EmptyFileHash,
+    /// The HMAC-SHA256 signature of the hash list is missing or doesn't match, see `signature`
+    #[error("Invalid HMAC-SHA256 signature")]
+    InvalidSignature,
+    /// The signing key file isn't `signature::KEY_LEN` bytes long
+    #[error("Key must be 32 bytes, not {len}")]
+    InvalidKey {
+        /// Length of the key file in bytes
+        len: usize,
+    },
+    /// JSON manifests are only verified, as their entries aren't lines that can be changed
+    #[error("JSON manifests can only be verified, not changed, extracted or signed")]
+    ManifestNotEditable,
+}
+
+impl HashError {
+    /// Sort an IO error from reading `path` into the matching variant
+    pub fn from_read(path: &Path, source: io::Error) -> Self {
+        let path = path.to_path_buf();
+        match source.kind() {
+            ErrorKind::NotFound => Self::FileNotFound { path },
+            ErrorKind::PermissionDenied => Self::PermissionDenied { path },
+            _ => Self::ReadError { path, source },
+        }
+    }
+
+    /// Read errors that may go away on their own, e.g. on network filesystems
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::ReadError { source, .. } if matches!(
+                source.kind(),
+                ErrorKind::ConnectionReset | ErrorKind::TimedOut | ErrorKind::WouldBlock
+            )
+        )
+    }
+}
+
+/// Algorithms supported for hash lists
+#[derive(Debug, Default, Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum HashAlgorithm {
+    /// MD5
+    #[default]
+    Md5,
+    /// SHA-1
+    Sha1,
+    /// SHA-224
+    Sha224,
+    /// SHA-256
+    Sha256,
+    /// SHA-384
+    Sha384,
+    /// SHA-512
+    Sha512,
+    /// Output length in bytes, from 1 to 64
+    Blake2b(usize),
+    /// BLAKE3 with the default 32 bytes output
+    Blake3,
+    /// CRC-32 as used by SFV files
+    Crc32,
+}
+
+impl HashAlgorithm {
+    /// Ordered so that SHA-256 is detected before BLAKE3 and SHA-512 before BLAKE2b-512, as they
+    /// have the same digest lengths
+    const ALL: [HashAlgorithm; 9] = [
+        Self::Md5,
+        Self::Sha1,
+        Self::Sha224,
+        Self::Sha256,
+        Self::Sha384,
+        Self::Sha512,
+        Self::Blake2b(64),
+        Self::Blake3,
+        Self::Crc32,
+    ];
+
+    /// Output length of `blake2b` without a length
+    const BLAKE2B_DEFAULT_LEN: usize = 32;
+
+    /// Length of the hex encoded digest
+    pub fn digest_len(self) -> usize {
+        match self {
+            HashAlgorithm::Md5 => 32,
+            HashAlgorithm::Sha1 => 40,
+            HashAlgorithm::Sha224 => 56,
+            HashAlgorithm::Sha256 => 64,
+            HashAlgorithm::Sha384 => 96,
+            HashAlgorithm::Sha512 => 128,
+            HashAlgorithm::Blake2b(len) => len * 2,
+            HashAlgorithm::Blake3 => 64,
+            HashAlgorithm::Crc32 => 8,
+        }
+    }
+
+    /// First algorithm with a hex digest of `len` characters
+    pub fn from_digest_len(len: usize) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.digest_len() == len)
+    }
+
+    /// Tag used by BSD style hash lists, e.g. `SHA256 (file) = <hash>`
+    pub fn bsd_tag(self) -> String {
+        match self {
+            HashAlgorithm::Md5 => "MD5".to_string(),
+            HashAlgorithm::Sha1 => "SHA1".to_string(),
+            HashAlgorithm::Sha224 => "SHA224".to_string(),
+            HashAlgorithm::Sha256 => "SHA256".to_string(),
+            HashAlgorithm::Sha384 => "SHA384".to_string(),
+            HashAlgorithm::Sha512 => "SHA512".to_string(),
+            // Same as b2sum
+            HashAlgorithm::Blake2b(64) => "BLAKE2b".to_string(),
+            HashAlgorithm::Blake2b(len) => format!("BLAKE2b-{}", len * 8),
+            HashAlgorithm::Blake3 => "BLAKE3".to_string(),
+            HashAlgorithm::Crc32 => "CRC32".to_string(),
+        }
+    }
+
+    /// Inverse of `bsd_tag`
+    pub fn from_bsd_tag(tag: &str) -> Option<Self> {
+        if let Some(bits) = tag.strip_prefix("BLAKE2b-") {
+            let bits: usize = bits.parse().ok()?;
+            let valid = bits.is_multiple_of(8) && (8..=512).contains(&bits);
+            return valid.then_some(Self::Blake2b(bits / 8));
+        }
+
+        Self::ALL
+            .into_iter()
+            .find(|x| x.bsd_tag().eq_ignore_ascii_case(tag))
+    }
+
+    /// Name accepted by `from_str`, e.g. `sha256` or `blake2b:64`
+    pub fn name(self) -> String {
+        match self {
+            HashAlgorithm::Blake2b(Self::BLAKE2B_DEFAULT_LEN) => "blake2b".to_string(),
+            HashAlgorithm::Blake2b(len) => format!("blake2b:{len}"),
+            _ => self.bsd_tag().to_ascii_lowercase(),
+        }
+    }
+
+    /// Hash of an empty input, which every empty file matches
+    pub fn empty_hash(self) -> String {
+        self.hasher().finalize()
+    }
+
+    /// Used by the TUI to cycle through algorithms
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|&x| x == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    fn hasher(self) -> Hasher {
+        match self {
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            HashAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            HashAlgorithm::Sha224 => Hasher::Sha224(Sha224::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha384 => Hasher::Sha384(Sha384::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            HashAlgorithm::Blake2b(len) => Hasher::Blake2b(
+                Blake2bVar::new(len).expect("Blake2b length is checked when parsing"),
+            ),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+        }
+    }
+}
+
+impl Display for HashAlgorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HashAlgorithm::Md5 => write!(f, "MD5"),
+            HashAlgorithm::Sha1 => write!(f, "SHA-1"),
+            HashAlgorithm::Sha224 => write!(f, "SHA-224"),
+            HashAlgorithm::Sha256 => write!(f, "SHA-256"),
+            HashAlgorithm::Sha384 => write!(f, "SHA-384"),
+            HashAlgorithm::Sha512 => write!(f, "SHA-512"),
+            HashAlgorithm::Blake2b(len) => write!(f, "BLAKE2b-{}", len * 8),
+            HashAlgorithm::Blake3 => write!(f, "BLAKE3"),
+            HashAlgorithm::Crc32 => write!(f, "CRC-32"),
+        }
+    }
+}
+
+/// Parses `md5`, `sha1`, `sha224`, `sha256`, `sha384`, `sha512`, `blake2b[:<bytes>]`, `blake3` and
+/// `crc32`
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+        let algorithm = match s.split_once(':') {
+            Some(("blake2b", len)) => {
+                let len = len
+                    .parse()
+                    .map_err(|e| format!("Invalid BLAKE2b length {len:?}: {e}"))?;
+                if !(1..=64).contains(&len) {
+                    return Err(format!("BLAKE2b length must be from 1 to 64, got {len}"));
+                }
+                HashAlgorithm::Blake2b(len)
+            }
+            Some(_) => return Err(format!("Unknown algorithm: {s}")),
+            None => match s.as_str() {
+                "md5" => HashAlgorithm::Md5,
+                "sha1" => HashAlgorithm::Sha1,
+                "sha224" => HashAlgorithm::Sha224,
+                "sha256" => HashAlgorithm::Sha256,
+                "sha384" => HashAlgorithm::Sha384,
+                "sha512" => HashAlgorithm::Sha512,
+                "blake2b" => HashAlgorithm::Blake2b(Self::BLAKE2B_DEFAULT_LEN),
+                "blake3" => HashAlgorithm::Blake3,
+                "crc32" => HashAlgorithm::Crc32,
+                _ => return Err(format!("Unknown algorithm: {s}")),
+            },
+        };
+
+        Ok(algorithm)
+    }
+}
+
+enum Hasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha224(Sha224),
+    Sha256(Sha256),
+    Sha384(Sha384),
+    Sha512(Sha512),
+    Blake2b(Blake2bVar),
+    // Boxed as it's much bigger than the others
+    Blake3(Box<blake3::Hasher>),
+    Crc32(crc32fast::Hasher),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha224(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha384(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            Hasher::Blake2b(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            Hasher::Crc32(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Hasher::Md5(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Sha1(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Sha224(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Sha256(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Sha384(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Sha512(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Blake2b(hasher) => {
+                let mut output = vec![0u8; hasher.output_size()];
+                hasher
+                    .finalize_variable(&mut output)
+                    .expect("Buffer has the output size");
+                hex::encode(output)
+            }
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Hasher::Crc32(hasher) => format!("{:08x}", hasher.finalize()),
+        }
+    }
+}
 
+/// A file and its expected hash from a hash list
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct HashPair {
     file_path: String,
     expected_hash: String,
+    algorithm: HashAlgorithm,
+    /// 1-based line number in the hash list
+    pub(crate) line: usize,
+    pub(crate) format: HashListFormat,
+}
+
+impl HashPair {
+    /// Path as written in the hash list
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    /// Hash as written in the hash list
+    pub fn expected_hash(&self) -> &str {
+        &self.expected_hash
+    }
+
+    /// Algorithm of `expected_hash`
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Returns `None` if the digest length doesn't match `algorithm`, or no algorithm has that
+    /// digest length when `algorithm` is `None`
+    pub(crate) fn new(
+        file_path: &str,
+        expected_hash: &str,
+        algorithm: Option<HashAlgorithm>,
+    ) -> Option<Self> {
+        let algorithm = match algorithm {
+            Some(x) if x.digest_len() == expected_hash.len() => x,
+            Some(_) => return None,
+            None => HashAlgorithm::from_digest_len(expected_hash.len())?,
+        };
+
+        Some(Self {
+            file_path: normalize_separators(file_path),
+            expected_hash: expected_hash.to_string(),
+            algorithm,
+            line: 0,
+            format: HashListFormat::Gnu,
+        })
+    }
+}
+
+/// `file_path` with the path separators of this platform, so hash lists made on Windows work on
+/// Unix and the other way around
+fn normalize_separators(file_path: &str) -> String {
+    if cfg!(windows) {
+        file_path.replace('/', "\\")
+    } else {
+        file_path.replace('\\', "/")
+    }
+}
+
+/// A problem in a hash list that didn't stop parsing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// A line that couldn't be parsed and was skipped
+    Malformed {
+        /// 1-based line number
+        line: usize,
+        /// The line itself
+        content: String,
+    },
+    /// The hash list isn't valid UTF-8 and was read as Windows-1252
+    EncodingFallback,
+    /// A file already in the hash list, kept with `DuplicatePolicy::Warn`
+    Duplicate {
+        /// 1-based line number of the later entry
+        line: usize,
+        /// Path as written in the hash list
+        file_path: String,
+    },
+    /// Some paths are absolute and others relative, which usually means a misconfigured list
+    MixedPathTypes,
+    /// A file with different hashes in merged hash lists, see `merge_hash_lists`
+    MergeConflict {
+        /// Path as written in the hash lists
+        file_path: String,
+        /// Hash of the entry that was kept
+        kept_hash: String,
+        /// Hash of the entry that was dropped
+        dropped_hash: String,
+    },
+}
+
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseWarning::Malformed { line, content } => {
+                write!(f, "Malformed line {line}: {content}")
+            }
+            ParseWarning::Duplicate { line, file_path } => {
+                write!(f, "Duplicate entry at line {line}: {file_path}")
+            }
+            ParseWarning::EncodingFallback => {
+                write!(f, "Hash list isn't valid UTF-8, read as Windows-1252")
+            }
+            ParseWarning::MixedPathTypes => {
+                write!(f, "Hash list mixes absolute and relative paths")
+            }
+            ParseWarning::MergeConflict {
+                file_path,
+                kept_hash,
+                dropped_hash,
+            } => write!(
+                f,
+                "Conflicting hashes for {file_path}: kept {kept_hash}, dropped {dropped_hash}"
+            ),
+        }
+    }
+}
+
+/// Format of a hash list. JSON manifests are parsed too, see `reader::HashListReader`, but CSV is
+/// only generated, see `writer::HashListWriter`
+#[derive(
+    Debug, Default, Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum HashListFormat {
+    /// `<hash>  <file>`
+    #[default]
+    Gnu,
+    /// `<TAG> (<file>) = <hash>`
+    Bsd,
+    /// `<file> <CRC32>`
+    Sfv,
+    /// JSON array of `manifest::ManifestEntry`. Only picked with `--format json-manifest`
+    #[serde(skip)]
+    #[value(skip)]
+    JsonManifest,
+    /// `<file>,<hash>` rows under a `path,hash` header. Only picked with `--format csv`
+    #[serde(skip)]
+    #[value(skip)]
+    Csv,
+}
+
+impl HashListFormat {
+    /// A hash list line in this format, without the line break. For JSON manifests, it's the
+    /// entry as a JSON object without the file metadata, which `writer::HashListWriter` adds
+    pub fn entry(self, algorithm: HashAlgorithm, file_path: &str, hash: &str) -> String {
+        match self {
+            HashListFormat::Gnu => format!("{hash}  {file_path}"),
+            HashListFormat::Bsd => format!("{} ({file_path}) = {hash}", algorithm.bsd_tag()),
+            // SFV hashes are usually uppercase
+            HashListFormat::Sfv => format!("{file_path} {}", hash.to_ascii_uppercase()),
+            HashListFormat::JsonManifest => serde_json::json!({
+                "path": file_path,
+                "hash": hash,
+                "algorithm": algorithm.name(),
+            })
+            .to_string(),
+            HashListFormat::Csv => format!("{},{hash}", csv_field(file_path)),
+        }
+    }
+}
+
+/// What to do when a file appears more than once in a hash list
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DuplicatePolicy {
+    /// Keep every entry and add a `ParseWarning::Duplicate`
+    #[default]
+    Warn,
+    /// Fail with `HashError::DuplicateEntry`
+    Error,
+    /// Keep the last entry, in place of the first one
+    TakeLast,
+    /// Keep the first entry
+    TakeFirst,
+}
+
+/// Which entry is kept when merged hash lists have the same file, see `merge_hash_lists`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MergePolicy {
+    /// Keep the entry of the last hash list
+    #[default]
+    Last,
+    /// Keep the entry of the first hash list
+    First,
+}
+
+/// Parsed hash list
+#[derive(Debug, Clone, Default)]
+pub struct HashList {
+    /// Algorithm of the first valid line, from the `algorithm` argument, the header, the BSD tag
+    /// or the digest length
+    pub algorithm: HashAlgorithm,
+    /// Format of the first valid line
+    pub format: HashListFormat,
+    /// Valid lines, in order
+    pub pairs: Vec<HashPair>,
+    /// Malformed lines and duplicate entries, in order
+    pub warnings: Vec<ParseWarning>,
+    /// Number of `#` and SFV `;` comment lines, not counting the algorithm header
+    pub comment_num: usize,
+}
+
+impl HashList {
+    /// Lines that couldn't be parsed and were skipped
+    pub fn malformed_num(&self) -> usize {
+        self.warnings
+            .iter()
+            .filter(|x| matches!(x, ParseWarning::Malformed { .. }))
+            .count()
+    }
+
+    /// Lines of the entries in the format of the hash list, without line breaks
+    pub fn lines(&self) -> impl Iterator<Item = String> + '_ {
+        self.pairs.iter().map(|x| {
+            self.format
+                .entry(x.algorithm, &x.file_path, &x.expected_hash)
+        })
+    }
+}
+
+/// Progress bars of the CLI in parallel mode: the overall progress on top, and a bar per hashing
+/// thread with the file it's on
+#[derive(Debug)]
+pub struct ThreadProgress {
+    multi: MultiProgress,
+    overall: ProgressBar,
+    /// Bytes read so far by every thread, see `StatusWrapper::ProgressBar`
+    bytes_read: Arc<AtomicU64>,
+    /// By rayon thread index, added when a thread starts its first file
+    threads: Mutex<HashMap<usize, ProgressBar>>,
+}
+
+impl ThreadProgress {
+    /// `overall` shouldn't be hidden, as it's drawn by the `MultiProgress` from now on
+    pub fn new(overall: ProgressBar, bytes_read: Arc<AtomicU64>) -> Self {
+        let multi = MultiProgress::new();
+        let overall = multi.add(overall);
+        Self {
+            multi,
+            overall,
+            bytes_read,
+            threads: Default::default(),
+        }
+    }
+
+    /// Bar of the current thread, reset for a file of `len` bytes
+    fn start_file(&self, file_path: &Path, len: u64) -> ProgressBar {
+        let idx = rayon::current_thread_index().unwrap_or(0);
+        let bar = self
+            .threads
+            .lock()
+            .entry(idx)
+            .or_insert_with(|| {
+                let style = ProgressStyle::with_template("  {wide_msg} {bytes}/{total_bytes}")
+                    .expect("Valid template");
+                self.multi.add(ProgressBar::new(0).with_style(style))
+            })
+            .clone();
+        bar.reset();
+        bar.set_length(len);
+        bar.set_message(file_path.display().to_string());
+        bar
+    }
+
+    /// Remove the thread bars, leaving the overall one
+    pub fn finish(&self) {
+        for (_, bar) in self.threads.lock().drain() {
+            bar.finish_and_clear();
+        }
+    }
 }
 
+/// Where progress is reported
 #[derive(Debug, Clone)]
 pub enum StatusWrapper {
+    /// Status shared with the TUI
     Status(Arc<RwLock<Status>>),
-    ProgressBar(ProgressBar),
+    /// Progress bar of the CLI, with the bytes read so far for its throughput
+    ProgressBar(ProgressBar, Arc<AtomicU64>),
+    /// Progress bars of the CLI with a bar per thread
+    ThreadProgress(Arc<ThreadProgress>),
 }
 
 impl StatusWrapper {
+    /// `total_bytes_processed` and `current_file_bytes` of the TUI status
+    fn byte_counters(&self) -> Option<(Arc<AtomicU64>, Arc<AtomicU64>)> {
+        match self {
+            StatusWrapper::Status(status) => {
+                let status = status.read();
+                Some((
+                    Arc::clone(&status.total_bytes_processed),
+                    Arc::clone(&status.current_file_bytes),
+                ))
+            }
+            StatusWrapper::ProgressBar(..) | StatusWrapper::ThreadProgress(_) => None,
+        }
+    }
+
+    /// Bytes read so far of the CLI progress bars
+    fn bytes_read(&self) -> Option<Arc<AtomicU64>> {
+        match self {
+            StatusWrapper::Status(_) => None,
+            StatusWrapper::ProgressBar(_, bytes_read) => Some(Arc::clone(bytes_read)),
+            StatusWrapper::ThreadProgress(progress) => Some(Arc::clone(&progress.bytes_read)),
+        }
+    }
+
+    /// Bar showing the bytes read of `file_path`, with `StatusWrapper::ThreadProgress`
+    fn file_bar(&self, file_path: &Path, len: u64) -> Option<ProgressBar> {
+        match self {
+            StatusWrapper::ThreadProgress(progress) => Some(progress.start_file(file_path, len)),
+            StatusWrapper::Status(_) | StatusWrapper::ProgressBar(..) => None,
+        }
+    }
+
     fn set_text(&self, filename: String, file_hash: String, expected_hash: String) {
         match self {
             StatusWrapper::Status(status) => {
@@ -34,7 +744,7 @@ impl StatusWrapper {
                 status.file_hash = file_hash;
                 status.expected_hash = expected_hash;
             }
-            StatusWrapper::ProgressBar(_) => {}
+            StatusWrapper::ProgressBar(..) | StatusWrapper::ThreadProgress(_) => {}
         }
     }
 
@@ -44,9 +754,12 @@ impl StatusWrapper {
                 let mut status = status.write();
                 status.correct_num += 1;
             }
-            StatusWrapper::ProgressBar(progress) => {
+            StatusWrapper::ProgressBar(progress, _) => {
                 progress.inc(1);
             }
+            StatusWrapper::ThreadProgress(progress) => {
+                progress.overall.inc(1);
+            }
         }
     }
 
@@ -56,9 +769,74 @@ impl StatusWrapper {
                 let mut status = status.write();
                 status.incorrect_num += 1;
             }
-            StatusWrapper::ProgressBar(progress) => {
+            StatusWrapper::ProgressBar(progress, _) => {
+                progress.inc(1);
+            }
+            StatusWrapper::ThreadProgress(progress) => {
+                progress.overall.inc(1);
+            }
+        }
+    }
+
+    fn inc_missing(&self) {
+        match self {
+            StatusWrapper::Status(status) => {
+                let mut status = status.write();
+                status.missing_num += 1;
+            }
+            StatusWrapper::ProgressBar(progress, _) => {
+                progress.inc(1);
+            }
+            StatusWrapper::ThreadProgress(progress) => {
+                progress.overall.inc(1);
+            }
+        }
+    }
+
+    /// Count a result saved by an earlier run, see `Setting::resume`
+    fn inc_result(&self, result: CheckResult) {
+        match result {
+            CheckResult::Correct => self.inc_correct(),
+            CheckResult::Incorrect => self.inc_incorrect(),
+            CheckResult::Missing => self.inc_missing(),
+            CheckResult::Error => self.inc_error(),
+        }
+    }
+
+    /// Extra files aren't part of the progress bar length
+    fn inc_extra(&self) {
+        if let StatusWrapper::Status(status) = self {
+            status.write().extra_num += 1;
+        }
+    }
+
+    fn inc_skipped(&self) {
+        match self {
+            StatusWrapper::Status(status) => {
+                let mut status = status.write();
+                status.skipped_num += 1;
+            }
+            StatusWrapper::ProgressBar(progress, _) => {
                 progress.inc(1);
             }
+            StatusWrapper::ThreadProgress(progress) => {
+                progress.overall.inc(1);
+            }
+        }
+    }
+
+    /// Start the progress bar over for a single file
+    fn restart(&self) {
+        match self {
+            StatusWrapper::Status(_) => {}
+            StatusWrapper::ProgressBar(progress, _) => {
+                progress.reset();
+                progress.set_length(1);
+            }
+            StatusWrapper::ThreadProgress(progress) => {
+                progress.overall.reset();
+                progress.overall.set_length(1);
+            }
         }
     }
 
@@ -68,106 +846,1664 @@ impl StatusWrapper {
                 let mut status = status.write();
                 status.error_num += 1;
             }
-            StatusWrapper::ProgressBar(progress) => {
+            StatusWrapper::ProgressBar(progress, _) => {
                 progress.inc(1);
             }
+            StatusWrapper::ThreadProgress(progress) => {
+                progress.overall.inc(1);
+            }
         }
     }
 }
 
-pub fn hash_list_parser(file_path: &Path) -> Result<Vec<HashPair>> {
-    let content = std::fs::read_to_string(file_path)?;
-    let re = Regex::new(r"(?m)^[0-9a-z]{32}")?;
+/// Decode a hash list as UTF-8 without a BOM, or as Windows-1252 if it isn't valid UTF-8. Returns
+/// whether Windows-1252 was used
+fn decode_hash_list(bytes: &[u8]) -> (Cow<'_, str>, bool) {
+    let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(x) => (Cow::Borrowed(x), false),
+        Err(_) => (WINDOWS_1252.decode_without_bom_handling(bytes).0, true),
+    }
+}
 
-    let pair: Vec<_> = content
-        .lines()
-        .filter_map(|s| {
-            if !re.is_match(s) {
-                return None;
-            }
-
-            let (hash, file) = s.split_once(" ")?;
-            Some(HashPair {
-                file_path: file
-                    .strip_prefix(['*', ' '])
-                    .expect("file should always be prefixed")
-                    .to_string(),
-                expected_hash: hash.to_string(),
-            })
-        })
-        .collect();
+/// Parse the hash list at `file_path` with `parse_hash_list`. A `file_path` of `-` reads from
+/// stdin. Gzip and zstd compressed hash lists are decompressed on the fly, see `Compression`
+pub fn hash_list_parser(
+    file_path: &Path,
+    algorithm: Option<HashAlgorithm>,
+    duplicate_policy: DuplicatePolicy,
+    strict: bool,
+) -> Result<HashList, HashError> {
+    let read_error = |e| HashError::from_read(file_path, e);
+    let mut reader: Box<dyn BufRead> = if file_path == Path::new(STDIN_PATH) {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(file_path).map_err(read_error)?))
+    };
 
-    if pair.is_empty() {
-        return Err(eyre!("Empty hash list"));
-    }
+    let compression = match Compression::from_extension(file_path) {
+        Some(x) => Some(x),
+        None => Compression::from_magic(reader.fill_buf().map_err(read_error)?),
+    };
+    let reader = match compression {
+        Some(x) => x.decoder(reader).map_err(read_error)?,
+        None => reader,
+    };
 
-    Ok(pair)
+    parse_hash_list(reader, algorithm, duplicate_policy, strict).map_err(|e| match e {
+        HashError::ReadError { source, .. } => read_error(source),
+        e => e,
+    })
 }
 
-fn hashing_file(hash_pair: &HashPair, block_size: usize) -> Result<String> {
-    let mut file = File::open(&hash_pair.file_path)?;
-    let mut buffer = vec![0u8; block_size];
+/// Parse a hash list in GNU (`<hash>  <file>`), BSD (`MD5 (<file>) = <hash>`) or SFV
+/// (`<file> <crc32>`) format, or a JSON manifest, with the `HashListReader` for the format of the
+/// first line. Lines in another line format are still read. If `algorithm` is `None`, it is
+/// detected from the BSD tag or the digest length of each line, unless the hash list has an
+/// `# Algorithm: <name>` header. Files listed more than once are handled according to
+/// `duplicate_policy`. Lines that can't be parsed are skipped with a warning, or fail with
+/// `HashError::MalformedHashList` if `strict` is set. Absolute paths are used as-is, relative ones
+/// are from the current directory. Path separators are converted to the ones of this platform.
+/// Read errors have an empty path
+pub fn parse_hash_list(
+    mut reader: impl Read,
+    algorithm: Option<HashAlgorithm>,
+    duplicate_policy: DuplicatePolicy,
+    strict: bool,
+) -> Result<HashList, HashError> {
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .map_err(|source| HashError::ReadError {
+            path: PathBuf::new(),
+            source,
+        })?;
+    let (content, encoding_fallback) = decode_hash_list(&bytes);
+    let mut reader =
+        <dyn HashListReader>::auto_detect(content.as_bytes(), algorithm).map_err(|source| {
+            HashError::ReadError {
+                path: PathBuf::new(),
+                source,
+            }
+        })?;
+
+    let mut hash_list = HashList::default();
+    if encoding_fallback {
+        hash_list.warnings.push(ParseWarning::EncodingFallback);
+    }
+    // Index in `hash_list.pairs` of each file
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for entry in reader.entries() {
+        let pair = match entry {
+            Ok(x) => x,
+            Err(ParseError::Malformed { line, content }) if !strict => {
+                hash_list
+                    .warnings
+                    .push(ParseWarning::Malformed { line, content });
+                continue;
+            }
+            Err(
+                ParseError::Malformed { line, content } | ParseError::Invalid { line, content },
+            ) => return Err(HashError::MalformedHashList { line, content }),
+        };
+        if seen.is_empty() {
+            hash_list.format = pair.format;
+        }
 
-    let mut hasher = Md5::new();
-    while let Ok(bytes_read) = file.read(&mut buffer) {
-        if bytes_read == 0 {
-            break;
+        let Some(&first_idx) = seen.get(&pair.file_path) else {
+            seen.insert(pair.file_path.clone(), hash_list.pairs.len());
+            hash_list.pairs.push(pair);
+            continue;
+        };
+        match duplicate_policy {
+            DuplicatePolicy::Warn => {
+                hash_list.warnings.push(ParseWarning::Duplicate {
+                    line: pair.line,
+                    file_path: pair.file_path.clone(),
+                });
+                hash_list.pairs.push(pair);
+            }
+            DuplicatePolicy::Error => {
+                return Err(HashError::DuplicateEntry {
+                    line: pair.line,
+                    file_path: pair.file_path,
+                })
+            }
+            DuplicatePolicy::TakeLast => hash_list.pairs[first_idx] = pair,
+            DuplicatePolicy::TakeFirst => {}
         }
+    }
+    hash_list.comment_num = reader.comment_num();
 
-        hasher.update(&buffer[..bytes_read]);
+    let Some(first_pair) = hash_list.pairs.first() else {
+        return Err(HashError::EmptyHashList);
+    };
+    hash_list.algorithm = first_pair.algorithm;
+
+    let absolute_num = hash_list
+        .pairs
+        .iter()
+        .filter(|x| Path::new(&x.file_path).is_absolute())
+        .count();
+    if absolute_num > 0 && absolute_num < hash_list.pairs.len() {
+        hash_list.warnings.push(ParseWarning::MixedPathTypes);
     }
 
-    Ok(hex::encode(hasher.finalize()))
+    Ok(hash_list)
 }
 
-fn hash_checker(
-    hash_pair: HashPair,
+/// Files in `dir` named like one of `DEFAULT_HASH_LIST_NAMES`, in that order. Names are compared
+/// exactly, so a file is only found once on case-insensitive filesystems
+pub fn find_hash_lists(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut found: Vec<(usize, PathBuf)> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let priority = DEFAULT_HASH_LIST_NAMES.iter().position(|x| name == **x);
+        if let (Some(priority), true) = (priority, entry.path().is_file()) {
+            found.push((priority, entry.path()));
+        }
+    }
+    found.sort();
+    Ok(found.into_iter().map(|x| x.1).collect())
+}
+
+/// The hash list in `dir` if exactly one of `DEFAULT_HASH_LIST_NAMES` is there
+pub fn auto_find_hash_list(dir: &Path) -> Option<PathBuf> {
+    match find_hash_lists(dir).ok()?.as_slice() {
+        [x] => Some(x.clone()),
+        _ => None,
+    }
+}
+
+/// Hex digest of the file at `file_path`, updating the byte counters of `status` while reading.
+/// Symlinks are handled according to `Setting::follow_symlinks` and
+/// `Setting::hash_symlink_target`
+pub fn hash_file(
+    file_path: &Path,
+    algorithm: HashAlgorithm,
     setting: &Setting,
-    status: StatusWrapper,
-    tx: Sender<Message>,
-) {
-    let res = hashing_file(&hash_pair, setting.block_size);
-    let file_hash = match res {
-        Ok(x) => x,
-        Err(e) => {
-            let _ = tx.send(Message::Error(e));
-            status.inc_error();
-            return;
+    status: &StatusWrapper,
+) -> Result<String, HashError> {
+    let read_error = |e| HashError::from_read(file_path, e);
+    if !setting.follow_symlinks || setting.hash_symlink_target {
+        let metadata = fs::symlink_metadata(file_path).map_err(read_error)?;
+        if metadata.is_symlink() && setting.hash_symlink_target {
+            let target = fs::read_link(file_path).map_err(read_error)?;
+            let mut hasher = algorithm.hasher();
+            hasher.update(target.as_os_str().as_encoded_bytes());
+            return Ok(hasher.finalize());
+        } else if metadata.is_symlink() {
+            return Err(HashError::IsSymlink {
+                path: file_path.to_path_buf(),
+            });
+        }
+    }
+    let mut file = File::open(file_path).map_err(read_error)?;
+    let len = file.metadata().map_err(read_error)?.len();
+
+    let byte_counters = status.byte_counters();
+    if let Some((_, current_file_bytes)) = &byte_counters {
+        current_file_bytes.store(0, Ordering::Relaxed);
+    }
+    let file_bar = status.file_bar(file_path, len);
+    let bytes_read = status.bytes_read();
+    let mut hasher = algorithm.hasher();
+    let mut block_start = Instant::now();
+    let mut update = |data: &[u8]| {
+        tracing::debug!(bytes = data.len(), "Read block");
+        BYTES_READ.set(BYTES_READ.get() + data.len() as u64);
+        hasher.update(data);
+        throttle(setting, data.len(), &mut block_start);
+        duty_cycle(setting);
+        if let Some(file_bar) = &file_bar {
+            file_bar.inc(data.len() as u64);
+        }
+        if let Some(bytes_read) = &bytes_read {
+            bytes_read.fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+        if let Some((total_bytes, current_file_bytes)) = &byte_counters {
+            total_bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+            current_file_bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
         }
     };
 
-    if hash_pair.expected_hash != file_hash {
-        let _ = tx.send(Message::Incorrect(hash_pair.file_path.clone()));
-        status.inc_incorrect();
+    // Empty files can't be mapped
+    if len > 0 && (setting.mmap || len >= setting.mmap_threshold) {
+        // SAFETY: The file could be modified while mapped, which gives a wrong hash but is the
+        // same as modifying it while reading
+        let mmap = unsafe { Mmap::map(&file) }.map_err(read_error)?;
+        // Still in blocks so the TUI throughput is updated while hashing
+        mmap.chunks(setting.block_size.max(1)).for_each(update);
     } else {
-        status.inc_correct();
+        let mut buffer = vec![0u8; setting.block_size];
+        loop {
+            match file.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(x) => update(&buffer[..x]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(read_error(e)),
+            };
+        }
     }
 
-    status.set_text(hash_pair.file_path, file_hash, hash_pair.expected_hash);
+    Ok(hasher.finalize())
 }
 
-pub fn prepare_hashing(
-    mut hash_list: Vec<HashPair>,
-    setting: &Setting,
-    status: StatusWrapper,
-    tx: Sender<Message>,
-) {
-    let start_time = Instant::now();
-    if setting.sort {
-        hash_list.sort();
-    }
+/// Sleeps for as long as reading `bytes` should take at `Setting::rate_limit`. In sequential
+/// mode, that's what the block took beyond the time since `block_start`. In parallel mode, the
+/// threads take their bytes from `TOKEN_BUCKET` instead
+fn throttle(setting: &Setting, bytes: usize, block_start: &mut Instant) {
+    let Some(rate_limit) = setting.rate_limit else {
+        return;
+    };
+    let rate = rate_limit * BYTES_PER_MB;
 
-    if setting.parallel {
-        hash_list.into_par_iter().for_each(|x| {
-            let tx_clone = tx.clone();
-            hash_checker(x, setting, status.clone(), tx_clone)
-        });
+    let wait = if setting.parallel {
+        TOKEN_BUCKET
+            .lock()
+            .get_or_insert_with(|| TokenBucket::new(rate))
+            .take(rate, bytes as f64)
     } else {
-        hash_list.into_iter().for_each(|x| {
-            let tx_clone = tx.clone();
-            hash_checker(x, setting, status.clone(), tx_clone)
-        });
+        Duration::from_secs_f64(bytes as f64 / rate).saturating_sub(block_start.elapsed())
+    };
+    // Outside the lock, so other threads can take their share meanwhile
+    thread::sleep(wait);
+    *block_start = Instant::now();
+}
+
+/// Sleeps once the thread has run for `Setting::cpu_limit` of `CPU_LIMIT_PERIOD`, for the rest of
+/// the period. Only checked between blocks, and time spent waiting for the disk counts as
+/// running, so this is a best-effort limit: a large `Setting::block_size` or a slow disk lets a
+/// thread use less or more than its share
+fn duty_cycle(setting: &Setting) {
+    let Some(cpu_limit) = setting.cpu_limit else {
+        return;
+    };
+
+    let running = PERIOD_START.get().elapsed();
+    if running >= CPU_LIMIT_PERIOD.mul_f32(cpu_limit) {
+        // So that `running` is `cpu_limit` of the time, even if the block overshot the period
+        thread::sleep(running.mul_f32((1.0 - cpu_limit) / cpu_limit));
+        PERIOD_START.set(Instant::now());
+    }
+}
+
+/// Bytes that can be read right away, refilled at a fixed rate
+#[derive(Debug)]
+struct TokenBucket {
+    /// Bytes per second
+    rate: f64,
+    /// Negative when threads are waiting for their bytes
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            tokens: 0.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take `bytes`, returning how long to wait until they are available. At most a second of
+    /// bytes is saved up, so idle time doesn't allow a burst over the limit
+    fn take(&mut self, rate: f64, bytes: f64) -> Duration {
+        // The limit changed since the last run
+        if rate != self.rate {
+            *self = Self::new(rate);
+        }
+        let now = Instant::now();
+        let refill = now.duration_since(self.last_refill).as_secs_f64() * rate;
+        self.tokens = (self.tokens + refill).min(rate);
+        self.last_refill = now;
+
+        self.tokens -= bytes;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
     }
+}
+
+/// Lets the TUI pause hashing. Hashing threads wait before starting a file while paused
+#[derive(Debug, Default)]
+pub struct Pause {
+    paused: AtomicBool,
+    /// Start of the current pause, and the total duration of previous pauses
+    time: Mutex<(Option<Instant>, Duration)>,
+}
+
+impl Pause {
+    /// Pause or resume
+    pub fn toggle(&self) {
+        let mut time = self.time.lock();
+        match time.0.take() {
+            Some(since) => time.1 += since.elapsed(),
+            None => time.0 = Some(Instant::now()),
+        }
+        self.paused.store(time.0.is_some(), Ordering::Relaxed);
+    }
+
+    /// Whether hashing is currently paused
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Total time spent paused, including the current pause
+    pub fn paused_duration(&self) -> Duration {
+        let time = self.time.lock();
+        time.1 + time.0.map(|x| x.elapsed()).unwrap_or_default()
+    }
+
+    fn wait(&self) {
+        while self.is_paused() {
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CheckResult {
+    Correct,
+    Incorrect,
+    Missing,
+    Error,
+}
+
+/// Hash the file of `hash_pair`, report the result and write it to `Setting::audit_log`. The
+/// timing is pushed to `file_stats` if it's set and the file could be hashed
+#[tracing::instrument(skip_all, fields(file = %hash_pair.file_path))]
+fn hash_checker(
+    hash_pair: HashPair,
+    setting: &Setting,
+    status: StatusWrapper,
+    tx: Sender<Message>,
+    cache: Option<&IncrementalCache>,
+    inode_cache: Option<&InodeCache>,
+    file_stats: Option<&Mutex<Vec<FileStats>>>,
+) -> CheckResult {
+    if setting.audit_log.is_none() && file_stats.is_none() {
+        return check_pair(hash_pair, setting, status, &tx, cache, inode_cache).0;
+    }
+
+    let start = Instant::now();
+    BYTES_READ.set(0);
+    let (file_path, expected_hash) = (hash_pair.file_path.clone(), hash_pair.expected_hash.clone());
+    let (result, file_hash) = check_pair(hash_pair, setting, status, &tx, cache, inode_cache);
+    let duration = start.elapsed();
+
+    if let (Some(file_stats), Some(_)) = (file_stats, &file_hash) {
+        file_stats.lock().push(FileStats {
+            path: file_path.clone(),
+            bytes: BYTES_READ.get(),
+            duration,
+        });
+    }
+
+    if let Some(audit_log) = &setting.audit_log {
+        let audit_result = match result {
+            CheckResult::Correct => "ok",
+            CheckResult::Incorrect => "fail",
+            CheckResult::Missing | CheckResult::Error => "error",
+        };
+        let entry = AuditEntry::new(
+            &file_path,
+            &expected_hash,
+            file_hash.as_deref().unwrap_or_default(),
+            audit_result,
+            duration,
+        );
+        if let Err(e) = append_audit_entry(audit_log, setting.audit_format, &entry) {
+            tracing::error!(?audit_log, %e, "Couldn't write to the audit log");
+            let _ = tx.send(Message::Warning(format!(
+                "Couldn't write to audit log {audit_log:?}: {e}"
+            )));
+        }
+    }
+    result
+}
+
+/// Hash the file of `hash_pair` and report the result. Also returns the hash of the file, if it
+/// could be hashed
+fn check_pair(
+    hash_pair: HashPair,
+    setting: &Setting,
+    status: StatusWrapper,
+    tx: &Sender<Message>,
+    cache: Option<&IncrementalCache>,
+    inode_cache: Option<&InodeCache>,
+) -> (CheckResult, Option<String>) {
+    if is_empty_file_hash(&hash_pair, setting) {
+        tracing::error!(error = %HashError::EmptyFileHash);
+        let _ = tx.send(Message::Error {
+            file_path: hash_pair.file_path,
+            error: HashError::EmptyFileHash,
+        });
+        status.inc_error();
+        return (CheckResult::Error, None);
+    }
+
+    let corrected = case_corrected_path(&hash_pair.file_path, setting);
+    if let Some(found) = &corrected {
+        let _ = tx.send(Message::CaseCorrected {
+            listed: hash_pair.file_path.clone(),
+            found: found.to_string_lossy().into_owned(),
+        });
+    }
+    let file_path = corrected
+        .as_deref()
+        .unwrap_or(Path::new(&hash_pair.file_path));
+    // Unchanged files are reported with their last hash
+    let res = match cache.and_then(|x| x.lookup(&hash_pair.file_path, hash_pair.algorithm)) {
+        Some(x) => Ok(x),
+        None => {
+            // Hard links to an already hashed file have the same content
+            let res = match inode_cache
+                .and_then(|x| x.lookup(&hash_pair.file_path, hash_pair.algorithm))
+            {
+                Some(x) => Ok(x),
+                None => {
+                    let res = hash_file_with_retries(&hash_pair, file_path, setting, &status, tx);
+                    if let (Some(inode_cache), Ok(hash)) = (inode_cache, &res) {
+                        inode_cache.insert(&hash_pair.file_path, hash_pair.algorithm, hash);
+                    }
+                    res
+                }
+            };
+            if let Some(cache) = cache {
+                cache.update(
+                    &hash_pair.file_path,
+                    hash_pair.algorithm,
+                    res.as_deref().ok(),
+                );
+            }
+            res
+        }
+    };
+    let file_hash = match res {
+        Ok(x) => x,
+        Err(HashError::FileNotFound { .. }) => {
+            tracing::error!("File is missing");
+            let _ = tx.send(Message::Missing(hash_pair.file_path));
+            status.inc_missing();
+            return (CheckResult::Missing, None);
+        }
+        Err(e) => {
+            tracing::error!(error = %e);
+            let _ = tx.send(Message::Error {
+                file_path: hash_pair.file_path,
+                error: e,
+            });
+            status.inc_error();
+            return (CheckResult::Error, None);
+        }
+    };
+
+    let result = if !setting.hashes_match(&hash_pair.expected_hash, &file_hash) {
+        let _ = tx.send(Message::Incorrect {
+            file_path: hash_pair.file_path.clone(),
+            expected_hash: hash_pair.expected_hash.clone(),
+            file_hash: file_hash.clone(),
+        });
+        status.inc_incorrect();
+        CheckResult::Incorrect
+    } else {
+        if setting.verbosity == Verbosity::Verbose {
+            let _ = tx.send(Message::Correct {
+                file_path: hash_pair.file_path.clone(),
+                expected_hash: hash_pair.expected_hash.clone(),
+                file_hash: file_hash.clone(),
+            });
+        }
+        status.inc_correct();
+        CheckResult::Correct
+    };
+
+    status.set_text(
+        hash_pair.file_path,
+        file_hash.clone(),
+        hash_pair.expected_hash,
+    );
+    (result, Some(file_hash))
+}
+
+/// `hash_file` of `file_path`, which is where the file of `hash_pair` was found, retried up to
+/// `Setting::retries` times on transient errors
+fn hash_file_with_retries(
+    hash_pair: &HashPair,
+    file_path: &Path,
+    setting: &Setting,
+    status: &StatusWrapper,
+    tx: &Sender<Message>,
+) -> Result<String, HashError> {
+    let mut attempt = 0;
+    loop {
+        match hash_file(file_path, hash_pair.algorithm, setting, status) {
+            Err(e) if e.is_transient() && attempt < setting.retries => {
+                attempt += 1;
+                let _ = tx.send(Message::Retrying(hash_pair.file_path.clone(), attempt));
+                thread::sleep(RETRY_BASE_DELAY * 2u32.saturating_pow(attempt - 1));
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Verify a single pair without reporting progress, for `HashVerifier`
+pub(crate) fn verify_pair(hash_pair: HashPair, setting: &Setting) -> VerificationResult {
+    let status = StatusWrapper::ProgressBar(ProgressBar::hidden(), Default::default());
+    let corrected = case_corrected_path(&hash_pair.file_path, setting);
+    let file_path = corrected
+        .as_deref()
+        .unwrap_or(Path::new(&hash_pair.file_path));
+    let res = if is_empty_file_hash(&hash_pair, setting) {
+        Err(HashError::EmptyFileHash)
+    } else {
+        hash_file(file_path, hash_pair.algorithm, setting, &status)
+    };
+    let (actual, status) = match res {
+        Ok(x) if setting.hashes_match(&hash_pair.expected_hash, &x) => {
+            (Some(x), VerificationStatus::Correct)
+        }
+        Ok(x) => (Some(x), VerificationStatus::Incorrect),
+        Err(HashError::FileNotFound { .. }) => (None, VerificationStatus::Missing),
+        Err(e) => (None, VerificationStatus::Error(e)),
+    };
+
+    VerificationResult {
+        path: hash_pair.file_path,
+        expected: hash_pair.expected_hash,
+        actual,
+        status,
+    }
+}
+
+/// Thread pool with `setting.threads` threads, or `None` to use the global pool if not set. A new
+/// pool is built on every call since the global pool can only be configured once
+fn thread_pool(setting: &Setting) -> Result<Option<ThreadPool>, ThreadPoolBuildError> {
+    setting
+        .threads
+        .map(|n| ThreadPoolBuilder::new().num_threads(n).build())
+        .transpose()
+}
+
+/// Run `task` on every item, in parallel if set. With `setting.fail_fast`, the remaining tasks are
+/// skipped after the first failure. If the thread pool of `setting.threads` can't be built, the
+/// items are run one at a time with a warning sent to `tx`, rather than on every CPU. Returns the
+/// results of tasks that ran and whether it was aborted
+fn run_tasks<T, F>(
+    items: Vec<T>,
+    setting: &Setting,
+    tx: &Sender<Message>,
+    pause: &Pause,
+    cancelled: &AtomicBool,
+    task: F,
+) -> (Vec<CheckResult>, bool)
+where
+    T: Send,
+    F: Fn(T) -> CheckResult + Sync + Send,
+{
+    let aborted = AtomicBool::new(false);
+    let checked_task = |x| {
+        pause.wait();
+        if aborted.load(Ordering::Relaxed) || cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let result = task(x);
+        let ignored = result == CheckResult::Missing && setting.ignore_missing;
+        if setting.fail_fast && result != CheckResult::Correct && !ignored {
+            aborted.store(true, Ordering::Relaxed);
+        }
+        Some(result)
+    };
+
+    let pool = match setting.parallel.then(|| thread_pool(setting)).transpose() {
+        Ok(x) => x,
+        Err(e) => {
+            tracing::error!(%e, "Couldn't build the thread pool");
+            let _ = tx.send(Message::Warning(format!(
+                "Couldn't start {} threads, hashing one file at a time: {e}",
+                setting.threads.unwrap_or_default()
+            )));
+            None
+        }
+    };
+    let results = match pool {
+        Some(Some(pool)) => {
+            pool.install(|| items.into_par_iter().filter_map(checked_task).collect())
+        }
+        Some(None) => items.into_par_iter().filter_map(checked_task).collect(),
+        None => items.into_iter().map_while(checked_task).collect(),
+    };
+
+    (results, aborted.load(Ordering::Relaxed))
+}
+
+/// `setting` with the `Setting::threads` picked by `Setting::parallel_auto` for the files in
+/// `dir`, or `None` if it's unchanged
+fn auto_threads_setting(setting: &Setting, dir: &Path) -> Option<Setting> {
+    let auto_threads = setting.auto_threads(dir)?;
+    Some(Setting {
+        threads: Some(auto_threads.threads),
+        ..setting.clone()
+    })
+}
+
+/// Selected files of `hash_list` that don't exist, checked without hashing anything so they
+/// can be reported before a long run
+pub fn find_missing_files(hash_list: &[HashPair], setting: &Setting) -> Vec<String> {
+    hash_list
+        .iter()
+        .filter(|x| {
+            setting.is_selected(&x.file_path)
+                && !Path::new(&x.file_path).exists()
+                && case_corrected_path(&x.file_path, setting).is_none()
+        })
+        .map(|x| x.file_path.clone())
+        .collect()
+}
+
+/// What `dry_run_hash_list` found out about the files of a hash list, without hashing them
+#[derive(Debug, Default)]
+pub struct DryRunReport {
+    /// Files that exist and can be read
+    pub verifiable_num: usize,
+    /// Sum of the sizes of the verifiable files
+    pub total_bytes: u64,
+    /// Paths as written in the hash list
+    pub missing: Vec<String>,
+    /// Paths as written in the hash list of the files that exist but can't be read, with why
+    pub unreadable: Vec<(String, HashError)>,
+}
+
+/// Check that the selected files of `hash_list` exist and can be opened, without hashing
+/// anything, to debug a hash list before a long run. See `Setting::dry_run`
+pub fn dry_run_hash_list(hash_list: &[HashPair], setting: &Setting) -> DryRunReport {
+    let mut report = DryRunReport::default();
+    for pair in hash_list {
+        if !setting.is_selected(&pair.file_path) || is_ignored_empty_file(pair, setting) {
+            continue;
+        }
+        let corrected = case_corrected_path(&pair.file_path, setting);
+        let file_path = corrected.as_deref().unwrap_or(Path::new(&pair.file_path));
+        match readable_len(file_path, setting) {
+            Ok(len) => {
+                report.verifiable_num += 1;
+                report.total_bytes += len;
+            }
+            Err(HashError::FileNotFound { .. }) => report.missing.push(pair.file_path.clone()),
+            Err(e) => report.unreadable.push((pair.file_path.clone(), e)),
+        }
+    }
+    report
+}
+
+/// Bytes `hash_file` would read from `file_path`, if it can open it
+fn readable_len(file_path: &Path, setting: &Setting) -> Result<u64, HashError> {
+    let read_error = |e| HashError::from_read(file_path, e);
+    if !setting.follow_symlinks || setting.hash_symlink_target {
+        let metadata = fs::symlink_metadata(file_path).map_err(read_error)?;
+        // Only the target path is hashed
+        if metadata.is_symlink() && setting.hash_symlink_target {
+            return Ok(0);
+        } else if metadata.is_symlink() {
+            return Err(HashError::IsSymlink {
+                path: file_path.to_path_buf(),
+            });
+        }
+    }
+    let file = File::open(file_path).map_err(read_error)?;
+    let metadata = file.metadata().map_err(read_error)?;
+    if metadata.is_dir() {
+        return Err(read_error(ErrorKind::IsADirectory.into()));
+    }
+    Ok(metadata.len())
+}
+
+/// The file whose path matches `file_path` ignoring case, for hash lists made on case-insensitive
+/// filesystems. Only the directories matching a component of `file_path` are walked. If several
+/// files match, the one with the fewest characters in another case is returned
+pub fn find_case_insensitive(file_path: &Path) -> Option<PathBuf> {
+    // The root and leading `.` or `..` are kept as is
+    let mut base = PathBuf::new();
+    let mut names = vec![];
+    for component in file_path.components() {
+        match component {
+            Component::Normal(x) => names.push(x.to_string_lossy().into_owned()),
+            _ if names.is_empty() => base.push(component),
+            _ => return None,
+        }
+    }
+    if names.is_empty() {
+        return None;
+    }
+    let relative = base.as_os_str().is_empty();
+    if relative {
+        base.push(".");
+    }
+    let folded = names.iter().map(|x| x.to_lowercase()).collect_vec();
+
+    // Characters that differ from `file_path`, the closest match has the fewest
+    let distance = |path: &Path| -> usize {
+        let found = path.strip_prefix(&base).unwrap_or(path).components();
+        found
+            .zip(&names)
+            .map(|(found, listed)| {
+                let found = found.as_os_str().to_string_lossy();
+                found
+                    .chars()
+                    .zip(listed.chars())
+                    .filter(|(a, b)| a != b)
+                    .count()
+            })
+            .sum()
+    };
+
+    let found = WalkDir::new(&base)
+        .min_depth(names.len())
+        .max_depth(names.len())
+        .into_iter()
+        .filter_entry(|x| {
+            x.depth() == 0
+                || x.file_name().to_string_lossy().to_lowercase() == folded[x.depth() - 1]
+        })
+        .filter_map(|x| x.ok())
+        .filter(|x| x.path().is_file())
+        .map(|x| x.into_path())
+        .min_by_key(|x| distance(x))?;
+
+    if relative {
+        Some(found.strip_prefix(&base).unwrap_or(&found).to_path_buf())
+    } else {
+        Some(found)
+    }
+}
+
+/// Whether the file of `hash_pair` is empty and skipped with `Setting::ignore_zero`
+fn is_ignored_empty_file(hash_pair: &HashPair, setting: &Setting) -> bool {
+    if !setting.ignore_zero {
+        return false;
+    }
+    let file_path = case_corrected_path(&hash_pair.file_path, setting)
+        .unwrap_or_else(|| PathBuf::from(&hash_pair.file_path));
+    fs::metadata(file_path).is_ok_and(|x| x.is_file() && x.len() == 0)
+}
+
+/// With `Setting::strict_zero`, whether the expected hash of `hash_pair` is the hash of an empty
+/// input
+fn is_empty_file_hash(hash_pair: &HashPair, setting: &Setting) -> bool {
+    setting.strict_zero
+        && hash_pair
+            .expected_hash
+            .eq_ignore_ascii_case(&hash_pair.algorithm.empty_hash())
+}
+
+/// The file found by `find_case_insensitive` when `file_path` doesn't exist and
+/// `Setting::case_insensitive_paths` is set
+fn case_corrected_path(file_path: &str, setting: &Setting) -> Option<PathBuf> {
+    let path = Path::new(file_path);
+    if !setting.case_insensitive_paths || path.exists() {
+        return None;
+    }
+    find_case_insensitive(path)
+}
+
+/// Files under `Setting::files_dir` that aren't in `listed`, relative to that directory. The hash
+/// list and its cache file are left out
+fn find_extra_files(
+    listed: &HashSet<PathBuf>,
+    hash_list_path: &Path,
+    setting: &Setting,
+) -> Result<Vec<String>> {
+    let hash_list_path = std::path::absolute(hash_list_path)?;
+    let root = &setting.files_dir(&hash_list_path)?;
+    let cache_path = cache_path(&hash_list_path);
+
+    let mut extra = vec![];
+    for entry in WalkDir::new(root).sort_by_file_name() {
+        let entry = entry?;
+        let path = entry.path();
+        if !entry.file_type().is_file()
+            || listed.contains(path)
+            || path == hash_list_path
+            || path == cache_path
+        {
+            continue;
+        }
+
+        let relative = path
+            .strip_prefix(root)
+            .expect("Walked paths are under the root")
+            .to_string_lossy()
+            .into_owned();
+        if setting.is_selected(&relative) {
+            extra.push(relative);
+        }
+    }
+
+    Ok(extra)
+}
+
+/// Verify every file of `hash_list`, sending a `Message` for each result and a
+/// `Message::Completed` or `Message::Cancelled` at the end. Blocks until the run is done, or
+/// until `cancelled` is set with `Setting::watch`. `hash_list_path` is used for the
+/// `Setting::incremental` cache and `Setting::check_extra`. Files already verified in
+/// `Setting::checkpoint_file` are counted without being hashed with `Setting::resume`. Returns the
+/// results of the first pass
+pub fn prepare_hashing(
+    hash_list: Vec<HashPair>,
+    setting: &Setting,
+    status: StatusWrapper,
+    tx: Sender<Message>,
+    pause: &Pause,
+    cancelled: &AtomicBool,
+    hash_list_path: &Path,
+) -> VerificationSummary {
+    let checkpoint = setting
+        .checkpoint_file
+        .as_ref()
+        .map(|x| Arc::new(open_checkpoint(x, setting, &tx)));
+    prepare_hashing_with_checkpoint(
+        hash_list,
+        setting,
+        status,
+        tx,
+        pause,
+        cancelled,
+        hash_list_path,
+        checkpoint,
+    )
+}
+
+/// `prepare_hashing` with the `checkpoint` of `Setting::checkpoint_file` opened by the caller,
+/// e.g. to save it from a signal handler if the files being hashed never finish
+#[allow(clippy::too_many_arguments)]
+pub fn prepare_hashing_with_checkpoint(
+    hash_list: Vec<HashPair>,
+    setting: &Setting,
+    status: StatusWrapper,
+    tx: Sender<Message>,
+    pause: &Pause,
+    cancelled: &AtomicBool,
+    hash_list_path: &Path,
+    checkpoint: Option<Arc<Checkpoint>>,
+) -> VerificationSummary {
+    let start_time = Instant::now();
+    let files_dir = setting.files_dir(hash_list_path).ok();
+    let auto_setting = files_dir.and_then(|x| auto_threads_setting(setting, &x));
+    let setting = auto_setting.as_ref().unwrap_or(setting);
+    let cache = setting
+        .incremental
+        .then(|| IncrementalCache::open(cache_path(hash_list_path), setting.ignore_cache));
+    // Skipped files are listed too, so they aren't extra
+    let listed: HashSet<_> = if setting.check_extra {
+        hash_list
+            .iter()
+            .map(|x| {
+                case_corrected_path(&x.file_path, setting)
+                    .unwrap_or_else(|| PathBuf::from(&x.file_path))
+            })
+            .filter_map(|x| std::path::absolute(x).ok())
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    let (mut hash_list, skipped): (Vec<_>, Vec<_>) = hash_list
+        .into_iter()
+        .partition(|x| setting.is_selected(&x.file_path) && !is_ignored_empty_file(x, setting));
+    for pair in &skipped {
+        let _ = tx.send(Message::Skipped(pair.file_path.clone()));
+        status.inc_skipped();
+    }
+    if setting.sort {
+        hash_list.sort();
+    }
+    let watch_list = setting.watch.then(|| hash_list.clone());
+    let mut resumed = vec![];
+    if let Some(checkpoint) = &checkpoint {
+        hash_list.retain(|x| match checkpoint.get(&x.file_path) {
+            Some(result) => {
+                status.inc_result(result);
+                resumed.push(result);
+                false
+            }
+            None => true,
+        });
+    }
+    if !resumed.is_empty() {
+        let _ = tx.send(Message::Warning(format!(
+            "Resuming from the checkpoint, skipping {} verified files",
+            resumed.len()
+        )));
+    }
+    if let Some(audit_log) = &setting.audit_log {
+        if let Err(e) = start_audit_log(audit_log, setting.audit_format) {
+            tracing::error!(?audit_log, %e, "Couldn't open the audit log");
+            let _ = tx.send(Message::Warning(format!(
+                "Couldn't open audit log {audit_log:?}: {e}"
+            )));
+        }
+    }
+
+    let inode_cache = InodeCache::default();
+    let incorrect_files = Mutex::new(vec![]);
+    let file_stats = setting.benchmark.then(|| Mutex::new(vec![]));
+    let (mut results, aborted) = run_tasks(hash_list, setting, &tx, pause, cancelled, |x| {
+        let file_path = x.file_path.clone();
+        let result = hash_checker(
+            x,
+            setting,
+            status.clone(),
+            tx.clone(),
+            cache.as_ref(),
+            Some(&inode_cache),
+            file_stats.as_ref(),
+        );
+        if let Some(checkpoint) = &checkpoint {
+            // Files finishing after Ctrl+C are saved before the process exits
+            let flush = cancelled.load(Ordering::Relaxed);
+            if let Err(e) = checkpoint.record(&file_path, result, flush) {
+                tracing::error!(%e, "Couldn't save the checkpoint");
+                let _ = tx.send(Message::Warning(format!(
+                    "Couldn't save the checkpoint: {e}"
+                )));
+            }
+        }
+        if result == CheckResult::Incorrect {
+            incorrect_files.lock().push(file_path);
+        }
+        result
+    });
+    results.extend(resumed);
+
+    // Deleted after a full run, otherwise saved with everything verified so far
+    if let Some(checkpoint) = &checkpoint {
+        let res = if aborted || cancelled.load(Ordering::Relaxed) {
+            checkpoint.save()
+        } else {
+            checkpoint.remove()
+        };
+        if let Err(e) = res {
+            tracing::error!(%e, "Couldn't update the checkpoint");
+            let _ = tx.send(Message::Warning(format!(
+                "Couldn't update the checkpoint: {e}"
+            )));
+        }
+    }
+
+    // Only saved after a full run
+    if let Some(cache) = cache.filter(|_| !aborted && !cancelled.load(Ordering::Relaxed)) {
+        if let Err(e) = cache.save() {
+            tracing::error!(%e, "Couldn't save the cache");
+            let _ = tx.send(Message::Warning(format!("Couldn't save the cache: {e}")));
+        }
+    }
+
+    let mut extra_num = 0;
+    if setting.check_extra && !aborted && !cancelled.load(Ordering::Relaxed) {
+        match find_extra_files(&listed, hash_list_path, setting) {
+            Ok(extra) => {
+                extra_num = extra.len();
+                for file_path in extra {
+                    let _ = tx.send(Message::Extra(file_path));
+                    status.inc_extra();
+                }
+            }
+            Err(e) => {
+                tracing::error!(%e, "Couldn't look for extra files");
+                let _ = tx.send(Message::Warning(format!(
+                    "Couldn't look for extra files: {e}"
+                )));
+            }
+        }
+    }
+
+    let elapsed = start_time.elapsed().saturating_sub(pause.paused_duration());
+    let outcome = VerificationOutcome {
+        skipped_num: skipped.len(),
+        extra_num,
+        ..VerificationOutcome::new(&results, aborted, elapsed)
+    };
+    send_outcome(&tx, outcome, cancelled);
+
+    if let Some(hash_list) = watch_list {
+        watch_files(hash_list, setting, status, tx, cancelled);
+    }
+
+    VerificationSummary::new(
+        hash_list_path,
+        outcome,
+        incorrect_files.into_inner(),
+        file_stats.map(Mutex::into_inner).unwrap_or_default(),
+    )
+}
+
+/// Checkpoint at `path`, with the results of the last run if `Setting::resume` is set. A
+/// corrupted checkpoint is reported and ignored
+pub fn open_checkpoint(path: &Path, setting: &Setting, tx: &Sender<Message>) -> Checkpoint {
+    let interval = setting.checkpoint_interval;
+    if !setting.resume {
+        return Checkpoint::new(path.to_path_buf(), interval);
+    }
+    match Checkpoint::resume(path.to_path_buf(), interval) {
+        Ok(checkpoint) => checkpoint,
+        Err(e) => {
+            tracing::error!(%e, "Ignoring the checkpoint");
+            let _ = tx.send(Message::Warning(format!("{e}, starting over")));
+            Checkpoint::new(path.to_path_buf(), interval)
+        }
+    }
+}
+
+/// Verify files again when they are modified, until `cancelled` is set
+fn watch_files(
+    hash_list: Vec<HashPair>,
+    setting: &Setting,
+    status: StatusWrapper,
+    tx: Sender<Message>,
+    cancelled: &AtomicBool,
+) {
+    let warn = |message: String| {
+        tracing::error!("{message}");
+        let _ = tx.send(Message::Warning(message));
+    };
+
+    // Missing files can't be watched
+    let pairs: HashMap<PathBuf, HashPair> = hash_list
+        .into_iter()
+        .filter_map(|x| Some((fs::canonicalize(&x.file_path).ok()?, x)))
+        .collect();
+
+    let (event_tx, event_rx) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(event_tx) {
+        Ok(x) => x,
+        Err(e) => return warn(format!("Couldn't start watching: {e}")),
+    };
+    // Directories are watched so files replaced by editors are still seen
+    for dir in pairs.keys().filter_map(|x| x.parent()).unique() {
+        if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+            warn(format!("Couldn't watch {dir:?}: {e}"));
+        }
+    }
+
+    let mut changed = HashSet::new();
+    let mut last_event = Instant::now();
+    while !cancelled.load(Ordering::Relaxed) {
+        match event_rx.recv_timeout(Duration::from_millis(50)) {
+            Ok(Ok(event)) if matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) => {
+                changed.extend(event.paths.into_iter().filter(|x| pairs.contains_key(x)));
+                last_event = Instant::now();
+            }
+            Ok(Err(e)) => warn(format!("Watch error: {e}")),
+            Err(RecvTimeoutError::Disconnected) => break,
+            _ => {}
+        }
+
+        if !changed.is_empty() && last_event.elapsed() >= WATCH_DEBOUNCE {
+            for path in changed.drain() {
+                let pair = pairs[&path].clone();
+                let _ = tx.send(Message::Modified(pair.file_path.clone()));
+                status.restart();
+                // Not cached, the content has changed
+                hash_checker(pair, setting, status.clone(), tx.clone(), None, None, None);
+            }
+        }
+    }
+}
+
+fn send_outcome(tx: &Sender<Message>, outcome: VerificationOutcome, cancelled: &AtomicBool) {
+    let message = if cancelled.load(Ordering::Relaxed) {
+        Message::Cancelled(outcome)
+    } else {
+        Message::Completed(outcome)
+    };
+    let _ = tx.send(message);
+}
+
+/// Recursively list every regular file in `dir`
+pub fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in WalkDir::new(dir) {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            files.push(entry.into_path());
+        }
+    }
+
+    Ok(files)
+}
+
+/// Keep the entries of `hash_list` whose path, as listed, matches `pattern`, like
+/// `Setting::include`. The warnings of the parsed hash list are dropped
+pub fn extract_hash_list(mut hash_list: HashList, pattern: &Pattern) -> HashList {
+    hash_list.pairs.retain(|x| pattern.matches(&x.file_path));
+    hash_list.warnings.clear();
+    hash_list
+}
+
+/// A file with different hashes in two hash lists, see `cross_check_hash_lists`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrossCheckConflict {
+    /// Path as written in both hash lists
+    pub path: String,
+    /// Hash from the first hash list
+    pub hash1: String,
+    /// Hash from the second hash list
+    pub hash2: String,
+}
+
+impl Display for CrossCheckConflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Conflicting hashes for {}: {} in the first hash list, {} in the second",
+            self.path, self.hash1, self.hash2
+        )
+    }
+}
+
+/// Files listed in both `first` and `second` with different hashes, sorted by path. Hashes are
+/// compared ignoring case, and paths as written
+pub fn cross_check_hash_lists(first: &HashList, second: &HashList) -> Vec<CrossCheckConflict> {
+    let hashes = |hash_list: &HashList| -> HashMap<String, String> {
+        hash_list
+            .pairs
+            .iter()
+            .map(|x| (x.file_path.clone(), x.expected_hash.clone()))
+            .collect()
+    };
+    let (first, second) = (hashes(first), hashes(second));
+
+    let mut conflicts = first
+        .into_iter()
+        .filter_map(|(path, hash1)| {
+            let hash2 = second.get(&path)?;
+            (!hash1.eq_ignore_ascii_case(hash2)).then(|| CrossCheckConflict {
+                path,
+                hash1,
+                hash2: hash2.clone(),
+            })
+        })
+        .collect_vec();
+    conflicts.sort_by(|a, b| a.path.cmp(&b.path));
+    conflicts
+}
+
+/// Combine `hash_lists` into one with an entry per file path, in the order the files first appear.
+/// Which entry is kept for a file in several hash lists depends on `policy`, and files with
+/// different hashes get a `ParseWarning::MergeConflict`. The format is the one of the first list
+pub fn merge_hash_lists(hash_lists: Vec<HashList>, policy: MergePolicy) -> HashList {
+    let mut merged = HashList::default();
+    if let Some(first) = hash_lists.first() {
+        merged.algorithm = first.algorithm;
+        merged.format = first.format;
+    }
+
+    // Index in `merged.pairs` of each file
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for pair in hash_lists.into_iter().flat_map(|x| x.pairs) {
+        let Some(&idx) = seen.get(&pair.file_path) else {
+            seen.insert(pair.file_path.clone(), merged.pairs.len());
+            merged.pairs.push(pair);
+            continue;
+        };
+
+        let existing = &mut merged.pairs[idx];
+        if existing
+            .expected_hash
+            .eq_ignore_ascii_case(&pair.expected_hash)
+        {
+            continue;
+        }
+        let (kept_hash, dropped_hash) = match policy {
+            MergePolicy::Last => {
+                let dropped = std::mem::replace(existing, pair);
+                (existing.expected_hash.clone(), dropped.expected_hash)
+            }
+            MergePolicy::First => (existing.expected_hash.clone(), pair.expected_hash),
+        };
+        merged.warnings.push(ParseWarning::MergeConflict {
+            file_path: existing.file_path.clone(),
+            kept_hash,
+            dropped_hash,
+        });
+    }
+
+    merged
+}
+
+/// How a file differs between two hash lists, see `diff_hash_lists`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffKind {
+    /// Only in the new hash list
+    Added,
+    /// Only in the old hash list
+    Removed,
+    /// In both, with different hashes
+    Changed,
+    /// In both, with the same hash
+    Unchanged,
+    /// Only in the new hash list, with the hash of a file only in the old one, see
+    /// `detect_renames`
+    Renamed {
+        /// Path in the old hash list
+        from: String,
+    },
+    /// Only in the new hash list, with the hash of a file in both, see `detect_renames`
+    Copied {
+        /// Path of the file with the same hash
+        from: String,
+    },
+}
+
+impl DiffKind {
+    /// Path the file was renamed or copied from
+    pub fn source(&self) -> Option<&str> {
+        match self {
+            DiffKind::Renamed { from } | DiffKind::Copied { from } => Some(from),
+            _ => None,
+        }
+    }
+}
+
+/// A file of either hash list, see `diff_hash_lists`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffEntry {
+    /// Path as written in the hash lists
+    pub file_path: String,
+    /// How it differs
+    pub kind: DiffKind,
+    /// Hash in the old hash list, `None` if added
+    pub old_hash: Option<String>,
+    /// Hash in the new hash list, `None` if removed
+    pub new_hash: Option<String>,
+}
+
+/// Compare the entries of `old` and `new` by file path, sorted by path. Hashes are compared
+/// ignoring case, and the last entry of a file listed more than once is used
+pub fn diff_hash_lists(old: &[HashPair], new: &[HashPair]) -> Vec<DiffEntry> {
+    let hashes_by_path = |pairs: &[HashPair]| -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|x| (x.file_path.clone(), x.expected_hash.clone()))
+            .collect()
+    };
+    let mut old = hashes_by_path(old);
+    let new = hashes_by_path(new);
+
+    let mut entries = vec![];
+    for (file_path, new_hash) in new {
+        let old_hash = old.remove(&file_path);
+        let kind = match &old_hash {
+            None => DiffKind::Added,
+            Some(x) if x.eq_ignore_ascii_case(&new_hash) => DiffKind::Unchanged,
+            Some(_) => DiffKind::Changed,
+        };
+        entries.push(DiffEntry {
+            file_path,
+            kind,
+            old_hash,
+            new_hash: Some(new_hash),
+        });
+    }
+    entries.extend(old.into_iter().map(|(file_path, old_hash)| DiffEntry {
+        file_path,
+        kind: DiffKind::Removed,
+        old_hash: Some(old_hash),
+        new_hash: None,
+    }));
+    entries.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+
+    entries
+}
+
+/// Turn added files of `entries` from `diff_hash_lists` with the hash of a removed file into
+/// `DiffKind::Renamed`, dropping the removed file. Added files with the hash of a file that is in
+/// both hash lists, or was already renamed, become `DiffKind::Copied`
+pub fn detect_renames(entries: Vec<DiffEntry>) -> Vec<DiffEntry> {
+    let hash_key = |x: &Option<String>| x.as_deref().unwrap_or_default().to_ascii_lowercase();
+    // Files in the new hash list that copies can come from, by hash
+    let mut sources: HashMap<String, String> = HashMap::new();
+    // In path order, which is the order they are renamed in
+    let mut removed: HashMap<String, Vec<String>> = HashMap::new();
+    for entry in &entries {
+        match entry.kind {
+            DiffKind::Unchanged | DiffKind::Changed => {
+                sources
+                    .entry(hash_key(&entry.new_hash))
+                    .or_insert_with(|| entry.file_path.clone());
+            }
+            DiffKind::Removed => removed
+                .entry(hash_key(&entry.old_hash))
+                .or_default()
+                .push(entry.file_path.clone()),
+            _ => {}
+        }
+    }
+
+    let mut renamed = HashSet::new();
+    let mut entries = entries
+        .into_iter()
+        .map(|mut entry| {
+            if entry.kind != DiffKind::Added {
+                return entry;
+            }
+
+            let hash = hash_key(&entry.new_hash);
+            if let Some(from) = sources.get(&hash) {
+                entry.kind = DiffKind::Copied { from: from.clone() };
+            } else if let Some(from) = removed.get_mut(&hash).filter(|x| !x.is_empty()) {
+                let from = from.remove(0);
+                renamed.insert(from.clone());
+                entry.old_hash = entry.new_hash.clone();
+                entry.kind = DiffKind::Renamed { from };
+                sources.insert(hash, entry.file_path.clone());
+            }
+            entry
+        })
+        .collect_vec();
+    entries.retain(|x| x.kind != DiffKind::Removed || !renamed.contains(&x.file_path));
+
+    entries
+}
+
+/// Files of `hash_list` with the same hash as another file, grouped by hash. Groups are in the
+/// order their first file is listed, and a file listed more than once only counts once
+pub fn find_duplicate_files(hash_list: &[HashPair]) -> Vec<(String, Vec<String>)> {
+    let mut groups: Vec<(String, Vec<String>)> = vec![];
+    // Index in `groups` of each lowercase hash
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for pair in hash_list {
+        let key = pair.expected_hash.to_ascii_lowercase();
+        let idx = *seen.entry(key).or_insert_with(|| {
+            groups.push((pair.expected_hash.clone(), vec![]));
+            groups.len() - 1
+        });
+        let files = &mut groups[idx].1;
+        if !files.contains(&pair.file_path) {
+            files.push(pair.file_path.clone());
+        }
+    }
+    groups.retain(|(_, files)| files.len() > 1);
+
+    groups
+}
+
+/// Write `hash_list` to `path` in its format, like `write_hash_list_atomic`. Fails with
+/// `HashError::ManifestNotEditable` for JSON manifests, which would lose their metadata
+pub fn write_formatted_hash_list_atomic(
+    path: &Path,
+    hash_list: &HashList,
+) -> Result<(), HashError> {
+    if hash_list.format == HashListFormat::JsonManifest {
+        return Err(HashError::ManifestNotEditable);
+    }
+    write_lines_atomic(path, hash_list.lines())
+}
+
+/// Files under `dir` that aren't in `hash_list`, sorted. The hash list at `hash_list_path` and its
+/// cache file are left out. Listed paths are resolved from the current directory
+pub fn find_unlisted_files(
+    hash_list: &[HashPair],
+    hash_list_path: &Path,
+    dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    // Listed files that don't exist can't be canonicalized, but aren't on disk anyway
+    let resolve = |x: &Path| x.canonicalize().or_else(|_| std::path::absolute(x));
+    let hash_list_path = resolve(hash_list_path)?;
+    let cache_path = cache_path(&hash_list_path);
+    let listed: HashSet<_> = hash_list
+        .iter()
+        .filter_map(|x| resolve(Path::new(&x.file_path)).ok())
+        .collect();
+
+    let mut files = list_files(&dir.canonicalize()?)?
+        .into_iter()
+        .filter(|x| !listed.contains(x) && *x != hash_list_path && *x != cache_path)
+        .collect_vec();
+    files.sort();
+    Ok(files)
+}
+
+fn hash_generator(
+    root: &Path,
+    file_path: PathBuf,
+    setting: &Setting,
+    status: StatusWrapper,
+    tx: Sender<Message>,
+) -> CheckResult {
+    let listed_path = file_path
+        .strip_prefix(root)
+        .unwrap_or(&file_path)
+        .to_string_lossy()
+        .into_owned();
+
+    let algorithm = setting.generation_algorithm();
+    let res = hash_file(&file_path, algorithm, setting, &status);
+    let file_hash = match res {
+        Ok(x) => x,
+        Err(e) => {
+            let _ = tx.send(Message::Error {
+                file_path: listed_path,
+                error: e,
+            });
+            status.inc_error();
+            return CheckResult::Error;
+        }
+    };
+
+    status.inc_correct();
+    status.set_text(listed_path.clone(), file_hash.clone(), String::new());
+    let _ = tx.send(Message::Generated(listed_path, file_hash));
+    CheckResult::Correct
+}
+
+/// Write `entries` of `(file path, hash)` hashed with `algorithm` as a hash list in `format`, see
+/// `writer::HashListWriter`. The list is written to a temporary file next to `path`, synced, then
+/// renamed over `path`, so an interrupted write never leaves a truncated hash list. If the rename
+/// fails, the temporary file is copied instead
+pub fn write_hash_list_atomic(
+    path: &Path,
+    format: HashListFormat,
+    algorithm: HashAlgorithm,
+    entries: &[(String, String)],
+) -> Result<(), HashError> {
+    // Listed paths are relative to the hash list
+    let root = path.parent().unwrap_or(Path::new(""));
+    write_atomic(path, |writer| {
+        let writer = HashListWriter::new(writer, format, algorithm, root);
+        write_entries(writer, entries)?;
+        Ok(())
+    })
+}
+
+/// `entries` as a GNU hash list, like `write_hash_list_atomic`, compressed with `compression`
+pub fn compressed_hash_list(
+    entries: &[(String, String)],
+    compression: Compression,
+) -> io::Result<Vec<u8>> {
+    // The algorithm is only needed by other formats
+    let algorithm = HashAlgorithm::default();
+    let writer = HashListWriter::new(Vec::new(), HashListFormat::Gnu, algorithm, Path::new(""));
+    let content = write_entries(writer, entries)?;
+    compression.compress(&content)
+}
+
+/// Write `entries` with `writer` and finish it, returning the underlying writer
+fn write_entries<W: Write>(
+    mut writer: HashListWriter<W>,
+    entries: &[(String, String)],
+) -> io::Result<W> {
+    for (file_path, hash) in entries {
+        writer.write_entry(file_path, hash)?;
+    }
+    writer.finish()?;
+    Ok(writer.into_inner())
+}
+
+/// See `write_hash_list_atomic`
+pub(crate) fn write_lines_atomic(
+    path: &Path,
+    lines: impl IntoIterator<Item = String>,
+) -> Result<(), HashError> {
+    write_atomic(path, |writer| {
+        for line in lines {
+            writeln!(writer, "{line}")?;
+        }
+        Ok(())
+    })
+}
+
+/// Write `bytes` to `path` like `write_hash_list_atomic`, e.g. a finished `HashListWriter` buffer
+pub fn write_bytes_atomic(path: &Path, bytes: &[u8]) -> Result<(), HashError> {
+    write_atomic(path, |writer| writer.write_all(bytes))
+}
+
+fn write_atomic(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<&mut File>) -> io::Result<()>,
+) -> Result<(), HashError> {
+    let write_error = |source| HashError::WriteError {
+        path: path.to_path_buf(),
+        source,
+    };
+    let dir = match path.parent() {
+        Some(x) if !x.as_os_str().is_empty() => x,
+        _ => Path::new("."),
+    };
+
+    let mut temp_file = NamedTempFile::new_in(dir).map_err(write_error)?;
+    let mut writer = BufWriter::new(temp_file.as_file_mut());
+    write(&mut writer).map_err(write_error)?;
+    writer.flush().map_err(write_error)?;
+    drop(writer);
+    temp_file.as_file().sync_all().map_err(write_error)?;
+
+    if let Err(e) = temp_file.persist(path) {
+        // The temporary file is deleted when `e.file` is dropped
+        fs::copy(e.file.path(), path).map_err(write_error)?;
+    }
+
+    Ok(())
+}
+
+/// Rewrite the hash list at `path`, which `hash_list` was parsed from, with the hashes of
+/// `new_hashes` by file path and without the lines of the files in `deleted`. Only the lines of
+/// listed files whose hash changed are replaced, so comments and the other lines are kept in order.
+/// Written like `write_hash_list_atomic`. Returns the number of lines replaced. Fails with
+/// `HashError::ManifestNotEditable` for JSON manifests
+pub fn update_hash_list(
+    path: &Path,
+    hash_list: &[HashPair],
+    new_hashes: &HashMap<String, String>,
+    deleted: &HashSet<String>,
+) -> Result<usize, HashError> {
+    if hash_list
+        .iter()
+        .any(|x| x.format == HashListFormat::JsonManifest)
+    {
+        return Err(HashError::ManifestNotEditable);
+    }
+    let bytes = fs::read(path).map_err(|e| HashError::from_read(path, e))?;
+    let (content, _) = decode_hash_list(&bytes);
+    let replacements: HashMap<_, _> = hash_list
+        .iter()
+        .filter_map(|pair| {
+            let hash = new_hashes.get(&pair.file_path)?;
+            (!pair.expected_hash.eq_ignore_ascii_case(hash)).then(|| {
+                let line = pair.format.entry(pair.algorithm, &pair.file_path, hash);
+                (pair.line, line)
+            })
+        })
+        .collect();
+    let deleted_lines: HashSet<_> = hash_list
+        .iter()
+        .filter(|x| deleted.contains(&x.file_path))
+        .map(|x| x.line)
+        .collect();
+
+    // Numbered like in `hash_list_parser`
+    let lines = content
+        .lines()
+        .enumerate()
+        .filter(|(idx, _)| !deleted_lines.contains(&(idx + 1)))
+        .map(|(idx, line)| {
+            replacements
+                .get(&(idx + 1))
+                .cloned()
+                .unwrap_or_else(|| line.trim_end_matches('\r').to_string())
+        });
+    write_lines_atomic(path, lines)?;
+
+    Ok(replacements.len())
+}
+
+/// Add `entries` of `(file path, hash)` to the end of the hash list at `path`, in `format`. A line
+/// break is added first if the hash list doesn't end with one. Fails with
+/// `HashError::ManifestNotEditable` for JSON manifests
+pub fn append_to_hash_list(
+    path: &Path,
+    format: HashListFormat,
+    algorithm: HashAlgorithm,
+    entries: &[(String, String)],
+) -> Result<(), HashError> {
+    if format == HashListFormat::JsonManifest {
+        return Err(HashError::ManifestNotEditable);
+    }
+    let write_error = |source| HashError::WriteError {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .open(path)
+        .map_err(write_error)?;
+    let mut last_byte = [b'\n'];
+    if file.metadata().map_err(write_error)?.len() > 0 {
+        file.seek(SeekFrom::End(-1)).map_err(write_error)?;
+        file.read_exact(&mut last_byte).map_err(write_error)?;
+    }
+
+    let mut writer = BufWriter::new(file);
+    if last_byte[0] != b'\n' {
+        writeln!(writer).map_err(write_error)?;
+    }
+    for (file_path, hash) in entries {
+        writeln!(writer, "{}", format.entry(algorithm, file_path, hash)).map_err(write_error)?;
+    }
+    writer.flush().map_err(write_error)
+}
+
+/// Hash every file in `file_list`, sending a `Message::Generated` with its path relative to `root`
+pub fn generate_hash_list(
+    root: &Path,
+    mut file_list: Vec<PathBuf>,
+    setting: &Setting,
+    status: StatusWrapper,
+    tx: Sender<Message>,
+    pause: &Pause,
+    cancelled: &AtomicBool,
+) {
+    let start_time = Instant::now();
+    let auto_setting = auto_threads_setting(setting, root);
+    let setting = auto_setting.as_ref().unwrap_or(setting);
+    if setting.sort {
+        file_list.sort();
+    }
+
+    let (results, aborted) = run_tasks(file_list, setting, &tx, pause, cancelled, |x| {
+        hash_generator(root, x, setting, status.clone(), tx.clone())
+    });
 
-    let _ = tx.send(Message::Completed(Instant::now() - start_time));
+    let elapsed = start_time.elapsed().saturating_sub(pause.paused_duration());
+    let outcome = VerificationOutcome::new(&results, aborted, elapsed);
+    send_outcome(&tx, outcome, cancelled);
 }
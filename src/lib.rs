@@ -0,0 +1,1047 @@
+//! Verify and generate hash lists (`md5sum`, `sha256sum`, BSD tags, SFV, ...).
+//!
+//! Parse a hash list with [`hash_list_parser`], then verify it with [`prepare_hashing`], which
+//! sends a [`Message`] for each result and updates the [`Status`] counters.
+#![warn(missing_docs)]
+
+pub mod audit;
+pub mod benchmark;
+pub mod cache;
+pub mod checkpoint;
+pub mod compression;
+pub mod config;
+pub mod hash;
+pub mod manifest;
+pub mod notification;
+pub mod reader;
+pub mod recent;
+pub mod signature;
+pub mod system;
+pub mod util;
+pub mod writer;
+
+pub use crate::hash::{
+    hash_file, hash_list_parser, prepare_hashing, DuplicatePolicy, HashAlgorithm, HashError,
+    HashList, HashPair, MergePolicy, ParseWarning,
+};
+
+use crate::audit::AuditFormat;
+use crate::benchmark::FileStats;
+use crate::hash::{verify_pair, CheckResult, HashListFormat};
+use crate::system::{detect_threads, AutoThreads};
+use glob::Pattern;
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io::{self, IsTerminal};
+use std::ops::AddAssign;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Smallest `Setting::block_size` accepted by `SettingBuilder`
+pub const MIN_BLOCK_SIZE: usize = 512;
+
+/// How long the files being hashed get to finish after Ctrl+C, before exiting anyway
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Throughput is averaged over this window
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(2);
+
+/// Progress of a run, shared with the hashing threads
+#[derive(Debug, Default, Clone)]
+pub struct Status {
+    /// File being hashed
+    pub filename: String,
+    /// Hash of the last file
+    pub file_hash: String,
+    /// Expected hash of the last file
+    pub expected_hash: String,
+    /// Correct files, or hashed files in generation mode
+    pub correct_num: usize,
+    /// Files that don't match their expected hash
+    pub incorrect_num: usize,
+    /// Files in the hash list that don't exist
+    pub missing_num: usize,
+    /// Files that couldn't be read
+    pub error_num: usize,
+    /// Files left out by `Setting::include` and `Setting::exclude`
+    pub skipped_num: usize,
+    /// Files on disk that aren't in the hash list, see `Setting::check_extra`
+    pub extra_num: usize,
+    /// Updated while reading, without taking the lock
+    pub total_bytes_processed: Arc<AtomicU64>,
+    /// Bytes read of the last file that started hashing
+    pub current_file_bytes: Arc<AtomicU64>,
+    /// `(timestamp, total_bytes_processed)` within the last `THROUGHPUT_WINDOW`
+    throughput_samples: VecDeque<(Instant, u64)>,
+}
+
+/// Worst result of a run so far, see `Status::severity`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Every file is correct
+    Correct,
+    /// Some files are incorrect or extra
+    Incorrect,
+    /// Some files are missing or couldn't be read
+    Error,
+}
+
+impl Status {
+    /// Number of entries that are done, whatever the result
+    pub fn processed(&self) -> usize {
+        self.correct_num + self.incorrect_num + self.missing_num + self.error_num + self.skipped_num
+    }
+
+    /// Errors and missing files are worse than incorrect and extra files
+    pub fn severity(&self) -> Severity {
+        if self.error_num > 0 || self.missing_num > 0 {
+            Severity::Error
+        } else if self.incorrect_num > 0 || self.extra_num > 0 {
+            Severity::Incorrect
+        } else {
+            Severity::Correct
+        }
+    }
+
+    /// Sample `total_bytes_processed` for [`Status::throughput`]
+    pub fn record_throughput_sample(&mut self) {
+        let now = Instant::now();
+        let bytes = self.total_bytes_processed.load(Ordering::Relaxed);
+        self.throughput_samples.push_back((now, bytes));
+
+        while self
+            .throughput_samples
+            .front()
+            .is_some_and(|(time, _)| now.duration_since(*time) > THROUGHPUT_WINDOW)
+        {
+            self.throughput_samples.pop_front();
+        }
+    }
+
+    /// Bytes per second over the throughput samples
+    pub fn throughput(&self) -> f64 {
+        let samples = &self.throughput_samples;
+        match (samples.front(), samples.back()) {
+            (Some((start, start_bytes)), Some((end, end_bytes))) if end > start => {
+                (end_bytes - start_bytes) as f64 / end.duration_since(*start).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// Sent from the hashing threads for every file and at the end of a run
+#[derive(Debug)]
+pub enum Message {
+    /// Only sent with `Verbosity::Verbose`
+    Correct {
+        /// Path as written in the hash list
+        file_path: String,
+        /// Hash from the hash list, which may differ in case from `file_hash`
+        expected_hash: String,
+        /// Hash of the file
+        file_hash: String,
+    },
+    /// The file doesn't match its expected hash
+    Incorrect {
+        /// Path as written in the hash list
+        file_path: String,
+        /// Hash from the hash list
+        expected_hash: String,
+        /// Hash of the file
+        file_hash: String,
+    },
+    /// Something the user should know that didn't stop the run
+    Warning(String),
+    /// File path and hash of a file in generation mode
+    Generated(String, String),
+    /// The file couldn't be hashed
+    Error {
+        /// Path as written in the hash list
+        file_path: String,
+        /// Why hashing failed
+        error: HashError,
+    },
+    /// File path of a file in the hash list that doesn't exist
+    Missing(String),
+    /// File path of a file left out by `Setting::include` and `Setting::exclude`, or an empty file
+    /// with `Setting::ignore_zero`
+    Skipped(String),
+    /// File path and attempt number of a file hashed again after a transient error, see
+    /// `Setting::retries`
+    Retrying(String, u32),
+    /// File path of a file on disk that isn't in the hash list, see `Setting::check_extra`
+    Extra(String),
+    /// A listed file was found with another case, see `Setting::case_insensitive_paths`
+    CaseCorrected {
+        /// Path as written in the hash list
+        listed: String,
+        /// Path of the file that was hashed instead
+        found: String,
+    },
+    /// Every file has been checked
+    Completed(VerificationOutcome),
+    /// A watched file changed and is being verified again, see `Setting::watch`
+    Modified(String),
+    /// Files checked without hashing them, see `Setting::dry_run`. The missing and unreadable ones
+    /// are sent before as `Message::Missing` and `Message::Error`
+    DryRun {
+        /// Files that exist and can be read
+        verifiable_num: usize,
+        /// Sum of the sizes of the verifiable files
+        total_bytes: u64,
+    },
+    /// Stopped by the user from the TUI, with the partial results
+    Cancelled(VerificationOutcome),
+    /// Placeholder for an empty log
+    Empty,
+}
+
+/// Result counts of a finished run
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VerificationOutcome {
+    /// Correct files, or hashed files in generation mode
+    pub correct_num: usize,
+    /// Files that don't match their expected hash
+    pub incorrect_num: usize,
+    /// Files in the hash list that don't exist
+    pub missing_num: usize,
+    /// Files that couldn't be read
+    pub error_num: usize,
+    /// Files left out by `Setting::include` and `Setting::exclude`
+    pub skipped_num: usize,
+    /// Files on disk that aren't in the hash list, see `Setting::check_extra`
+    pub extra_num: usize,
+    /// Stopped early because of `Setting::fail_fast`
+    pub aborted: bool,
+    /// Time taken by the run
+    pub elapsed: Duration,
+}
+
+impl VerificationOutcome {
+    fn new(results: &[CheckResult], aborted: bool, elapsed: Duration) -> Self {
+        let count = |result| results.iter().filter(|&&x| x == result).count();
+
+        Self {
+            correct_num: count(CheckResult::Correct),
+            incorrect_num: count(CheckResult::Incorrect),
+            missing_num: count(CheckResult::Missing),
+            error_num: count(CheckResult::Error),
+            skipped_num: 0,
+            extra_num: 0,
+            aborted,
+            elapsed,
+        }
+    }
+
+    /// 0 if everything is correct, 1 if any file is incorrect or extra, 2 if any file couldn't be
+    /// hashed. Missing files count as errors unless `ignore_missing` is set
+    pub fn exit_code(&self, ignore_missing: bool) -> ExitCode {
+        if self.error_num > 0 || (self.missing_num > 0 && !ignore_missing) {
+            ExitCode::from(2)
+        } else if self.incorrect_num > 0 || self.extra_num > 0 {
+            ExitCode::from(1)
+        } else {
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+/// Counts and times are added up, and the sum is aborted if either run was
+impl AddAssign for VerificationOutcome {
+    fn add_assign(&mut self, rhs: Self) {
+        self.correct_num += rhs.correct_num;
+        self.incorrect_num += rhs.incorrect_num;
+        self.missing_num += rhs.missing_num;
+        self.error_num += rhs.error_num;
+        self.skipped_num += rhs.skipped_num;
+        self.extra_num += rhs.extra_num;
+        self.aborted |= rhs.aborted;
+        self.elapsed += rhs.elapsed;
+    }
+}
+
+/// Returned by `prepare_hashing`, so the results can be used without reading the messages
+#[derive(Debug, Default, Clone)]
+pub struct VerificationSummary {
+    /// Counts of the first pass, also sent with `Message::Completed`
+    pub outcome: VerificationOutcome,
+    /// Paths of the incorrect files, as written in the hash list
+    pub incorrect_files: Vec<String>,
+    /// Timing of every hashed file of the first pass, with `Setting::benchmark`
+    pub file_stats: Vec<FileStats>,
+    /// Lines of the hash list that couldn't be parsed. Set by the caller, as `prepare_hashing`
+    /// only gets the parsed entries
+    pub malformed_num: usize,
+    /// Results of each hash list, in the order they were verified
+    pub hash_lists: Vec<(PathBuf, FileSummary)>,
+}
+
+impl VerificationSummary {
+    /// Summary of the single hash list at `hash_list_path`
+    pub(crate) fn new(
+        hash_list_path: &Path,
+        outcome: VerificationOutcome,
+        incorrect_files: Vec<String>,
+        file_stats: Vec<FileStats>,
+    ) -> Self {
+        let file_summary = FileSummary {
+            outcome,
+            incorrect_files: incorrect_files.clone(),
+        };
+        Self {
+            outcome,
+            incorrect_files,
+            file_stats,
+            malformed_num: 0,
+            hash_lists: vec![(hash_list_path.to_path_buf(), file_summary)],
+        }
+    }
+}
+
+/// Results of several hash lists, in order, see `VerificationOutcome`'s `AddAssign`
+impl AddAssign for VerificationSummary {
+    fn add_assign(&mut self, rhs: Self) {
+        self.outcome += rhs.outcome;
+        self.incorrect_files.extend(rhs.incorrect_files);
+        self.file_stats.extend(rhs.file_stats);
+        self.malformed_num += rhs.malformed_num;
+        self.hash_lists.extend(rhs.hash_lists);
+    }
+}
+
+/// Results of one of the hash lists of a `VerificationSummary`
+#[derive(Debug, Default, Clone)]
+pub struct FileSummary {
+    /// Counts of the first pass
+    pub outcome: VerificationOutcome,
+    /// Paths of the incorrect files, as written in the hash list
+    pub incorrect_files: Vec<String>,
+}
+
+impl Display for VerificationOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Correct: {}, Incorrect: {}, Missing: {}, Error: {}",
+            self.correct_num, self.incorrect_num, self.missing_num, self.error_num
+        )?;
+        if self.skipped_num > 0 {
+            write!(f, ", Skipped: {}", self.skipped_num)?;
+        }
+        if self.extra_num > 0 {
+            write!(f, ", Extra: {}", self.extra_num)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a file compares to its expected hash
+#[derive(Debug)]
+pub enum VerificationStatus {
+    /// The hash matches
+    Correct,
+    /// The hash doesn't match
+    Incorrect,
+    /// The file doesn't exist
+    Missing,
+    /// The file couldn't be read
+    Error(HashError),
+}
+
+/// Result of verifying one file with `HashVerifier`
+#[derive(Debug)]
+pub struct VerificationResult {
+    /// Path as written in the hash list
+    pub path: String,
+    /// Hash from the hash list
+    pub expected: String,
+    /// Hash of the file, `None` if it couldn't be hashed
+    pub actual: Option<String>,
+    /// How `actual` compares to `expected`
+    pub status: VerificationStatus,
+}
+
+/// Verifies a hash list lazily, one file per `next()` on the calling thread. Unlike
+/// `prepare_hashing`, nothing is reported through a channel
+///
+/// ```no_run
+/// use md5check::{hash_list_parser, HashVerifier, Setting, VerificationStatus};
+/// use std::path::Path;
+///
+/// let hash_list = hash_list_parser(Path::new("MD5SUMS"), None, Default::default(), false)?;
+/// let incorrect = HashVerifier::new(hash_list, Setting::default())
+///     .filter(|x| !matches!(x.status, VerificationStatus::Correct))
+///     .count();
+/// # Ok::<(), md5check::HashError>(())
+/// ```
+#[derive(Debug)]
+pub struct HashVerifier {
+    pairs: std::vec::IntoIter<HashPair>,
+    setting: Setting,
+}
+
+impl HashVerifier {
+    /// Only `sort`, `include`, `exclude`, the reading settings (`block_size`, `mmap`,
+    /// `mmap_threshold`, `rate_limit`, `cpu_limit`, `follow_symlinks` and `hash_symlink_target`)
+    /// and the matching settings (`strict_zero`, `case_sensitive` and `case_insensitive_paths`) of
+    /// `setting` are used. The others, like `threads`, `retries`, `fail_fast`, `ignore_zero`,
+    /// `base_dir`, the checkpoint, the cache and the audit log, are ignored. Files left out by the
+    /// patterns are not verified at all
+    pub fn new(hash_list: HashList, setting: Setting) -> Self {
+        let mut pairs = hash_list.pairs;
+        pairs.retain(|x| setting.is_selected(x.file_path()));
+        if setting.sort {
+            pairs.sort();
+        }
+
+        Self {
+            pairs: pairs.into_iter(),
+            setting,
+        }
+    }
+
+    /// Verify the remaining files on rayon's current thread pool. Results are unordered unless
+    /// collected
+    pub fn par_iter(self) -> impl ParallelIterator<Item = VerificationResult> {
+        let setting = self.setting;
+        self.pairs
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map(move |x| verify_pair(x, &setting))
+    }
+
+    /// Verify the remaining files in order and sum up the results as those of the hash list at
+    /// `hash_list_path`
+    pub fn summary(self, hash_list_path: &Path) -> VerificationSummary {
+        let start_time = Instant::now();
+        let mut results = Vec::with_capacity(self.len());
+        let mut incorrect_files = Vec::new();
+        for result in self {
+            results.push(match result.status {
+                VerificationStatus::Correct => CheckResult::Correct,
+                VerificationStatus::Incorrect => {
+                    incorrect_files.push(result.path);
+                    CheckResult::Incorrect
+                }
+                VerificationStatus::Missing => CheckResult::Missing,
+                VerificationStatus::Error(_) => CheckResult::Error,
+            });
+        }
+        let outcome = VerificationOutcome::new(&results, false, start_time.elapsed());
+        VerificationSummary::new(hash_list_path, outcome, incorrect_files, Vec::new())
+    }
+}
+
+impl Iterator for HashVerifier {
+    type Item = VerificationResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pair = self.pairs.next()?;
+        Some(verify_pair(pair, &self.setting))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.pairs.size_hint()
+    }
+}
+
+impl ExactSizeIterator for HashVerifier {}
+
+/// How much is printed in CLI mode
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    /// No progress bar, only incorrect files and errors
+    Quiet,
+    /// A progress bar showing the last result
+    #[default]
+    Normal,
+    /// A line for every file
+    Verbose,
+    /// Nothing at all, the exit status tells the result. Only set with `--status`
+    #[serde(skip)]
+    Silent,
+}
+
+/// When errors and missing files are colored in CLI mode. The TUI always has colors
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// If stderr is a terminal, unless `NO_COLOR` is set or `TERM` is `dumb`
+    #[default]
+    Auto,
+    /// Even when `NO_COLOR` is set or stderr is redirected
+    Always,
+    /// Plain text only
+    Never,
+}
+
+impl ColorMode {
+    /// Whether output to stderr should be colored
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Auto => {
+                // Any value but an empty one disables colors, see https://no-color.org
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|x| !x.is_empty());
+                let dumb = std::env::var_os("TERM").is_some_and(|x| x == "dumb");
+                !no_color && !dumb && io::stderr().is_terminal()
+            }
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+/// Options of a run
+#[derive(Debug, Clone)]
+pub struct Setting {
+    /// Hash files on a thread pool
+    pub parallel: bool,
+    /// Sort the hash list by path before hashing
+    pub sort: bool,
+    /// Stop at the first incorrect file or error
+    pub fail_fast: bool,
+    /// Read buffer size in bytes
+    pub block_size: usize,
+    /// Smallest `block_size` the TUI goes down to. Defaults to the page size, as smaller reads
+    /// are slower
+    pub min_block_size: usize,
+    /// `None` means the algorithm is detected from the hash list
+    pub algorithm: Option<HashAlgorithm>,
+    /// `None` means picked by `parallel_auto`, or rayon's default (number of logical CPUs)
+    pub threads: Option<usize>,
+    /// Without `threads`, use a single thread on rotational disks and a thread per physical core
+    /// otherwise, see `system::detect_threads`
+    pub parallel_auto: bool,
+    /// How much is printed in CLI mode
+    pub verbosity: Verbosity,
+    /// Memory map every file instead of reading it in blocks
+    pub mmap: bool,
+    /// Files at least this large are memory mapped even without `mmap`
+    pub mmap_threshold: u64,
+    /// Most MB/s read from disk, shared by every thread in parallel mode. `None` means unlimited
+    pub rate_limit: Option<f64>,
+    /// Fraction of the time each hashing thread runs, from 0 to 1. With a thread per core, that's
+    /// the fraction of the cores used. Best effort, as threads only sleep between blocks. `None`
+    /// means unlimited
+    pub cpu_limit: Option<f32>,
+    /// Skip files unchanged since the last run, see `cache::IncrementalCache`
+    pub incremental: bool,
+    /// Hash every file with `incremental`, but still update the cache
+    pub ignore_cache: bool,
+    /// Re-verify files when they change after the first run
+    pub watch: bool,
+    /// Missing files don't affect the exit status
+    pub ignore_missing: bool,
+    /// Skip empty files, which match the hash of an empty input whether or not that was intended
+    pub ignore_zero: bool,
+    /// Fail files whose expected hash is the hash of an empty input, see
+    /// `HashError::EmptyFileHash`
+    pub strict_zero: bool,
+    /// Hash the target of symlinks. Otherwise they fail with `HashError::IsSymlink`
+    pub follow_symlinks: bool,
+    /// Hash the target path of symlinks as a string instead of the file it points to
+    pub hash_symlink_target: bool,
+    /// Only verify files matching one of these patterns, unless empty
+    pub include: Vec<Pattern>,
+    /// Don't verify files matching any of these patterns, even if they are included
+    pub exclude: Vec<Pattern>,
+    /// What to do with files listed more than once in the hash list
+    pub duplicate_policy: DuplicatePolicy,
+    /// Fail on hash list lines that can't be parsed instead of skipping them, see
+    /// `HashError::MalformedHashList`
+    pub strict: bool,
+    /// Report how many hash list lines couldn't be parsed after the summary, even when quiet, see
+    /// `VerificationSummary::malformed_num`
+    pub warn: bool,
+    /// How many times a file is hashed again after a transient read error
+    pub retries: u32,
+    /// Report files in the directory of the hash list that aren't listed in it
+    pub check_extra: bool,
+    /// Look for missing files before hashing, see `hash::find_missing_files`
+    pub preflight: bool,
+    /// Uppercase expected hashes don't match, which is almost never wanted
+    pub case_sensitive: bool,
+    /// Directory the relative paths of the hash list are from, instead of the directory of the
+    /// hash list. Absolute when set with `SettingBuilder::base_dir`
+    pub base_dir: Option<PathBuf>,
+    /// Look for a file differing only in case when a listed file doesn't exist, for hash lists
+    /// made on Windows. On by default on Windows
+    pub case_insensitive_paths: bool,
+    /// Append every verification result to this file, see `audit`. Absolute when set with
+    /// `SettingBuilder::audit_log`
+    pub audit_log: Option<PathBuf>,
+    /// Format of `audit_log`
+    pub audit_format: AuditFormat,
+    /// Time every hashed file, see `VerificationSummary::file_stats`
+    pub benchmark: bool,
+    /// Check the signature of hash lists with this key file before verifying them, see
+    /// `signature`. Absolute when set with `SettingBuilder::signature_key`
+    pub signature_key: Option<PathBuf>,
+    /// Save the verified files to this file every `checkpoint_interval`, see `checkpoint`. It's
+    /// deleted once the run is complete. Absolute when set with `SettingBuilder::checkpoint_file`
+    pub checkpoint_file: Option<PathBuf>,
+    /// How often `checkpoint_file` is saved
+    pub checkpoint_interval: Duration,
+    /// Skip the files already verified in `checkpoint_file`, counting their saved results
+    pub resume: bool,
+    /// Only check that the listed files exist and can be read, without hashing them, see
+    /// `hash::dry_run_hash_list`
+    pub dry_run: bool,
+    /// Show a desktop notification once the run completes, see `notification::notify_completed`
+    pub notify: bool,
+    /// When errors and missing files are colored in CLI mode
+    pub color: ColorMode,
+    /// Format of generated hash lists, unless `--format` asks for a JSON manifest or CSV
+    pub hash_list_format: HashListFormat,
+}
+
+impl Setting {
+    /// Whether `file_path` passes `include` and `exclude`
+    pub fn is_selected(&self, file_path: &str) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|x| x.matches(file_path));
+        included && !self.exclude.iter().any(|x| x.matches(file_path))
+    }
+
+    /// Compare an expected hash from a hash list to a computed one, see `case_sensitive`
+    pub fn hashes_match(&self, expected_hash: &str, file_hash: &str) -> bool {
+        if self.case_sensitive {
+            expected_hash == file_hash
+        } else {
+            expected_hash.eq_ignore_ascii_case(file_hash)
+        }
+    }
+
+    /// Threads picked by `parallel_auto` for the files under `dir`, or `None` if they aren't
+    /// picked automatically
+    pub fn auto_threads(&self, dir: &Path) -> Option<AutoThreads> {
+        (self.parallel && self.parallel_auto && self.threads.is_none()).then(|| detect_threads(dir))
+    }
+
+    /// Algorithm files are hashed with when generating a hash list. SFV hash lists are always
+    /// CRC-32
+    pub fn generation_algorithm(&self) -> HashAlgorithm {
+        match self.hash_list_format {
+            HashListFormat::Sfv => HashAlgorithm::Crc32,
+            _ => self.algorithm.unwrap_or_default(),
+        }
+    }
+
+    /// Directory the relative paths of the hash list at `hash_list_path` are from, see `base_dir`
+    pub fn files_dir(&self, hash_list_path: &Path) -> io::Result<PathBuf> {
+        match &self.base_dir {
+            Some(x) => std::path::absolute(x),
+            None => Ok(std::path::absolute(hash_list_path)?
+                .parent()
+                .expect("An absolute path to a file has a parent")
+                .to_path_buf()),
+        }
+    }
+}
+
+impl Default for Setting {
+    fn default() -> Self {
+        Self {
+            parallel: true,
+            sort: false,
+            fail_fast: false,
+            block_size: 8192,
+            min_block_size: page_size::get(),
+            algorithm: None,
+            threads: None,
+            parallel_auto: true,
+            verbosity: Verbosity::Normal,
+            mmap: false,
+            mmap_threshold: 64 * 1024 * 1024,
+            rate_limit: None,
+            cpu_limit: None,
+            incremental: false,
+            ignore_cache: false,
+            watch: false,
+            ignore_missing: false,
+            ignore_zero: false,
+            strict_zero: false,
+            follow_symlinks: true,
+            hash_symlink_target: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            duplicate_policy: DuplicatePolicy::Warn,
+            strict: false,
+            warn: false,
+            retries: 0,
+            check_extra: false,
+            preflight: false,
+            case_sensitive: false,
+            base_dir: None,
+            case_insensitive_paths: cfg!(windows),
+            audit_log: None,
+            audit_format: AuditFormat::Json,
+            benchmark: false,
+            signature_key: None,
+            checkpoint_file: None,
+            checkpoint_interval: Duration::from_secs(30),
+            resume: false,
+            dry_run: false,
+            notify: false,
+            color: ColorMode::Auto,
+            hash_list_format: HashListFormat::Gnu,
+        }
+    }
+}
+
+/// Invalid fields found by `SettingBuilder::build`
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum SettingError {
+    /// `block_size` is below `MIN_BLOCK_SIZE`
+    #[error("Block size must be at least {MIN_BLOCK_SIZE} bytes, got {0}")]
+    BlockSizeTooSmall(usize),
+    /// `min_block_size` is below `MIN_BLOCK_SIZE`
+    #[error("Minimum block size must be at least {MIN_BLOCK_SIZE} bytes, got {0}")]
+    MinBlockSizeTooSmall(usize),
+    /// `threads` is `Some(0)`
+    #[error("Number of threads must be greater than 0")]
+    ZeroThreads,
+    /// `rate_limit` is zero, negative or not a number
+    #[error("Rate limit must be greater than 0")]
+    InvalidRateLimit,
+    /// `cpu_limit` isn't greater than 0 and at most 1
+    #[error("CPU limit must be greater than 0 and at most 1")]
+    InvalidCpuLimit,
+    /// `hash_list_format` is SFV, but `algorithm` isn't CRC-32
+    #[error("SFV hash lists must be CRC-32, got {0}")]
+    SfvAlgorithm(HashAlgorithm),
+}
+
+/// Builds a validated `Setting`, starting from `Setting::default()`
+///
+/// ```
+/// use md5check::{HashAlgorithm, SettingBuilder};
+///
+/// let setting = SettingBuilder::new()
+///     .parallel(true)
+///     .block_size(65536)
+///     .algorithm(HashAlgorithm::Sha256)
+///     .build()
+///     .unwrap();
+/// assert_eq!(setting.block_size, 65536);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct SettingBuilder {
+    setting: Setting,
+}
+
+impl SettingBuilder {
+    /// Builder with the default settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// See `Setting::parallel`
+    pub fn parallel(&mut self, parallel: bool) -> &mut Self {
+        self.setting.parallel = parallel;
+        self
+    }
+
+    /// See `Setting::sort`
+    pub fn sort(&mut self, sort: bool) -> &mut Self {
+        self.setting.sort = sort;
+        self
+    }
+
+    /// See `Setting::fail_fast`
+    pub fn fail_fast(&mut self, fail_fast: bool) -> &mut Self {
+        self.setting.fail_fast = fail_fast;
+        self
+    }
+
+    /// See `Setting::block_size`. Must be at least `MIN_BLOCK_SIZE`
+    pub fn block_size(&mut self, block_size: usize) -> &mut Self {
+        self.setting.block_size = block_size;
+        self
+    }
+
+    /// See `Setting::min_block_size`. Must be at least `MIN_BLOCK_SIZE`
+    pub fn min_block_size(&mut self, min_block_size: usize) -> &mut Self {
+        self.setting.min_block_size = min_block_size;
+        self
+    }
+
+    /// See `Setting::algorithm`
+    pub fn algorithm(&mut self, algorithm: HashAlgorithm) -> &mut Self {
+        self.setting.algorithm = Some(algorithm);
+        self
+    }
+
+    /// See `Setting::threads`. Must be greater than 0
+    pub fn threads(&mut self, threads: usize) -> &mut Self {
+        self.setting.threads = Some(threads);
+        self
+    }
+
+    /// See `Setting::parallel_auto`
+    pub fn parallel_auto(&mut self, parallel_auto: bool) -> &mut Self {
+        self.setting.parallel_auto = parallel_auto;
+        self
+    }
+
+    /// See `Setting::rate_limit`. Must be greater than 0
+    pub fn rate_limit(&mut self, rate_limit: f64) -> &mut Self {
+        self.setting.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// See `Setting::cpu_limit`. Must be greater than 0 and at most 1
+    pub fn cpu_limit(&mut self, cpu_limit: f32) -> &mut Self {
+        self.setting.cpu_limit = Some(cpu_limit);
+        self
+    }
+
+    /// See `Setting::verbosity`
+    pub fn verbosity(&mut self, verbosity: Verbosity) -> &mut Self {
+        self.setting.verbosity = verbosity;
+        self
+    }
+
+    /// See `Setting::mmap`
+    pub fn mmap(&mut self, mmap: bool) -> &mut Self {
+        self.setting.mmap = mmap;
+        self
+    }
+
+    /// See `Setting::mmap_threshold`
+    pub fn mmap_threshold(&mut self, mmap_threshold: u64) -> &mut Self {
+        self.setting.mmap_threshold = mmap_threshold;
+        self
+    }
+
+    /// See `Setting::incremental`
+    pub fn incremental(&mut self, incremental: bool) -> &mut Self {
+        self.setting.incremental = incremental;
+        self
+    }
+
+    /// See `Setting::ignore_cache`
+    pub fn ignore_cache(&mut self, ignore_cache: bool) -> &mut Self {
+        self.setting.ignore_cache = ignore_cache;
+        self
+    }
+
+    /// See `Setting::watch`
+    pub fn watch(&mut self, watch: bool) -> &mut Self {
+        self.setting.watch = watch;
+        self
+    }
+
+    /// See `Setting::ignore_zero`
+    pub fn ignore_zero(&mut self, ignore_zero: bool) -> &mut Self {
+        self.setting.ignore_zero = ignore_zero;
+        self
+    }
+
+    /// See `Setting::strict_zero`
+    pub fn strict_zero(&mut self, strict_zero: bool) -> &mut Self {
+        self.setting.strict_zero = strict_zero;
+        self
+    }
+
+    /// See `Setting::follow_symlinks`
+    pub fn follow_symlinks(&mut self, follow_symlinks: bool) -> &mut Self {
+        self.setting.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// See `Setting::hash_symlink_target`
+    pub fn hash_symlink_target(&mut self, hash_symlink_target: bool) -> &mut Self {
+        self.setting.hash_symlink_target = hash_symlink_target;
+        self
+    }
+
+    /// See `Setting::ignore_missing`
+    pub fn ignore_missing(&mut self, ignore_missing: bool) -> &mut Self {
+        self.setting.ignore_missing = ignore_missing;
+        self
+    }
+
+    /// Add a pattern to `Setting::include`
+    pub fn include(&mut self, pattern: Pattern) -> &mut Self {
+        self.setting.include.push(pattern);
+        self
+    }
+
+    /// Add a pattern to `Setting::exclude`
+    pub fn exclude(&mut self, pattern: Pattern) -> &mut Self {
+        self.setting.exclude.push(pattern);
+        self
+    }
+
+    /// See `Setting::duplicate_policy`
+    pub fn duplicate_policy(&mut self, duplicate_policy: DuplicatePolicy) -> &mut Self {
+        self.setting.duplicate_policy = duplicate_policy;
+        self
+    }
+
+    /// See `Setting::strict`
+    pub fn strict(&mut self, strict: bool) -> &mut Self {
+        self.setting.strict = strict;
+        self
+    }
+
+    /// See `Setting::warn`
+    pub fn warn(&mut self, warn: bool) -> &mut Self {
+        self.setting.warn = warn;
+        self
+    }
+
+    /// See `Setting::retries`
+    pub fn retries(&mut self, retries: u32) -> &mut Self {
+        self.setting.retries = retries;
+        self
+    }
+
+    /// See `Setting::check_extra`
+    pub fn check_extra(&mut self, check_extra: bool) -> &mut Self {
+        self.setting.check_extra = check_extra;
+        self
+    }
+
+    /// See `Setting::preflight`
+    pub fn preflight(&mut self, preflight: bool) -> &mut Self {
+        self.setting.preflight = preflight;
+        self
+    }
+
+    /// See `Setting::case_sensitive`
+    pub fn case_sensitive(&mut self, case_sensitive: bool) -> &mut Self {
+        self.setting.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// See `Setting::base_dir`. A relative `base_dir` is from the current directory. Canonicalized
+    /// if it exists, so walked paths compare equal to listed ones
+    pub fn base_dir(&mut self, base_dir: PathBuf) -> &mut Self {
+        let absolute = base_dir
+            .canonicalize()
+            .or_else(|_| std::path::absolute(&base_dir));
+        self.setting.base_dir = Some(absolute.unwrap_or(base_dir));
+        self
+    }
+
+    /// See `Setting::case_insensitive_paths`
+    pub fn case_insensitive_paths(&mut self, case_insensitive_paths: bool) -> &mut Self {
+        self.setting.case_insensitive_paths = case_insensitive_paths;
+        self
+    }
+
+    /// See `Setting::audit_log`. A relative `audit_log` is from the current directory, as the CLI
+    /// moves to the directory of the hash list before verifying
+    pub fn audit_log(&mut self, audit_log: PathBuf) -> &mut Self {
+        self.setting.audit_log = Some(std::path::absolute(&audit_log).unwrap_or(audit_log));
+        self
+    }
+
+    /// See `Setting::audit_format`
+    pub fn audit_format(&mut self, audit_format: AuditFormat) -> &mut Self {
+        self.setting.audit_format = audit_format;
+        self
+    }
+
+    /// See `Setting::benchmark`
+    pub fn benchmark(&mut self, benchmark: bool) -> &mut Self {
+        self.setting.benchmark = benchmark;
+        self
+    }
+
+    /// See `Setting::signature_key`. A relative path is from the current directory, like
+    /// `audit_log`
+    pub fn signature_key(&mut self, signature_key: PathBuf) -> &mut Self {
+        self.setting.signature_key =
+            Some(std::path::absolute(&signature_key).unwrap_or(signature_key));
+        self
+    }
+
+    /// See `Setting::checkpoint_file`. A relative path is from the current directory, like
+    /// `audit_log`
+    pub fn checkpoint_file(&mut self, checkpoint_file: PathBuf) -> &mut Self {
+        self.setting.checkpoint_file =
+            Some(std::path::absolute(&checkpoint_file).unwrap_or(checkpoint_file));
+        self
+    }
+
+    /// See `Setting::checkpoint_interval`
+    pub fn checkpoint_interval(&mut self, checkpoint_interval: Duration) -> &mut Self {
+        self.setting.checkpoint_interval = checkpoint_interval;
+        self
+    }
+
+    /// See `Setting::resume`
+    pub fn resume(&mut self, resume: bool) -> &mut Self {
+        self.setting.resume = resume;
+        self
+    }
+
+    /// See `Setting::dry_run`
+    pub fn dry_run(&mut self, dry_run: bool) -> &mut Self {
+        self.setting.dry_run = dry_run;
+        self
+    }
+
+    /// See `Setting::notify`
+    pub fn notify(&mut self, notify: bool) -> &mut Self {
+        self.setting.notify = notify;
+        self
+    }
+
+    /// See `Setting::color`
+    pub fn color(&mut self, color: ColorMode) -> &mut Self {
+        self.setting.color = color;
+        self
+    }
+
+    /// See `Setting::hash_list_format`
+    pub fn hash_list_format(&mut self, hash_list_format: HashListFormat) -> &mut Self {
+        self.setting.hash_list_format = hash_list_format;
+        self
+    }
+
+    /// Check the fields and return the setting
+    pub fn build(&self) -> Result<Setting, SettingError> {
+        let setting = self.setting.clone();
+        if setting.block_size < MIN_BLOCK_SIZE {
+            return Err(SettingError::BlockSizeTooSmall(setting.block_size));
+        }
+        if setting.min_block_size < MIN_BLOCK_SIZE {
+            return Err(SettingError::MinBlockSizeTooSmall(setting.min_block_size));
+        }
+        if setting.threads == Some(0) {
+            return Err(SettingError::ZeroThreads);
+        }
+        if setting.rate_limit.is_some_and(|x| x.is_nan() || x <= 0.0) {
+            return Err(SettingError::InvalidRateLimit);
+        }
+        let cpu_limit = setting.cpu_limit.unwrap_or(1.0);
+        if cpu_limit.is_nan() || cpu_limit <= 0.0 || cpu_limit > 1.0 {
+            return Err(SettingError::InvalidCpuLimit);
+        }
+        if let (HashListFormat::Sfv, Some(x)) = (setting.hash_list_format, setting.algorithm) {
+            if x != HashAlgorithm::Crc32 {
+                return Err(SettingError::SfvAlgorithm(x));
+            }
+        }
+
+        Ok(setting)
+    }
+}
+
+/// Continue building from an existing setting, e.g. one from `config::Config::apply`
+impl From<Setting> for SettingBuilder {
+    fn from(setting: Setting) -> Self {
+        Self { setting }
+    }
+}
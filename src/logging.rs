@@ -0,0 +1,75 @@
+//! `tracing` output of the library, enabled with `--log-level` or `--log-file`
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing_subscriber::filter::LevelFilter;
+
+/// Most verbose events that are logged
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogLevel {
+    /// Everything
+    Trace,
+    /// Every block read
+    Debug,
+    /// The default with only `--log-file`
+    #[default]
+    Info,
+    /// Problems that don't fail a file
+    Warn,
+    /// Failed files and operations
+    Error,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(value: LogLevel) -> Self {
+        match value {
+            LogLevel::Trace => LevelFilter::TRACE,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Error => LevelFilter::ERROR,
+        }
+    }
+}
+
+/// Where the TUI logs without `--log-file`, as stderr would corrupt the terminal
+fn tui_log_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|x| x.join("md5check").join("debug.log"))
+}
+
+/// Install the global subscriber if `level` or `log_file` is set, at `LogLevel::Info` unless
+/// `level` is set. Logs are appended to `log_file`, or else written to stderr, or to
+/// `tui_log_path` with `tui`
+pub fn init_logging(level: Option<LogLevel>, log_file: Option<&Path>, tui: bool) -> Result<()> {
+    if level.is_none() && log_file.is_none() {
+        return Ok(());
+    }
+
+    let subscriber =
+        tracing_subscriber::fmt().with_max_level(LevelFilter::from(level.unwrap_or_default()));
+    let log_file = match log_file {
+        Some(x) => Some(x.to_path_buf()),
+        None if tui => {
+            Some(tui_log_path().ok_or_else(|| eyre!("Couldn't find the local data directory"))?)
+        }
+        None => None,
+    };
+    match log_file {
+        Some(path) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| eyre!("Couldn't open log file {path:?}: {e}"))?;
+            subscriber.with_writer(Mutex::new(file)).init();
+        }
+        None => subscriber.with_writer(std::io::stderr).init(),
+    }
+    Ok(())
+}
@@ -1,88 +1,741 @@
 mod cli;
-mod hash;
+mod logging;
+mod man;
 mod ui;
 
-use crate::cli::cli_mode;
+use crate::cli::{
+    append_mode, cli_mode, count_mode, cross_check_mode, delete_mode, diff_mode, dry_run_mode,
+    duplicates_mode, extract_mode, generate_mode, list_mode, merge_mode, profile_mode, sign_mode,
+    update_mode, ReportFormat,
+};
+use crate::logging::{init_logging, LogLevel};
 use crate::ui::App;
-use clap::Parser;
+use clap::{ArgGroup, CommandFactory, Parser, ValueHint};
+use clap_complete::Shell;
+use color_eyre::eyre::eyre;
 use color_eyre::{Report, Result};
-use std::path::PathBuf;
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::execute;
+use glob::Pattern;
+use md5check::audit::AuditFormat;
+use md5check::compression::Compression;
+use md5check::config::Config;
+use md5check::hash::{find_hash_lists, HashListFormat};
+use md5check::util::expand_path;
+use md5check::{
+    ColorMode, DuplicatePolicy, HashAlgorithm, MergePolicy, Setting, SettingBuilder, SettingError,
+    Verbosity,
+};
+use std::convert::Infallible;
+use std::io::{stdout, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::time::Duration;
 
-#[derive(Debug, Default, Clone)]
-struct Status {
-    filename: String,
-    file_hash: String,
-    expected_hash: String,
-    correct_num: usize,
-    incorrect_num: usize,
-    error_num: usize,
-}
-
-#[derive(Debug)]
-enum Message {
-    Incorrect(String),
-    Error(Report),
-    Completed(Duration),
-    Empty,
-}
+impl Args {
+    /// CLI flags override the config file, which overrides the defaults
+    fn into_setting(self, config: &Config) -> Result<Setting, SettingError> {
+        // Sequential unless `--parallel` is given
+        self.into_setting_from(
+            config,
+            Setting {
+                parallel: false,
+                ..Default::default()
+            },
+        )
+    }
 
-// TODO: Add core_num setting
-#[derive(Debug, Clone, Copy)]
-struct Setting {
-    parallel: bool,
-    sort: bool,
-    block_size: usize,
-}
+    /// Flags override `config`, which overrides `defaults`
+    fn into_setting_from(
+        self,
+        config: &Config,
+        defaults: Setting,
+    ) -> Result<Setting, SettingError> {
+        let mut builder = SettingBuilder::from(config.apply(defaults));
 
-impl Default for Setting {
-    fn default() -> Self {
-        Self {
-            parallel: true,
-            sort: false,
-            block_size: 8192,
+        if self.parallel {
+            builder.parallel(true);
         }
-    }
-}
-
-impl From<Args> for Setting {
-    fn from(mut value: Args) -> Self {
-        Self {
-            parallel: value.parallel,
-            sort: value.sort,
-            block_size: value.block_size,
+        if self.sort {
+            builder.sort(true);
+        }
+        if self.fail_fast {
+            builder.fail_fast(true);
+        }
+        if let Some(block_size) = self.block_size {
+            builder.block_size(block_size);
+        }
+        if let Some(algorithm) = self.algorithm {
+            builder.algorithm(algorithm);
+        }
+        if let Some(threads) = self.threads {
+            builder.threads(threads);
+        }
+        if self.no_parallel_auto {
+            builder.parallel_auto(false);
+        }
+        if self.mmap {
+            builder.mmap(true);
+        }
+        if let Some(mmap_threshold) = self.mmap_threshold {
+            builder.mmap_threshold(mmap_threshold);
+        }
+        if let Some(rate_limit) = self.rate_limit {
+            builder.rate_limit(rate_limit);
+        }
+        if let Some(cpu_limit) = self.cpu_limit {
+            builder.cpu_limit(cpu_limit);
+        }
+        if self.incremental {
+            builder.incremental(true);
+        }
+        for pattern in self.include {
+            builder.include(pattern);
+        }
+        for pattern in self.exclude {
+            builder.exclude(pattern);
+        }
+        builder.duplicate_policy(self.duplicates);
+        if self.strict {
+            builder.strict(true);
+        }
+        if self.warn {
+            builder.warn(true);
+        }
+        if self.notify {
+            builder.notify(true);
+        }
+        if self.no_color {
+            builder.color(ColorMode::Never);
+        } else if let Some(color) = self.color {
+            builder.color(color);
+        }
+        if let Some(hash_list_format) = self.hash_list_format {
+            builder.hash_list_format(hash_list_format);
+        }
+        if let Some(retries) = self.retries {
+            builder.retries(retries);
+        }
+        if self.check_extra {
+            builder.check_extra(true);
+        }
+        if self.preflight {
+            builder.preflight(true);
+        }
+        if self.case_sensitive {
+            builder.case_sensitive(true);
+        }
+        if self.case_insensitive_paths {
+            builder.case_insensitive_paths(true);
         }
+        if let Some(base_dir) = self.base_dir {
+            builder.base_dir(base_dir);
+        }
+        if let Some(audit_log) = self.audit_log {
+            builder.audit_log(audit_log);
+        }
+        if let (Some(key), true) = (self.key, self.verify_signature) {
+            builder.signature_key(key);
+        }
+        if let Some(checkpoint_file) = self.checkpoint_file {
+            builder.checkpoint_file(checkpoint_file);
+        }
+        if self.status {
+            builder.verbosity(Verbosity::Silent);
+        } else if self.quiet {
+            builder.verbosity(Verbosity::Quiet);
+        } else if self.verbose {
+            builder.verbosity(Verbosity::Verbose);
+        }
+
+        builder
+            .ignore_cache(self.ignore_cache)
+            .watch(self.watch)
+            .ignore_missing(self.ignore_missing)
+            .ignore_zero(self.ignore_zero)
+            .strict_zero(self.strict_zero)
+            .follow_symlinks(!self.no_follow_symlinks)
+            .hash_symlink_target(self.hash_symlink_target)
+            .audit_format(self.audit_format)
+            .benchmark(self.benchmark)
+            .checkpoint_interval(Duration::from_secs(self.checkpoint_interval))
+            .resume(self.resume)
+            .dry_run(self.dry_run)
+            .build()
     }
 }
 
 #[derive(Debug, Clone, Parser)]
-#[command(version, about)]
+#[command(group(ArgGroup::new("verbosity").args(["quiet", "verbose", "status"])))]
+#[command(version, about, after_help = after_help())]
 struct Args {
     #[arg(short, long)]
     parallel: bool,
     #[arg(short, long)]
     sort: bool,
-    #[arg(short, long, default_value_t = Setting::default().block_size)]
-    block_size: usize,
+    /// Stop at the first incorrect file or error
+    #[arg(long, alias = "failfast")]
+    fail_fast: bool,
+    /// Read buffer size in bytes, at least 512 [default: 8192]
+    #[arg(short, long)]
+    block_size: Option<usize>,
+    /// Hash lists to verify, one after the other, or `-` for stdin. The other modes take a single
+    /// hash list
+    #[arg(
+        short,
+        long = "file-path",
+        value_name = "FILE_PATH",
+        value_hint = ValueHint::FilePath,
+        num_args = 1..,
+        conflicts_with = "generate"
+    )]
+    file_paths: Vec<PathBuf>,
+    /// Generate a hash list for every file in this directory
+    #[arg(short, long, value_hint = ValueHint::DirPath)]
+    generate: Option<PathBuf>,
+    /// Compress the hash list generated with `--generate`, which is then only written once
+    /// complete. Hash lists ending in `.gz`, `.gzip`, `.zst` or `.zstd`, or starting with the
+    /// magic bytes of either, are always decompressed when read
+    #[arg(long, value_enum, value_name = "FORMAT", requires = "generate")]
+    compress: Option<Compression>,
+    /// Format of the hash list generated with `--generate`, also in the TUI. `sfv` hash lists are
+    /// always CRC-32 [default: gnu]
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with = "format")]
+    hash_list_format: Option<HashListFormat>,
+    /// Hash the files in this directory that aren't in the hash list from `--file-path` and add
+    /// them to it, in the same format
+    #[arg(
+        long,
+        value_name = "DIR",
+        value_hint = ValueHint::DirPath,
+        requires = "file_paths"
+    )]
+    append: Option<PathBuf>,
+    /// Hash every file in the hash list from `--file-path` and replace the hashes of the files
+    /// that changed. Missing files are kept
+    #[arg(
+        long,
+        requires = "file_paths",
+        conflicts_with_all = ["append", "watch", "check_extra", "incremental"]
+    )]
+    update: bool,
+    /// Remove the entries of files that don't exist from the hash list from `--file-path`,
+    /// keeping the order of the others. Can be combined with `--update`
+    #[arg(
+        long,
+        requires = "file_paths",
+        conflicts_with_all = ["append", "watch", "check_extra"]
+    )]
+    delete: bool,
+    /// Only print what `--delete` would remove, without changing the hash list. When verifying,
+    /// only check that the listed files exist and can be read, and print how much would be read
+    #[arg(
+        long,
+        requires = "file_paths",
+        conflicts_with_all = ["generate", "merge", "diff", "cross_check", "watch", "append"]
+    )]
+    dry_run: bool,
+    /// Combine these hash lists into the one at `--output`, with an entry per file and the format
+    /// of the first one
+    #[arg(
+        long,
+        num_args = 2..,
+        value_name = "FILE",
+        requires = "output",
+        conflicts_with_all = ["file_paths", "generate"]
+    )]
+    merge: Vec<PathBuf>,
+    /// Compare two hash lists and print the files added (`+`), removed (`-`) and changed (`~`) in
+    /// the second one, in the `--format` format. Unchanged files (`=`) are printed when verbose
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["OLD", "NEW"],
+        conflicts_with_all = ["file_paths", "generate", "merge"]
+    )]
+    diff: Vec<PathBuf>,
+    /// Check that the files listed in both hash lists have the same hash in each, then verify the
+    /// files of both. Files with conflicting hashes make the run fail. Listed files are relative
+    /// to the directory of the first one
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["LIST1", "LIST2"],
+        conflicts_with_all = ["file_paths", "generate", "merge", "diff", "benchmark"]
+    )]
+    cross_check: Vec<PathBuf>,
+    /// With `--diff`, report added files with the hash of a removed file as renamed (`R`), and
+    /// added files with the hash of a file in both hash lists as copied (`C`)
+    #[arg(long, requires = "diff")]
+    detect_renames: bool,
+    /// Print the files of the hash list from `--file-path` that have the same hash, a group per
+    /// hash
+    #[arg(
+        long,
+        requires = "file_paths",
+        conflicts_with_all = ["append", "update", "delete", "watch", "check_extra"]
+    )]
+    find_duplicates: bool,
+    /// Print the number of entries and skipped lines of the hash list from `--file-path` and its
+    /// algorithm, without verifying. As JSON with `--format json`
+    #[arg(
+        long,
+        requires = "file_paths",
+        conflicts_with_all = ["append", "update", "delete", "find_duplicates", "profile_block_size", "watch"]
+    )]
+    count: bool,
+    /// Print the paths of the hash list from `--file-path`, one per line, without verifying. With
+    /// `--verbose`, as `<hash>  <path>` lines, and with `--format json`, as a JSON array
+    #[arg(
+        long,
+        requires = "file_paths",
+        conflicts_with_all = ["append", "update", "delete", "find_duplicates", "profile_block_size", "watch", "count"]
+    )]
+    list: bool,
+    /// Write the entries of the hash list from `--file-path` whose path matches this glob pattern
+    /// to `--output` or stdout, in the same format. Returns 1 if none match
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        requires = "file_paths",
+        conflicts_with_all = ["append", "update", "delete", "find_duplicates", "profile_block_size", "watch", "count", "list"]
+    )]
+    extract: Option<Pattern>,
+    /// Only list files of at least this many bytes with `--find-duplicates`, using their size on
+    /// disk
+    #[arg(long, requires = "find_duplicates")]
+    min_size: Option<u64>,
+    /// Hash a file with block sizes from 4 KiB to 256 KiB and recommend the fastest. The file is
+    /// the first one of the hash list, or `--sample-file`
+    #[arg(
+        long,
+        conflicts_with_all = ["generate", "merge", "diff", "append", "update", "delete", "find_duplicates"]
+    )]
+    profile_block_size: bool,
+    /// File to hash with `--profile-block-size`
+    #[arg(long, value_name = "FILE", requires = "profile_block_size")]
+    sample_file: Option<PathBuf>,
+    /// Write the block size recommended by `--profile-block-size` to the config file
+    #[arg(long, requires = "profile_block_size")]
+    save_block_size: bool,
+    /// Which entry `--merge` keeps for a file in several hash lists
+    #[arg(long, value_enum, default_value_t)]
+    merge_keep: MergePolicy,
+    /// Where to write the generated hash list (defaults to stdout), or the verification report
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Only print incorrect files and errors to stderr, without a progress bar
     #[arg(short, long)]
-    file_path: PathBuf,
+    quiet: bool,
+    /// Print a line for every file
+    #[arg(short, long)]
+    verbose: bool,
+    /// Print nothing, not even errors, like `md5sum --status`. The exit status is 0 if every file
+    /// is correct, 1 if any is incorrect and 2 on errors
+    #[arg(
+        long,
+        requires = "file_paths",
+        conflicts_with_all = ["format", "output", "warn", "preflight", "watch", "benchmark", "dry_run", "generate", "merge", "diff", "cross_check", "profile_block_size", "list", "count", "extract", "sign", "append", "find_duplicates", "update", "delete"]
+    )]
+    status: bool,
+    /// Format of the verification report. Written to stdout if `--output` isn't set, except for
+    /// plain text. `json-manifest` generates or verifies a JSON manifest instead, and `csv`
+    /// generates the hash list as CSV
+    #[arg(long, value_enum, default_value_t)]
+    format: ReportFormat,
+    /// Hash algorithm of the hash list: md5, sha1, sha224, sha256, sha384, sha512, blake2b[:<bytes>],
+    /// blake3 or crc32. Detected from the digest length if not set
+    #[arg(short, long)]
+    algorithm: Option<HashAlgorithm>,
+    /// Number of threads used in parallel mode. Defaults to 1 if the files are on a rotational
+    /// disk (Linux only) and to the number of physical cores otherwise
+    #[arg(short, long)]
+    threads: Option<usize>,
+    /// Without `--threads`, use rayon's default of a thread per logical CPU instead of picking
+    /// the number from the disk and CPU
+    #[arg(long)]
+    no_parallel_auto: bool,
+    /// Memory map files instead of reading them in blocks. Files over `--mmap-threshold` are
+    /// always memory mapped
+    #[arg(long)]
+    mmap: bool,
+    /// Size in bytes from which files are memory mapped [default: 67108864]
+    #[arg(long)]
+    mmap_threshold: Option<u64>,
+    /// Read at most this many MB/s from disk, shared by every thread, to leave I/O for others
+    #[arg(long, value_name = "MB/S")]
+    rate_limit: Option<f64>,
+    /// Fraction of the time each hashing thread runs, from 0 to 1, e.g. 0.5 to use about half of
+    /// the cores. Threads sleep between blocks, so this is approximate
+    #[arg(long, value_name = "FRACTION")]
+    cpu_limit: Option<f32>,
+    /// Skip files whose modification time and size haven't changed since the last run, using
+    /// a `.md5check_cache` file next to the hash list
+    #[arg(long)]
+    incremental: bool,
+    /// Hash every file with `--incremental`, but still update the cache
+    #[arg(long)]
+    ignore_cache: bool,
+    /// Keep running after the first pass and re-verify files when they are modified, until
+    /// Ctrl+C is pressed
+    #[arg(long)]
+    watch: bool,
+    /// Don't fail because of files missing from disk. They are still reported
+    #[arg(long)]
+    ignore_missing: bool,
+    /// Skip empty files, as any empty file matches the hash of an empty input
+    #[arg(long, conflicts_with = "generate")]
+    ignore_zero: bool,
+    /// Fail files whose expected hash is the hash of an empty input (e.g. MD5
+    /// d41d8cd98f00b204e9800998ecf8427e), which is rarely intended
+    #[arg(long, conflicts_with_all = ["generate", "ignore_zero"])]
+    strict_zero: bool,
+    /// Hash the files symlinks point to. This is the default
+    #[arg(long, overrides_with = "no_follow_symlinks")]
+    follow_symlinks: bool,
+    /// Fail listed symlinks instead of hashing the files they point to
+    #[arg(long, overrides_with = "follow_symlinks")]
+    no_follow_symlinks: bool,
+    /// Hash the target path of symlinks, as returned by `readlink`, instead of the files they
+    /// point to
+    #[arg(long, conflicts_with = "no_follow_symlinks")]
+    hash_symlink_target: bool,
+    /// Only verify files matching this glob pattern. Can be repeated
+    #[arg(long, value_name = "PATTERN", conflicts_with = "generate")]
+    include: Vec<Pattern>,
+    /// Don't verify files matching this glob pattern, even if included. Can be repeated
+    #[arg(long, value_name = "PATTERN", conflicts_with = "generate")]
+    exclude: Vec<Pattern>,
+    /// What to do with files listed more than once in the hash list
+    #[arg(long, value_enum, default_value_t)]
+    duplicates: DuplicatePolicy,
+    /// Fail on hash list lines that can't be parsed instead of skipping them, to catch truncated
+    /// or corrupted hash lists
+    #[arg(long, conflicts_with = "generate")]
+    strict: bool,
+    /// Print how many hash list lines couldn't be parsed after the summary, even with `--quiet`,
+    /// like `md5sum --warn`
+    #[arg(long, conflicts_with_all = ["generate", "strict"])]
+    warn: bool,
+    /// Show a desktop notification once the files are verified or hashed, also in the TUI
+    #[arg(long)]
+    notify: bool,
+    /// When to color errors and missing files. `auto` colors them if stderr is a terminal, unless
+    /// `NO_COLOR` is set or `TERM` is `dumb`. `--color` alone means `always` [default: auto]
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "always"
+    )]
+    color: Option<ColorMode>,
+    /// Same as `--color=never`
+    #[arg(long, conflicts_with = "color")]
+    no_color: bool,
+    /// Retry files this many times after a transient read error (connection reset, timed out),
+    /// e.g. on network filesystems. The delay starts at 100 ms and doubles [default: 0]
+    #[arg(long)]
+    retries: Option<u32>,
+    /// After verifying, report files in the directory of the hash list that aren't listed in it,
+    /// respecting `--include`/`--exclude`. Extra files fail the run like incorrect files
+    #[arg(long, conflicts_with = "generate")]
+    check_extra: bool,
+    /// Check that every listed file exists before hashing and list the missing ones. Unless
+    /// `--ignore-missing` is set, asks whether to continue
+    #[arg(long, conflicts_with = "generate")]
+    preflight: bool,
+    /// Compare hashes case-sensitively, so uppercase hashes in the hash list don't match
+    #[arg(long, conflicts_with = "generate")]
+    case_sensitive: bool,
+    /// Directory the relative paths of the hash list are from. Defaults to the directory of the
+    /// hash list
+    #[arg(
+        long,
+        value_name = "DIR",
+        value_hint = ValueHint::DirPath,
+        value_parser = parse_expanded_path,
+        conflicts_with_all = ["generate", "merge", "diff"]
+    )]
+    base_dir: Option<PathBuf>,
+    /// When a listed file doesn't exist, look for one differing only in case, for hash lists made
+    /// on Windows. Always on on Windows
+    #[arg(long, conflicts_with_all = ["generate", "merge", "diff"])]
+    case_insensitive_paths: bool,
+    /// Append every verification result to this file, with the time, process, user, hashes and
+    /// duration. Separate from `--output` and never truncated
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        value_parser = parse_expanded_path,
+        conflicts_with_all = ["generate", "merge", "diff"]
+    )]
+    audit_log: Option<PathBuf>,
+    /// Format of `--audit-log`
+    #[arg(long, value_enum, default_value_t, requires = "audit_log")]
+    audit_format: AuditFormat,
+    /// Time every file and print a summary of the throughputs at the end: median, 95th
+    /// percentile, slowest and fastest files. `--output` and `--format json` apply to the summary
+    /// instead of the report
+    #[arg(long, conflicts_with_all = ["generate", "merge", "diff"])]
+    benchmark: bool,
+    /// Sign the hash list from `--file-path` with the key from `--key`, adding a
+    /// `# HMAC-SHA256: <hex>` line at the end. An existing signature is replaced
+    #[arg(
+        long,
+        requires_all = ["file_paths", "key"],
+        conflicts_with_all = ["append", "update", "delete", "find_duplicates", "profile_block_size", "watch", "count", "list", "extract", "verify_signature"]
+    )]
+    sign: bool,
+    /// Check the signature added by `--sign` with the key from `--key` before verifying any file
+    #[arg(long, requires = "key", conflicts_with_all = ["generate", "merge", "diff"])]
+    verify_signature: bool,
+    /// 32-byte binary key file for `--sign` and `--verify-signature`
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        value_parser = parse_expanded_path
+    )]
+    key: Option<PathBuf>,
+    /// Save the verified files to this file every `--checkpoint-interval` seconds, so an
+    /// interrupted run can be continued with `--resume`. Deleted once the run is complete
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        value_parser = parse_expanded_path,
+        conflicts_with_all = ["generate", "merge", "diff", "cross_check"]
+    )]
+    checkpoint_file: Option<PathBuf>,
+    /// Seconds between two saves of `--checkpoint-file`
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 30,
+        requires = "checkpoint_file"
+    )]
+    checkpoint_interval: u64,
+    /// Skip the files already verified in `--checkpoint-file`, counting their saved results
+    #[arg(long, requires = "checkpoint_file")]
+    resume: bool,
+    /// Log what the program is doing at this level, to stderr or in the TUI to
+    /// `~/.local/share/md5check/debug.log`
+    #[arg(long, value_enum, value_name = "LEVEL")]
+    log_level: Option<LogLevel>,
+    /// Append logs to this file instead. Logs at `info` unless `--log-level` is set
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        value_parser = parse_expanded_path
+    )]
+    log_file: Option<PathBuf>,
+    /// Don't capture the mouse in the TUI
+    #[arg(long)]
+    no_mouse: bool,
+    /// Config file with the default settings. Defaults to `$MD5CHECK_CONFIG` or
+    /// `~/.config/md5check/config.toml`
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Print the completion script for this shell and exit
+    #[arg(long, value_enum, value_name = "SHELL", exclusive = true)]
+    generate_completions: Option<Shell>,
+    /// Write the man page `md5check.1` to the current directory and exit
+    #[arg(long, exclusive = true)]
+    generate_man_page: bool,
+}
+
+/// Hand-written, as clap doesn't generate examples. Shown after `--help` and in the man page
+const EXAMPLES: &[(&str, &str)] = &[
+    ("md5check", "Start the TUI"),
+    ("md5check -f MD5SUMS -p", "Verify a hash list in parallel"),
+    (
+        "md5check -g . -o MD5SUMS",
+        "Generate a hash list of the current directory",
+    ),
+    (
+        "md5check -f MD5SUMS --update",
+        "Replace the hashes of the files that changed",
+    ),
+    ("md5check --diff old.md5 new.md5", "Compare two hash lists"),
+];
+
+const EXIT_STATUS: &str = "Exit status is 0 if all files are correct, 1 if any file is \
+                           incorrect, 2 if any file couldn't be read or is missing, 130 if \
+                           interrupted by Ctrl+C or SIGTERM";
+
+fn after_help() -> String {
+    let examples: Vec<_> = EXAMPLES
+        .iter()
+        .map(|(example, description)| format!("  {example:<34}{description}"))
+        .collect();
+    format!("Examples:\n{}\n\n{EXIT_STATUS}", examples.join("\n"))
 }
 
-fn main() -> Result<()> {
-    match Args::try_parse() {
-        Ok(settings) => {
-            cli_mode(settings.file_path.clone(), settings.into())
+/// Paths in `--flag=~/dir` form aren't expanded by the shell
+fn parse_expanded_path(s: &str) -> Result<PathBuf, Infallible> {
+    Ok(expand_path(s))
+}
+
+/// The only hash list in the current directory named like one of `DEFAULT_HASH_LIST_NAMES`
+fn discover_hash_list() -> Result<PathBuf> {
+    let found = find_hash_lists(Path::new("."))?;
+    match found.as_slice() {
+        [x] => Ok(x.clone()),
+        [] => Err(eyre!(
+            "No hash list found in the current directory, specify one with --file-path"
+        )),
+        _ => {
+            let names: Vec<_> = found
+                .iter()
+                .filter_map(|x| Some(x.file_name()?.to_string_lossy()))
+                .collect();
+            Err(eyre!(
+                "Found several hash lists: {}. Specify one with --file-path",
+                names.join(", ")
+            ))
         }
-        Err(e) if e.kind() == clap::error::ErrorKind::DisplayHelp => {
-            eprintln!("{e}");
-            Ok(())
+    }
+}
+
+fn main() -> Result<ExitCode> {
+    let mut args = match Args::try_parse() {
+        Ok(x) => x,
+        // Prints help and version too
+        Err(e) => e.exit(),
+    };
+    if let Some(shell) = args.generate_completions {
+        let mut command = Args::command();
+        let name = command.get_name().to_string();
+        clap_complete::generate(shell, &mut command, name, &mut stdout());
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.generate_man_page {
+        let path = man::write_man_page(&std::env::current_dir()?)?;
+        eprintln!("Man page written to {path:?}");
+        return Ok(ExitCode::SUCCESS);
+    }
+    let config = Config::load(args.config.as_deref())?;
+    // Same checks as the modes below
+    let mut tui = args.merge.is_empty()
+        && args.diff.is_empty()
+        && args.cross_check.is_empty()
+        && args.generate.is_none()
+        && !args.profile_block_size
+        && args.file_paths.is_empty();
+    // Without a terminal to show the TUI, look for a hash list in the current directory
+    if tui && !stdout().is_terminal() {
+        args.file_paths = vec![discover_hash_list()?];
+        tui = false;
+    }
+    init_logging(args.log_level, args.log_file.as_deref(), tui)?;
+
+    let file_path = args.file_paths.first().cloned();
+    let single_list_mode = args.profile_block_size
+        || args.list
+        || args.count
+        || args.extract.is_some()
+        || args.sign
+        || args.append.is_some()
+        || args.find_duplicates
+        || args.update
+        || args.delete;
+    if args.file_paths.len() > 1 && single_list_mode {
+        return Err(eyre!("Several hash lists can only be verified"));
+    }
+    if args.file_paths.len() > 1 && args.checkpoint_file.is_some() {
+        return Err(eyre!(
+            "A checkpoint file can only be used with a single hash list"
+        ));
+    }
+
+    if !args.merge.is_empty() {
+        let (file_paths, output) = (args.merge.clone(), args.output.clone());
+        let output = output.expect("`--merge` requires `--output`");
+        let policy = args.merge_keep;
+        merge_mode(file_paths, output, args.into_setting(&config)?, policy)
+    } else if let [old_path, new_path] = args.diff.as_slice() {
+        let (old_path, new_path) = (old_path.clone(), new_path.clone());
+        let (output, format) = (args.output.clone(), args.format);
+        let renames = args.detect_renames;
+        let setting = args.into_setting(&config)?;
+        diff_mode(old_path, new_path, output, format, setting, renames)
+    } else if let [first, second] = args.cross_check.as_slice() {
+        let (first, second) = (first.clone(), second.clone());
+        let (output, format) = (args.output.clone(), args.format);
+        cross_check_mode(first, second, output, format, args.into_setting(&config)?)
+    } else if let Some(dir) = args.generate.clone() {
+        let (output, format, compression) = (args.output.clone(), args.format, args.compress);
+        let setting = args.into_setting(&config)?;
+        generate_mode(dir, output, format, compression, setting)
+    } else if args.profile_block_size {
+        let sample_file = args.sample_file.clone();
+        let save_to = args.save_block_size.then(|| args.config.clone());
+        profile_mode(file_path, sample_file, save_to, args.into_setting(&config)?)
+    } else if let (Some(file_path), true) = (file_path.clone(), args.list) {
+        let format = args.format;
+        list_mode(file_path, format, args.into_setting(&config)?)
+    } else if let (Some(file_path), true) = (file_path.clone(), args.count) {
+        let format = args.format;
+        count_mode(file_path, format, args.into_setting(&config)?)
+    } else if let (Some(file_path), Some(pattern)) = (file_path.clone(), args.extract.clone()) {
+        let output = args.output.clone();
+        extract_mode(file_path, pattern, output, args.into_setting(&config)?)
+    } else if let (Some(file_path), true) = (file_path.clone(), args.sign) {
+        let key = args.key.clone().expect("`--sign` requires `--key`");
+        sign_mode(file_path, key)
+    } else if let (Some(file_path), Some(dir)) = (file_path.clone(), args.append.clone()) {
+        append_mode(file_path, dir, args.into_setting(&config)?)
+    } else if let (Some(file_path), true) = (file_path.clone(), args.find_duplicates) {
+        let min_size = args.min_size;
+        duplicates_mode(file_path, min_size, args.into_setting(&config)?)
+    } else if let (Some(file_path), true) = (file_path.clone(), args.update) {
+        let (delete, dry_run) = (args.delete, args.dry_run);
+        update_mode(file_path, args.into_setting(&config)?, delete, dry_run)
+    } else if let (Some(file_path), true) = (file_path.clone(), args.delete) {
+        let dry_run = args.dry_run;
+        delete_mode(file_path, args.into_setting(&config)?, dry_run)
+    } else if let (false, true) = (args.file_paths.is_empty(), args.dry_run) {
+        let file_paths = args.file_paths.clone();
+        dry_run_mode(file_paths, args.into_setting(&config)?)
+    } else if !args.file_paths.is_empty() {
+        let (file_paths, output) = (args.file_paths.clone(), args.output.clone());
+        let (format, status) = (args.format, args.status);
+        let result = args
+            .into_setting(&config)
+            .map_err(Report::from)
+            .and_then(|setting| cli_mode(file_paths, output, format, setting));
+        match result {
+            // Errors too are only told by the exit status
+            Err(_) if status => Ok(ExitCode::from(2)),
+            result => result,
         }
-        _ => {
-            color_eyre::install()?;
-            let mut term = ratatui::init();
-            let app_result = App::default().run(&mut term);
-            ratatui::restore();
-            app_result
+    } else {
+        color_eyre::install()?;
+        let mouse = !args.no_mouse && config.mouse.unwrap_or(true);
+        // Parallel by default, it can be toggled with `p`
+        let setting = args.into_setting_from(&config, Setting::default())?;
+        if let Some(base_dir) = setting.base_dir.as_ref().filter(|x| !x.is_dir()) {
+            return Err(eyre!("Path is not directory: {base_dir:?}"));
+        }
+        let mut app = App::new(setting);
+
+        let mut term = ratatui::init();
+        let _ = ui::save_terminal_title();
+        let app_result = if mouse {
+            execute!(stdout(), EnableMouseCapture)
+                .map_err(Report::from)
+                .and_then(|_| app.run(&mut term))
+        } else {
+            app.run(&mut term)
+        };
+        if mouse {
+            let _ = execute!(stdout(), DisableMouseCapture);
         }
+        let _ = ui::restore_terminal_title();
+        ratatui::restore();
+        app_result.map(|_| ExitCode::SUCCESS)
     }
 }
@@ -0,0 +1,78 @@
+//! Man page generated from the `--help` text, with `--generate-man-page`
+
+use crate::{Args, EXAMPLES, EXIT_STATUS};
+use clap::CommandFactory;
+use clap_mangen::roff::{bold, roman, Roff};
+use clap_mangen::Man;
+use color_eyre::Result;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Files read or written outside of the arguments, with what they are for
+const FILES: &[(&str, &str)] = &[
+    (
+        "~/.config/md5check/config.toml",
+        "Default settings, unless $MD5CHECK_CONFIG or --config is set",
+    ),
+    (
+        "~/.local/share/md5check/recent.json",
+        "Recent hash lists of the TUI",
+    ),
+    (
+        "~/.local/share/md5check/debug.log",
+        "Logs of the TUI with --log-level",
+    ),
+    (
+        ".md5check_cache",
+        "Next to the hash list, used by --incremental",
+    ),
+];
+
+const SEE_ALSO: &[&str] = &["md5sum(1)", "sha256sum(1)", "b2sum(1)", "b3sum(1)"];
+
+/// Write `md5check.1` to `dir`. Returns its path
+pub fn write_man_page(dir: &Path) -> Result<PathBuf> {
+    // Examples and exit status get their own sections instead
+    let command = Args::command().after_help(None::<&str>);
+    let man = Man::new(command);
+    let path = dir.join(man.get_filename());
+
+    let mut writer = BufWriter::new(File::create(&path)?);
+    man.render_title(&mut writer)?;
+    man.render_name_section(&mut writer)?;
+    man.render_synopsis_section(&mut writer)?;
+    man.render_description_section(&mut writer)?;
+    man.render_options_section(&mut writer)?;
+    extra_sections().to_writer(&mut writer)?;
+    man.render_version_section(&mut writer)?;
+    writer.flush()?;
+
+    Ok(path)
+}
+
+fn extra_sections() -> Roff {
+    let mut roff = Roff::default();
+
+    roff.control("SH", ["EXAMPLES"]);
+    for (example, description) in EXAMPLES {
+        roff.control("TP", []);
+        roff.text([bold(*example)]);
+        roff.text([roman(*description)]);
+    }
+
+    roff.control("SH", ["EXIT STATUS"]);
+    roff.text([roman(EXIT_STATUS)]);
+
+    roff.control("SH", ["FILES"]);
+    for (file, description) in FILES {
+        roff.control("TP", []);
+        roff.text([bold(*file)]);
+        roff.text([roman(*description)]);
+    }
+
+    roff.control("SH", ["SEE ALSO"]);
+    roff.text([roman(SEE_ALSO.join(", "))]);
+
+    roff
+}
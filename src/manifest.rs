@@ -0,0 +1,110 @@
+//! JSON manifests: hash lists with the size, modification time and permissions of each file
+
+use crate::hash::{
+    write_lines_atomic, HashAlgorithm, HashError, HashList, HashListFormat, ParseWarning,
+};
+use crate::reader::{HashListReader, JsonManifestReader, ParseError};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A file of a JSON manifest, which is an array of these
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path as listed, like in a hash list
+    pub path: String,
+    /// Hex encoded digest
+    pub hash: String,
+    /// Algorithm name as accepted by `--algorithm`, e.g. `sha256`
+    pub algorithm: String,
+    /// Size in bytes
+    pub size: u64,
+    /// Modification time in RFC 3339 UTC, e.g. `2024-01-31T12:00:00Z`
+    pub mtime: String,
+    /// Octal permissions, e.g. `644`. Outside Unix, only tells read-only files apart
+    pub mode: String,
+}
+
+impl ManifestEntry {
+    /// Entry listed as `path`, with the metadata of the file at `file_path`
+    pub fn new(
+        path: String,
+        hash: String,
+        algorithm: HashAlgorithm,
+        file_path: &Path,
+    ) -> io::Result<Self> {
+        let metadata = fs::metadata(file_path)?;
+        let mtime = DateTime::<Utc>::from(metadata.modified()?);
+
+        Ok(Self {
+            path,
+            hash,
+            algorithm: algorithm.name(),
+            size: metadata.len(),
+            mtime: mtime.to_rfc3339_opts(SecondsFormat::Secs, true),
+            mode: file_mode(&metadata),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+
+    format!("{:o}", metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(metadata: &fs::Metadata) -> String {
+    let mode = if metadata.permissions().readonly() {
+        "444"
+    } else {
+        "644"
+    };
+    mode.to_string()
+}
+
+/// Write `entries` as a pretty-printed JSON array, like `write_hash_list_atomic`
+pub fn write_manifest_atomic(path: &Path, entries: &[ManifestEntry]) -> Result<(), HashError> {
+    write_lines_atomic(path, [manifest_json(entries)])
+}
+
+/// `entries` as a pretty-printed JSON array
+pub fn manifest_json(entries: &[ManifestEntry]) -> String {
+    serde_json::to_string_pretty(entries).expect("Manifest entries are serializable")
+}
+
+/// Read the JSON manifest at `file_path` as a hash list with `JsonManifestReader`, to verify it like
+/// any other. Entries that don't match their algorithm are skipped with a `ParseWarning::Malformed`
+/// numbered by entry
+pub fn manifest_parser(file_path: &Path) -> Result<HashList, HashError> {
+    let content = fs::read_to_string(file_path).map_err(|e| HashError::from_read(file_path, e))?;
+
+    let mut hash_list = HashList {
+        format: HashListFormat::JsonManifest,
+        ..HashList::default()
+    };
+    for entry in JsonManifestReader::new(&content).entries() {
+        match entry {
+            Ok(pair) => {
+                if hash_list.pairs.is_empty() {
+                    hash_list.algorithm = pair.algorithm();
+                }
+                hash_list.pairs.push(pair);
+            }
+            Err(ParseError::Malformed { line, content }) => hash_list
+                .warnings
+                .push(ParseWarning::Malformed { line, content }),
+            Err(ParseError::Invalid { line, content }) => {
+                return Err(HashError::MalformedHashList { line, content })
+            }
+        }
+    }
+
+    if hash_list.pairs.is_empty() {
+        return Err(HashError::EmptyHashList);
+    }
+    Ok(hash_list)
+}
@@ -0,0 +1,60 @@
+//! Desktop notification at the end of a run, see `Setting::notify`. Sent with `notify-rust`, and
+//! skipped on platforms it doesn't support
+
+use crate::VerificationOutcome;
+#[cfg(any(unix, windows))]
+use notify_rust::Notification;
+
+/// Title of the notification
+pub const NOTIFICATION_TITLE: &str = "md5check finished";
+
+/// Shown in the notification where it can be sent inline, which is only with D-Bus
+#[cfg(all(unix, not(target_os = "macos")))]
+const ICON: &[u8] = include_bytes!("../images/icon.png");
+
+/// Body of the notification for `outcome`
+pub fn notification_body(outcome: &VerificationOutcome) -> String {
+    let errors = outcome.missing_num + outcome.error_num;
+    if outcome.incorrect_num == 0 && errors == 0 {
+        format!("All {} files OK", outcome.correct_num)
+    } else {
+        format!(
+            "FAILED: {} mismatches, {errors} errors",
+            outcome.incorrect_num
+        )
+    }
+}
+
+/// Notify that the run ended with `outcome`. Waits for the notification to be sent, so the TUI
+/// calls it from another thread. Failing to notify is ignored, as the result is reported anyway
+pub fn notify_completed(outcome: &VerificationOutcome) {
+    let body = notification_body(outcome);
+    tracing::debug!(body, "Sending notification");
+    send(&body);
+}
+
+#[cfg(any(unix, windows))]
+fn send(body: &str) {
+    let mut notification = Notification::new();
+    notification
+        .appname("md5check")
+        .summary(NOTIFICATION_TITLE)
+        .body(body);
+    #[cfg(all(unix, not(target_os = "macos")))]
+    if let Some(icon) = icon() {
+        notification.image_data(icon);
+    }
+    if let Err(e) = notification.show() {
+        tracing::debug!(%e, "Couldn't send notification");
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn send(_body: &str) {}
+
+/// `ICON` decoded, as D-Bus takes raw pixels
+#[cfg(all(unix, not(target_os = "macos")))]
+fn icon() -> Option<notify_rust::Image> {
+    let icon = image::load_from_memory_with_format(ICON, image::ImageFormat::Png).ok()?;
+    notify_rust::Image::try_from(icon.into_rgba8()).ok()
+}
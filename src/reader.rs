@@ -0,0 +1,322 @@
+//! Hash lists read entry by entry, in the format sniffed from their first line, see
+//! `HashListReader`
+
+use crate::hash::{HashAlgorithm, HashListFormat, HashPair};
+use crate::manifest::ManifestEntry;
+use regex::Regex;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// A line of a hash list that isn't an entry
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseError {
+    /// A line that couldn't be parsed, which can be skipped
+    #[error("Malformed line {line}: {content}")]
+    Malformed {
+        /// 1-based line number, or entry number in JSON manifests
+        line: usize,
+        /// The line itself
+        content: String,
+    },
+    /// An `# Algorithm: <name>` header or a JSON manifest that couldn't be parsed, which stops
+    /// the parsing
+    #[error("Invalid hash list at line {line}: {content}")]
+    Invalid {
+        /// 1-based line number
+        line: usize,
+        /// The line itself, or the JSON error
+        content: String,
+    },
+}
+
+/// Entries of a hash list in a single format
+pub trait HashListReader {
+    /// Format sniffed from the first line
+    fn format(&self) -> HashListFormat;
+
+    /// Entries in order, with their line number. Blank lines and comments are skipped
+    fn entries(&mut self) -> Box<dyn Iterator<Item = Result<HashPair, ParseError>> + '_>;
+
+    /// `#` and SFV `;` comment lines read so far, not counting the algorithm header
+    fn comment_num(&self) -> usize {
+        0
+    }
+}
+
+impl dyn HashListReader {
+    /// Reader for the format of the first line of `reader` that isn't blank or a comment. Lines
+    /// in another line format are still read, as some tools mix them. `algorithm` is the one of
+    /// every entry, otherwise it's detected like in `hash::parse_hash_list`
+    pub fn auto_detect(
+        mut reader: impl BufRead,
+        algorithm: Option<HashAlgorithm>,
+    ) -> io::Result<Box<dyn HashListReader>> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+
+        let patterns = LinePatterns::new();
+        let first_line = content
+            .lines()
+            .map(|x| x.trim_end_matches('\r'))
+            .find(|x| !x.trim().is_empty() && !x.starts_with('#') && !x.starts_with(';'))
+            .unwrap_or_default();
+        // SFV file names may start with `[` too, so JSON comes last
+        let format = [
+            HashListFormat::Gnu,
+            HashListFormat::Bsd,
+            HashListFormat::Sfv,
+        ]
+        .into_iter()
+        .find(|&x| patterns.regex(x).is_match(first_line))
+        .or_else(|| {
+            first_line
+                .trim_start()
+                .starts_with('[')
+                .then_some(HashListFormat::JsonManifest)
+        });
+
+        let lines = LineSource {
+            content,
+            patterns,
+            algorithm,
+            comment_num: 0,
+        };
+        Ok(match format {
+            Some(HashListFormat::Bsd) => Box::new(BsdHashListReader { lines }),
+            Some(HashListFormat::Sfv) => Box::new(SfvHashListReader { lines }),
+            Some(HashListFormat::JsonManifest) => Box::new(JsonManifestReader::new(&lines.content)),
+            _ => Box::new(GnuHashListReader { lines }),
+        })
+    }
+}
+
+/// `<hash>  <file>` lines, from `md5sum` and co
+pub struct GnuHashListReader {
+    lines: LineSource,
+}
+
+impl HashListReader for GnuHashListReader {
+    fn format(&self) -> HashListFormat {
+        HashListFormat::Gnu
+    }
+
+    fn entries(&mut self) -> Box<dyn Iterator<Item = Result<HashPair, ParseError>> + '_> {
+        self.lines.entries(HashListFormat::Gnu)
+    }
+
+    fn comment_num(&self) -> usize {
+        self.lines.comment_num
+    }
+}
+
+/// `<TAG> (<file>) = <hash>` lines, from `md5sum --tag` and BSD `md5`
+pub struct BsdHashListReader {
+    lines: LineSource,
+}
+
+impl HashListReader for BsdHashListReader {
+    fn format(&self) -> HashListFormat {
+        HashListFormat::Bsd
+    }
+
+    fn entries(&mut self) -> Box<dyn Iterator<Item = Result<HashPair, ParseError>> + '_> {
+        self.lines.entries(HashListFormat::Bsd)
+    }
+
+    fn comment_num(&self) -> usize {
+        self.lines.comment_num
+    }
+}
+
+/// `<file> <CRC32>` lines, with `;` comments
+pub struct SfvHashListReader {
+    lines: LineSource,
+}
+
+impl HashListReader for SfvHashListReader {
+    fn format(&self) -> HashListFormat {
+        HashListFormat::Sfv
+    }
+
+    fn entries(&mut self) -> Box<dyn Iterator<Item = Result<HashPair, ParseError>> + '_> {
+        self.lines.entries(HashListFormat::Sfv)
+    }
+
+    fn comment_num(&self) -> usize {
+        self.lines.comment_num
+    }
+}
+
+/// A JSON array of `ManifestEntry`, numbered by entry as it may be on a single line. The metadata
+/// isn't read. Entries with an unknown algorithm fall back to detecting it from the digest length.
+/// Their line numbers aren't lines of the file, so they can't be changed in place
+pub struct JsonManifestReader {
+    manifest: Result<Vec<ManifestEntry>, ParseError>,
+}
+
+impl JsonManifestReader {
+    /// Manifest in `content`. Invalid JSON is returned by `HashListReader::entries`
+    pub fn new(content: &str) -> Self {
+        let manifest = serde_json::from_str(content).map_err(|e| ParseError::Invalid {
+            line: e.line(),
+            content: e.to_string(),
+        });
+        Self { manifest }
+    }
+}
+
+impl HashListReader for JsonManifestReader {
+    fn format(&self) -> HashListFormat {
+        HashListFormat::JsonManifest
+    }
+
+    fn entries(&mut self) -> Box<dyn Iterator<Item = Result<HashPair, ParseError>> + '_> {
+        let entries = match &self.manifest {
+            Ok(x) => x,
+            Err(e) => return Box::new(std::iter::once(Err(e.clone()))),
+        };
+        Box::new(entries.iter().enumerate().map(|(idx, entry)| {
+            let algorithm = HashAlgorithm::from_str(&entry.algorithm).ok();
+            let pair = HashPair::new(&entry.path, &entry.hash, algorithm)
+                .or_else(|| HashPair::new(&entry.path, &entry.hash, None));
+            match pair {
+                Some(mut pair) => {
+                    pair.line = idx + 1;
+                    pair.format = HashListFormat::JsonManifest;
+                    Ok(pair)
+                }
+                None => Err(ParseError::Malformed {
+                    line: idx + 1,
+                    content: entry.path.clone(),
+                }),
+            }
+        }))
+    }
+}
+
+/// Regexes of the line formats and the algorithm header
+struct LinePatterns {
+    gnu: Regex,
+    bsd: Regex,
+    sfv: Regex,
+    header: Regex,
+}
+
+impl LinePatterns {
+    fn new() -> Self {
+        Self {
+            gnu: Regex::new(r"^([0-9A-Fa-f]+) [ *](.+)$").expect("Valid regex"),
+            bsd: Regex::new(r"^([0-9A-Za-z-]+) \((.+)\) = ([0-9A-Fa-f]+)$").expect("Valid regex"),
+            sfv: Regex::new(r"^(.+) ([0-9A-Fa-f]{8})$").expect("Valid regex"),
+            header: Regex::new(r"(?i)^#\s*algorithm:\s*(\S+)\s*$").expect("Valid regex"),
+        }
+    }
+
+    /// Only for the line formats
+    fn regex(&self, format: HashListFormat) -> &Regex {
+        match format {
+            HashListFormat::Bsd => &self.bsd,
+            HashListFormat::Sfv => &self.sfv,
+            _ => &self.gnu,
+        }
+    }
+
+    /// Entry of `line` in the first line format it looks like, `format` first. `None` if it
+    /// doesn't look like any, or the digest doesn't match the algorithm
+    fn parse(
+        &self,
+        line: &str,
+        format: HashListFormat,
+        algorithm: Option<HashAlgorithm>,
+    ) -> Option<HashPair> {
+        let others = [
+            HashListFormat::Gnu,
+            HashListFormat::Bsd,
+            HashListFormat::Sfv,
+        ]
+        .into_iter()
+        .filter(|&x| x != format);
+        let (format, caps) = std::iter::once(format)
+            .chain(others)
+            .find_map(|x| self.regex(x).captures(line).map(|caps| (x, caps)))?;
+
+        let mut pair = match format {
+            HashListFormat::Bsd => HashAlgorithm::from_bsd_tag(&caps[1])
+                .filter(|x| algorithm.is_none_or(|y| y == *x))
+                .and_then(|x| HashPair::new(&caps[2], &caps[3], Some(x))),
+            HashListFormat::Sfv => {
+                // SFV hashes are usually uppercase
+                let hash = caps[2].to_ascii_lowercase();
+                algorithm
+                    .is_none_or(|x| x == HashAlgorithm::Crc32)
+                    .then(|| HashPair::new(&caps[1], &hash, Some(HashAlgorithm::Crc32)))
+                    .flatten()
+            }
+            _ => HashPair::new(&caps[2], &caps[1], algorithm),
+        }?;
+        pair.format = format;
+        Some(pair)
+    }
+}
+
+/// Lines of a hash list in a line format, see `LinePatterns::parse`
+struct LineSource {
+    content: String,
+    patterns: LinePatterns,
+    /// From the header, if it wasn't given
+    algorithm: Option<HashAlgorithm>,
+    comment_num: usize,
+}
+
+impl LineSource {
+    fn entries(
+        &mut self,
+        format: HashListFormat,
+    ) -> Box<dyn Iterator<Item = Result<HashPair, ParseError>> + '_> {
+        let Self {
+            content,
+            patterns,
+            algorithm,
+            comment_num,
+        } = self;
+        Box::new(content.lines().enumerate().filter_map(move |(idx, line)| {
+            // `lines` only strips `\r` before a `\n`, so a last line without one would keep it
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() {
+                return None;
+            }
+
+            if let Some(caps) = patterns.header.captures(line) {
+                return match HashAlgorithm::from_str(&caps[1]) {
+                    Ok(x) => {
+                        // Needed to tell apart algorithms with the same digest length
+                        algorithm.get_or_insert(x);
+                        None
+                    }
+                    Err(_) => Some(Err(ParseError::Invalid {
+                        line: idx + 1,
+                        content: line.to_string(),
+                    })),
+                };
+            }
+            // `#` comments and SFV comments, checked first as they could look like SFV lines
+            if line.starts_with('#') || line.starts_with(';') {
+                *comment_num += 1;
+                return None;
+            }
+
+            let entry = match patterns.parse(line, format, *algorithm) {
+                Some(mut pair) => {
+                    pair.line = idx + 1;
+                    Ok(pair)
+                }
+                None => Err(ParseError::Malformed {
+                    line: idx + 1,
+                    content: line.to_string(),
+                }),
+            };
+            Some(entry)
+        }))
+    }
+}
@@ -0,0 +1,51 @@
+//! Recently opened hash lists of the TUI
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Number of hash lists remembered, one per digit key
+pub const MAX_RECENT_FILES: usize = 10;
+
+/// TUI state kept between sessions in `<data dir>/md5check/recent.json`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RecentState {
+    /// Most recent first
+    pub recent_files: Vec<PathBuf>,
+}
+
+impl RecentState {
+    fn state_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|x| x.join("md5check").join("recent.json"))
+    }
+
+    /// Missing or unreadable state is treated as empty
+    pub fn load() -> Self {
+        Self::state_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Move `path` to the front of the list
+    pub fn add(&mut self, path: &Path) {
+        self.recent_files.retain(|x| x != path);
+        self.recent_files.insert(0, path.to_path_buf());
+        self.recent_files.truncate(MAX_RECENT_FILES);
+    }
+
+    /// Written to a temporary file first, then renamed over the old state
+    pub fn save(&self) -> Result<()> {
+        let path = Self::state_path().ok_or_else(|| eyre!("No data directory found"))?;
+        let dir = path.parent().expect("State path is in a directory");
+        fs::create_dir_all(dir)?;
+
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temp_path, &path)?;
+
+        Ok(())
+    }
+}
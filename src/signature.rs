@@ -0,0 +1,81 @@
+//! HMAC-SHA256 signatures of hash lists, written as a `# HMAC-SHA256: <hex>` comment line at the
+//! end so the hash list can still be parsed as usual
+
+use crate::hash::{write_bytes_atomic, HashError, HashListFormat};
+use crate::reader::HashListReader;
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+use std::fs;
+use std::path::Path;
+
+/// Start of the signature line
+pub const SIGNATURE_PREFIX: &str = "# HMAC-SHA256: ";
+/// Length of a signing key in bytes
+pub const KEY_LEN: usize = 32;
+
+/// Read the binary key file at `path`, which must be `KEY_LEN` bytes long
+pub fn read_key(path: &Path) -> Result<[u8; KEY_LEN], HashError> {
+    let key = fs::read(path).map_err(|e| HashError::from_read(path, e))?;
+    key.as_slice()
+        .try_into()
+        .map_err(|_| HashError::InvalidKey { len: key.len() })
+}
+
+/// Content of the hash list without the signature line, and the signature if it has one. Only
+/// the last line can be the signature
+fn split_signature(content: &[u8]) -> (&[u8], Option<&[u8]>) {
+    let trimmed = content.strip_suffix(b"\n").unwrap_or(content);
+    let start = trimmed
+        .iter()
+        .rposition(|&x| x == b'\n')
+        .map_or(0, |x| x + 1);
+    match trimmed[start..].strip_prefix(SIGNATURE_PREFIX.as_bytes()) {
+        Some(signature) => (&content[..start], Some(signature.trim_ascii())),
+        None => (content, None),
+    }
+}
+
+fn hmac(key: &[u8; KEY_LEN], content: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(content);
+    mac
+}
+
+/// Sign the hash list at `path` with `key`, replacing its signature if it has one. The file is
+/// written like `write_hash_list_atomic`. Returns the hex encoded signature. Fails with
+/// `HashError::ManifestNotEditable` for JSON manifests, as the signature line would make them
+/// invalid JSON
+pub fn sign_hash_list(path: &Path, key: &[u8; KEY_LEN]) -> Result<String, HashError> {
+    let content = fs::read(path).map_err(|e| HashError::from_read(path, e))?;
+    let (content, _) = split_signature(&content);
+    let text = String::from_utf8_lossy(content);
+    let reader = <dyn HashListReader>::auto_detect(text.as_bytes(), None)
+        .map_err(|e| HashError::from_read(path, e))?;
+    if reader.format() == HashListFormat::JsonManifest {
+        return Err(HashError::ManifestNotEditable);
+    }
+
+    let mut signed = content.to_vec();
+    if !signed.is_empty() && !signed.ends_with(b"\n") {
+        signed.push(b'\n');
+    }
+    let signature = hex::encode(hmac(key, &signed).finalize().into_bytes());
+    signed.extend_from_slice(format!("{SIGNATURE_PREFIX}{signature}\n").as_bytes());
+
+    write_bytes_atomic(path, &signed)?;
+    Ok(signature)
+}
+
+/// Check the signature of the hash list at `path` against `key`. Fails with
+/// `HashError::InvalidSignature` if it's missing or doesn't match the rest of the content
+pub fn verify_signature(path: &Path, key: &[u8; KEY_LEN]) -> Result<(), HashError> {
+    let content = fs::read(path).map_err(|e| HashError::from_read(path, e))?;
+    let (content, Some(signature)) = split_signature(&content) else {
+        return Err(HashError::InvalidSignature);
+    };
+    let signature = hex::decode(signature).map_err(|_| HashError::InvalidSignature)?;
+
+    hmac(key, content)
+        .verify_slice(&signature)
+        .map_err(|_| HashError::InvalidSignature)
+}
@@ -0,0 +1,48 @@
+//! Detection of the hardware files are read on, see `Setting::parallel_auto`
+
+use std::path::Path;
+
+/// Thread count picked by `Setting::parallel_auto`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoThreads {
+    /// 1 on rotational disks, to avoid seeking between files, else the number of physical cores
+    pub threads: usize,
+    /// Whether the disk is rotational. `None` if unknown, which is always the case outside Linux
+    pub rotational: Option<bool>,
+}
+
+/// Pick a thread count for reading the files under `path`
+pub fn detect_threads(path: &Path) -> AutoThreads {
+    let rotational = is_rotational(path);
+    let threads = match rotational {
+        Some(true) => 1,
+        _ => num_cpus::get_physical(),
+    };
+    AutoThreads {
+        threads,
+        rotational,
+    }
+}
+
+/// Read from `/sys/dev/block/<major>:<minor>/queue/rotational`. Devices without a queue, like
+/// tmpfs or network filesystems, are unknown
+#[cfg(target_os = "linux")]
+fn is_rotational(path: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = std::fs::metadata(path).ok()?.dev();
+    // Same encoding as glibc's `major` and `minor`
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    let sys_dev = format!("/sys/dev/block/{major}:{minor}");
+    // Partitions have the queue of their disk in the parent directory
+    ["queue/rotational", "../queue/rotational"]
+        .iter()
+        .find_map(|x| std::fs::read_to_string(format!("{sys_dev}/{x}")).ok())
+        .map(|x| x.trim() == "1")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_rotational(_path: &Path) -> Option<bool> {
+    None
+}
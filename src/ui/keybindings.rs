@@ -0,0 +1,200 @@
+/// Which part of the TUI a key binding applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyContext {
+    /// Available everywhere
+    Global,
+    Idle,
+    Running,
+    /// Showing the report of `Setting::dry_run`
+    DryRun,
+    Explorer,
+    BaseDirExplorer,
+}
+
+pub struct KeyBinding {
+    pub context: KeyContext,
+    pub key: &'static str,
+    pub description: &'static str,
+}
+
+const fn binding(context: KeyContext, key: &'static str, description: &'static str) -> KeyBinding {
+    KeyBinding {
+        context,
+        key,
+        description,
+    }
+}
+
+/// Every key handled by `App::handle_key_event`, keep in sync when adding a binding
+pub const KEYBINDINGS: &[KeyBinding] = &[
+    binding(KeyContext::Global, "Ctrl+c", "Exit"),
+    binding(KeyContext::Global, "?", "Show/hide this help"),
+    binding(
+        KeyContext::Idle,
+        "n",
+        "Select a hash list with the file explorer",
+    ),
+    binding(
+        KeyContext::Idle,
+        "v",
+        "Get an absolute hash list path from the clipboard",
+    ),
+    binding(KeyContext::Idle, "1-9 0", "Select a recent hash list"),
+    binding(
+        KeyContext::Idle,
+        "b",
+        "Select the directory the listed files are relative to",
+    ),
+    binding(KeyContext::Idle, "Enter", "Run the verification"),
+    binding(
+        KeyContext::Idle,
+        "A",
+        "Toggle appending new files to the hash list on Enter",
+    ),
+    binding(KeyContext::Idle, "p", "Toggle parallel hashing"),
+    binding(KeyContext::Idle, "s", "Toggle sorting the hash list"),
+    binding(KeyContext::Idle, "f", "Toggle fail fast"),
+    binding(
+        KeyContext::Idle,
+        "V",
+        "Toggle logging correct files (Shift+v, as v pastes)",
+    ),
+    binding(
+        KeyContext::Idle,
+        "w",
+        "Toggle watching for changes after the run",
+    ),
+    binding(
+        KeyContext::Idle,
+        "P",
+        "Toggle checking for missing files before the run",
+    ),
+    binding(
+        KeyContext::Idle,
+        "d",
+        "Toggle only checking the files can be read on Enter",
+    ),
+    binding(
+        KeyContext::Idle,
+        "S",
+        "Toggle failing on malformed hash list lines",
+    ),
+    binding(KeyContext::Idle, "a", "Cycle the hash algorithm"),
+    binding(
+        KeyContext::Idle,
+        "i/x",
+        "Edit the include/exclude glob patterns",
+    ),
+    binding(
+        KeyContext::Idle,
+        "+/-",
+        "Increase/decrease the number of threads",
+    ),
+    binding(
+        KeyContext::Idle,
+        "r/R",
+        "Increase/decrease the retries on transient read errors",
+    ),
+    binding(
+        KeyContext::Idle,
+        "←/→",
+        "Decrease/increase the block size by 1 KiB",
+    ),
+    binding(
+        KeyContext::Idle,
+        "Ctrl+←/→",
+        "Decrease/increase the block size by 1 MiB",
+    ),
+    binding(
+        KeyContext::Idle,
+        "Shift+←/→",
+        "Decrease/increase the block size by 1 GiB",
+    ),
+    binding(KeyContext::Idle, "↑/↓", "Select a queued hash list"),
+    binding(KeyContext::Idle, "Delete", "Remove the queued hash list"),
+    binding(KeyContext::Idle, "Alt+↑/↓", "Move the queued hash list"),
+    binding(
+        KeyContext::Idle,
+        "Click",
+        "Toggle parallel/sort/fail fast/verbose/watch/preflight/dry run/strict",
+    ),
+    binding(KeyContext::Running, "↑/↓ Wheel", "Scroll through logs"),
+    binding(KeyContext::Running, "PgUp/PgDn", "Scroll by a page"),
+    binding(
+        KeyContext::Running,
+        "Home/End",
+        "Jump to the newest/oldest message",
+    ),
+    binding(
+        KeyContext::Running,
+        "a",
+        "Auto-scroll to the newest message",
+    ),
+    binding(
+        KeyContext::Running,
+        "Click",
+        "Jump to a position on the log scrollbar",
+    ),
+    binding(KeyContext::Running, "/", "Search the log"),
+    binding(KeyContext::Running, "n/N", "Go to the next/previous match"),
+    binding(KeyContext::Running, "Esc", "Clear the search"),
+    binding(KeyContext::Running, "e", "Export the log to a file"),
+    binding(KeyContext::Running, "Delete", "Remove the queued hash list"),
+    binding(KeyContext::Running, "Alt+↑/↓", "Move the queued hash list"),
+    binding(KeyContext::Running, "Space", "Pause/resume"),
+    binding(
+        KeyContext::Running,
+        "q",
+        "Cancel and go back, or stop watching and exit",
+    ),
+    binding(
+        KeyContext::Running,
+        "Enter",
+        "Go back after the run is completed",
+    ),
+    binding(KeyContext::DryRun, "↑/↓ Wheel", "Scroll through logs"),
+    binding(KeyContext::DryRun, "Enter/Esc", "Go back"),
+    binding(KeyContext::Explorer, "↑/↓ j/k", "Move the selection"),
+    binding(
+        KeyContext::Explorer,
+        "←/→ h/l",
+        "Go to the parent/selected directory",
+    ),
+    binding(KeyContext::Explorer, "Enter Click", "Select the file"),
+    binding(
+        KeyContext::Explorer,
+        "g",
+        "Generate a hash list for the current directory",
+    ),
+    binding(KeyContext::Explorer, "a", "Add the file to the queue"),
+    binding(KeyContext::Explorer, "c", "Cancel the selection"),
+    binding(KeyContext::BaseDirExplorer, "↑/↓ j/k", "Move the selection"),
+    binding(
+        KeyContext::BaseDirExplorer,
+        "←/→ h/l",
+        "Go to the parent/selected directory",
+    ),
+    binding(
+        KeyContext::BaseDirExplorer,
+        "Enter Click",
+        "Open the selected directory",
+    ),
+    binding(
+        KeyContext::BaseDirExplorer,
+        "s",
+        "Use the current directory as the base directory",
+    ),
+    binding(
+        KeyContext::BaseDirExplorer,
+        "r",
+        "Reset to the directory of the hash list",
+    ),
+    binding(KeyContext::BaseDirExplorer, "c", "Cancel the selection"),
+];
+
+/// Bindings usable in `context`, including the global ones
+pub fn bindings_for(context: KeyContext) -> impl Iterator<Item = &'static KeyBinding> {
+    KEYBINDINGS
+        .iter()
+        .filter(move |x| x.context == KeyContext::Global || x.context == context)
+}
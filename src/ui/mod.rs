@@ -1,25 +1,92 @@
+pub mod keybindings;
 pub mod widgets;
 
-use crate::{
-    hash::{hash_list_parser, prepare_hashing},
-    Message, Setting, Status,
+use md5check::{
+    hash::{
+        append_to_hash_list, dry_run_hash_list, find_missing_files, find_unlisted_files,
+        generate_hash_list, hash_list_parser, list_files, prepare_hashing, write_hash_list_atomic,
+        HashListFormat, HashPair,
+    },
+    notification::notify_completed,
+    HashList, Message, Setting, Status, Verbosity, VerificationOutcome, DRAIN_TIMEOUT,
 };
 use std::cmp::min;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{stdout, BufWriter, Write};
 
 use color_eyre::eyre::{eyre, Context, Report, Result};
 use crossbeam::channel;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+    MouseEventKind,
+};
+use crossterm::{execute, style::Print, terminal::SetTitle};
 use parking_lot::RwLock;
 use ratatui::layout::Flex;
 use ratatui::prelude::*;
 use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget, DefaultTerminal, Frame};
-use ratatui_explorer::{FileExplorer, Theme};
-use std::path::PathBuf;
+use ratatui_explorer::{FileExplorer, Input, Theme};
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
-use crate::hash::StatusWrapper;
-// TODO: Get time estimate
+use std::time::{Duration, Instant};
+use md5check::hash::{HashAlgorithm, HashError, Pause, StatusWrapper, STDIN_PATH};
+use md5check::recent::RecentState;
+use md5check::system::AutoThreads;
+use md5check::util::expand_path;
+use crate::ui::keybindings::{bindings_for, KeyContext};
+use crate::ui::widgets::{
+    DetectedAlgorithm, LogSearch, PatternEdit, PatternField, SettingHitboxes,
+};
+use glob::Pattern;
+use itertools::Itertools;
+
+/// How long the partial results stay on screen after cancelling
+const CANCELLED_DISPLAY_TIME: Duration = Duration::from_secs(2);
+/// How long the log export notification stays on screen
+const NOTIFICATION_DISPLAY_TIME: Duration = Duration::from_secs(2);
+
+/// Push the current terminal title onto the xterm title stack
+pub(crate) fn save_terminal_title() -> std::io::Result<()> {
+    execute!(stdout(), Print("\x1b[22;0t"))
+}
+
+/// Pop the title saved by [`save_terminal_title`]
+pub(crate) fn restore_terminal_title() -> std::io::Result<()> {
+    execute!(stdout(), Print("\x1b[23;0t"))
+}
+
+/// Run waiting for the user to continue after the preflight found missing files
+struct PendingRun {
+    hash_list: Vec<HashPair>,
+    missing: Vec<String>,
+}
+
+/// Hash list of the queue that was verified before the current run, with its log
+pub struct FinishedList {
+    pub path: PathBuf,
+    pub outcome: VerificationOutcome,
+    messages: Vec<Message>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunMode {
+    Verify,
+    Generate {
+        output: PathBuf,
+        format: HashListFormat,
+        algorithm: HashAlgorithm,
+    },
+    /// Hashing the files missing from `hash_list`, added to it at the end
+    Append {
+        hash_list: PathBuf,
+        format: HashListFormat,
+        algorithm: HashAlgorithm,
+    },
+}
 
 pub struct App {
     hash_status: Arc<RwLock<Status>>,
@@ -30,13 +97,53 @@ pub struct App {
     selected_list: PathBuf,
     selected_idx: usize,
     showing_explorer: bool,
+    /// Picks `Setting::base_dir`, only directories are selected
+    base_dir_explorer: FileExplorer,
+    showing_base_dir_explorer: bool,
+    showing_help: bool,
+    help_scroll_offset: usize,
     running: bool,
+    run_mode: RunMode,
+    /// Run `RunMode::Append` instead of verifying on Enter
+    append_selected: bool,
+    pause: Arc<Pause>,
+    cancelled: Arc<AtomicBool>,
+    cancelled_at: Option<Instant>,
+    start_time: Instant,
+    /// Duration of the last completed run, shown until the next one starts
+    completed_duration: Option<Duration>,
     log_scroll_offset: u16,
+    /// Stick to the newest message, which is at the top of the log
+    log_auto_scroll: bool,
+    log_search: Option<LogSearch>,
+    pattern_edit: Option<PatternEdit>,
+    pending_run: Option<PendingRun>,
+    /// Showing the log of a dry run, until <Enter> or <Esc> goes back
+    showing_dry_run: bool,
+    /// From the last parsed hash list, cleared when another one is selected
+    detected_algorithm: Option<DetectedAlgorithm>,
+    /// Picked for the files of the last run, cleared when another hash list is selected
+    auto_threads: Option<AutoThreads>,
+    /// Hash lists verified one after another once the current run completes
+    queue: VecDeque<PathBuf>,
+    /// Queued hash list removed by `Delete` and moved by `Alt+Up`/`Alt+Down`
+    queue_idx: usize,
+    /// Queued hash lists verified so far, cleared when a run is started with <Enter>
+    finished: Vec<FinishedList>,
+    /// Path of the exported log or the error, and when it was exported
+    export_notification: Option<(Result<PathBuf>, Instant)>,
     message_rx: Option<channel::Receiver<Message>>,
     messages: Vec<Message>,
     entered_empty: bool,
     error: Option<Report>,
+    recent: RecentState,
     exit: bool,
+    /// Last rendered areas, for mouse support
+    setting_hitboxes: SettingHitboxes,
+    bottom_area: Cell<Rect>,
+    log_scrollbar_area: Cell<Rect>,
+    /// Last title written to the terminal, to only write it when it changes
+    terminal_title: String,
 }
 
 impl Default for App {
@@ -44,9 +151,18 @@ impl Default for App {
         let cwd = std::env::current_dir().unwrap(); //TODO: Make this better
         let theme = Theme::default()
             .add_default_title()
-            .with_title_bottom(|_| "Press <Enter> to select file. Press <c> to cancel".into());
+            .with_title_bottom(|_| {
+                "Press <Enter> to select file, <a> to add it to the queue, <g> to generate a hash list for this directory. Press <c> to cancel".into()
+            });
         let mut file_explorer = FileExplorer::with_theme(theme).unwrap(); //TODO: Make this better
         file_explorer.set_cwd(&cwd).unwrap();
+        let theme = Theme::default()
+            .add_default_title()
+            .with_title_bottom(|_| {
+                "Press <Enter> to open a directory, <s> to use this directory as the base directory. Press <r> to reset, <c> to cancel".into()
+            });
+        let mut base_dir_explorer = FileExplorer::with_theme(theme).unwrap();
+        base_dir_explorer.set_cwd(&cwd).unwrap();
 
         Self {
             hash_status: Default::default(),
@@ -56,19 +172,53 @@ impl Default for App {
             cwd,
             selected_list: PathBuf::new(),
             showing_explorer: false,
+            base_dir_explorer,
+            showing_base_dir_explorer: false,
+            showing_help: false,
+            help_scroll_offset: 0,
             selected_idx: 0,
             log_scroll_offset: 0,
+            log_auto_scroll: true,
+            log_search: None,
+            pattern_edit: None,
+            pending_run: None,
+            showing_dry_run: false,
+            detected_algorithm: None,
+            auto_threads: None,
+            queue: VecDeque::new(),
+            queue_idx: 0,
+            finished: vec![],
+            export_notification: None,
             running: false,
+            run_mode: RunMode::Verify,
+            append_selected: false,
+            pause: Default::default(),
+            cancelled: Default::default(),
+            cancelled_at: None,
+            start_time: Instant::now(),
+            completed_duration: None,
             entered_empty: false,
             message_rx: None,
             messages: vec![Message::Empty],
             error: None,
+            recent: RecentState::load(),
             exit: false,
+            setting_hitboxes: Default::default(),
+            bottom_area: Default::default(),
+            log_scrollbar_area: Default::default(),
+            terminal_title: String::new(),
         }
     }
 }
 
 impl App {
+    pub(crate) fn new(settings: Setting) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
+    }
+
     pub fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
         while !self.exit {
             if self.running {
@@ -76,9 +226,64 @@ impl App {
                     .message_rx
                     .clone()
                     .expect("If self.running, then self.message_rx exists");
-                let messages = message_rx.try_iter();
+                let messages = message_rx.try_iter().collect_vec();
+                let completed = messages.iter().find_map(|x| match x {
+                    Message::Completed(outcome) => Some(*outcome),
+                    _ => None,
+                });
+                if let Some(outcome) = completed {
+                    self.completed_duration = Some(outcome.elapsed);
+                    if self.settings.notify {
+                        // Sending waits on D-Bus, which shouldn't freeze the TUI
+                        std::thread::spawn(move || notify_completed(&outcome));
+                    }
+                }
+                if !self.completed() {
+                    self.hash_status.write().record_throughput_sample();
+                }
+                if messages.iter().any(|x| matches!(x, Message::Cancelled(_))) {
+                    self.cancelled_at = Some(Instant::now());
+                }
+                // Keep the same lines on screen as new ones are added on top
+                if self.log_auto_scroll {
+                    self.log_scroll_offset = 0;
+                } else {
+                    self.log_scroll_offset = min(
+                        self.log_scroll_offset.saturating_add(messages.len() as u16),
+                        (self.messages.len() + messages.len()).saturating_sub(2) as u16,
+                    );
+                }
                 self.messages.extend(messages);
+
+                if completed.is_some() {
+                    self.save_generated_entries();
+                    // The watcher keeps running after the first pass
+                    if !self.settings.watch {
+                        self.run_next_queued()?;
+                    }
+                }
+
+                if self
+                    .cancelled_at
+                    .is_some_and(|x| x.elapsed() >= CANCELLED_DISPLAY_TIME)
+                {
+                    self.reset();
+                }
+            }
+            if self
+                .export_notification
+                .as_ref()
+                .is_some_and(|(_, time)| time.elapsed() >= NOTIFICATION_DISPLAY_TIME)
+            {
+                self.export_notification = None;
             }
+
+            let title = self.terminal_title();
+            if title != self.terminal_title {
+                execute!(stdout(), SetTitle(&title))?;
+                self.terminal_title = title;
+            }
+
             terminal.draw(|frame| self.draw(frame))?;
             self.handle_events().wrap_err("handle events failed")?;
         }
@@ -92,43 +297,144 @@ impl App {
     fn handle_events(&mut self) -> Result<()> {
         if event::poll(Duration::from_micros(12500))? {
             let event = event::read()?;
+            let log_scroll_offset = self.log_scroll_offset;
             match event {
                 Event::Key(key_event)
                     if key_event == KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL) =>
                 {
-                    self.exit();
+                    self.drain_and_exit();
                     Ok(())
                 }
                 Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
                     self.handle_key_event(key_event)
                         .wrap_err_with(|| format!("handling key event failed:\n{key_event:#?}"))
                 }
+                Event::Mouse(mouse_event) => self
+                    .handle_mouse_event(mouse_event)
+                    .wrap_err_with(|| format!("handling mouse event failed:\n{mouse_event:#?}")),
                 _ => Ok(()),
             }?;
 
-            if self.showing_explorer {
+            if self.showing_explorer && !self.showing_help {
                 self.file_explorer.handle(&event)?
             }
+            if self.showing_base_dir_explorer && !self.showing_help {
+                self.base_dir_explorer.handle(&event)?
+            }
+
+            // Scrolling away from the newest message stops auto-scroll, going back resumes it
+            if self.log_scroll_offset != log_scroll_offset {
+                self.log_auto_scroll = self.log_scroll_offset == 0;
+            }
         }
 
         Ok(())
     }
 
     fn handle_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
-        if self.showing_explorer {
+        if self.showing_help {
+            match key_event.code {
+                KeyCode::Char('?') | KeyCode::Esc => self.showing_help = false,
+                KeyCode::Up => self.help_scroll_up(),
+                KeyCode::Down => self.help_scroll_down(),
+                _ => (),
+            }
+        } else if self.pending_run.is_some() {
+            match key_event.code {
+                KeyCode::Enter => {
+                    if let Some(pending_run) = self.pending_run.take() {
+                        self.start_run(pending_run.hash_list);
+                    }
+                }
+                KeyCode::Esc => {
+                    self.pending_run = None;
+                    self.messages = vec![Message::Empty];
+                }
+                _ => (),
+            }
+        } else if self.log_search.as_ref().is_some_and(|x| x.editing) {
+            self.handle_search_key_event(key_event);
+        } else if self.pattern_edit.is_some() {
+            self.handle_pattern_key_event(key_event);
+        } else if key_event.code == KeyCode::Char('?') {
+            self.showing_help = true;
+            self.help_scroll_offset = 0;
+        } else if self.showing_dry_run {
+            match key_event.code {
+                KeyCode::Up => self.log_scroll_up(),
+                KeyCode::Down => self.log_scroll_down(),
+                KeyCode::Enter | KeyCode::Esc => {
+                    self.showing_dry_run = false;
+                    self.messages = vec![Message::Empty];
+                    self.log_scroll_offset = 0;
+                }
+                _ => (),
+            }
+        } else if self.showing_base_dir_explorer {
+            match key_event.code {
+                KeyCode::Char('c') => self.showing_base_dir_explorer = false,
+                KeyCode::Char('s') => self.select_base_dir(),
+                KeyCode::Char('r') => {
+                    self.settings.base_dir = None;
+                    self.showing_base_dir_explorer = false;
+                }
+                _ => (),
+            }
+        } else if self.showing_explorer {
             match key_event.code {
                 KeyCode::Char('c') => self.cancel_selection()?,
                 KeyCode::Enter => self.selecting_file()?,
+                KeyCode::Char('a') => self.queue_selected_file(),
+                KeyCode::Char('g') => self.pre_generate()?,
                 _ => (),
             }
         } else if self.running {
             match key_event.code {
+                KeyCode::Up if key_event.modifiers == KeyModifiers::ALT => self.move_queued_up(),
+                KeyCode::Down if key_event.modifiers == KeyModifiers::ALT => {
+                    self.move_queued_down()
+                }
+                KeyCode::Delete => self.remove_queued(),
                 KeyCode::Up => {
                     self.log_scroll_up();
                 }
                 KeyCode::Down => {
                     self.log_scroll_down();
                 }
+                KeyCode::PageUp => {
+                    self.log_scroll_offset =
+                        self.log_scroll_offset.saturating_sub(self.log_page_size())
+                }
+                KeyCode::PageDown => self.log_scroll_down_by(self.log_page_size()),
+                KeyCode::Home => self.log_scroll_offset = 0,
+                KeyCode::End => self.log_scroll_offset = self.max_scroll_offset(),
+                KeyCode::Char('a') => {
+                    self.log_auto_scroll = true;
+                    self.log_scroll_offset = 0;
+                }
+                KeyCode::Char('/') => {
+                    self.log_search = Some(LogSearch {
+                        editing: true,
+                        ..Default::default()
+                    })
+                }
+                KeyCode::Char('n') if self.log_search.is_some() => self.next_match(),
+                KeyCode::Char('N') if self.log_search.is_some() => self.previous_match(),
+                KeyCode::Esc => self.log_search = None,
+                KeyCode::Char('e') => {
+                    self.export_notification = Some((self.export_log(), Instant::now()))
+                }
+                KeyCode::Char(' ') if !self.completed() => self.pause.toggle(),
+                KeyCode::Char('q') if !self.completed() => self.cancel(),
+                KeyCode::Char('q') if self.watching() => {
+                    self.cancelled.store(true, Ordering::Relaxed);
+                    self.exit();
+                }
+                KeyCode::Enter if self.completed() => {
+                    // Also stops the watcher, if any
+                    self.cancelled.store(true, Ordering::Relaxed);
+                    self.reset();
+                }
                 _ => (),
             }
         } else {
@@ -141,12 +447,38 @@ impl App {
 
             match key_event.code {
                 KeyCode::Char('n') => self.showing_explorer = true,
+                KeyCode::Char('b') => self.open_base_dir_explorer()?,
                 KeyCode::Char('v') => self.get_path_from_clipboard()?,
+                KeyCode::Char(c @ '0'..='9') => self.select_recent_file(c)?,
                 KeyCode::Char('p') => self.settings.parallel = !self.settings.parallel,
                 KeyCode::Char('s') => self.settings.sort = !self.settings.sort,
+                KeyCode::Char('f') => self.settings.fail_fast = !self.settings.fail_fast,
+                KeyCode::Char('V') => self.toggle_verbose(),
+                KeyCode::Char('w') => self.settings.watch = !self.settings.watch,
+                KeyCode::Char('P') => self.settings.preflight = !self.settings.preflight,
+                KeyCode::Char('d') => self.settings.dry_run = !self.settings.dry_run,
+                KeyCode::Char('S') => self.settings.strict = !self.settings.strict,
+                KeyCode::Char('a') => self.cycle_algorithm(),
+                KeyCode::Char('A') => self.append_selected = !self.append_selected,
+                KeyCode::Char('i') => self.edit_patterns(PatternField::Include),
+                KeyCode::Char('x') => self.edit_patterns(PatternField::Exclude),
+                KeyCode::Char('+') => self.increase_threads(),
+                KeyCode::Char('-') => self.decrease_threads(),
+                KeyCode::Char('r') => self.increase_retries(),
+                KeyCode::Char('R') => self.decrease_retries(),
+                KeyCode::Up if key_event.modifiers == KeyModifiers::ALT => self.move_queued_up(),
+                KeyCode::Down if key_event.modifiers == KeyModifiers::ALT => {
+                    self.move_queued_down()
+                }
+                KeyCode::Up => self.queue_idx = self.queue_idx.saturating_sub(1),
+                KeyCode::Down => {
+                    self.queue_idx = min(self.queue_idx + 1, self.queue.len().saturating_sub(1))
+                }
+                KeyCode::Delete => self.remove_queued(),
                 KeyCode::Left => self.decrease_block_size(block_size_step),
                 KeyCode::Right => self.increase_block_size(block_size_step),
                 KeyCode::Enter if !self.selected_list.to_string_lossy().is_empty() => {
+                    self.finished.clear();
                     self.pre_run()
                 }
                 KeyCode::Enter if self.selected_list.to_string_lossy().is_empty() => {
@@ -159,16 +491,308 @@ impl App {
         Ok(())
     }
 
+    fn handle_search_key_event(&mut self, key_event: KeyEvent) {
+        let Some(search) = self.log_search.as_mut() else {
+            return;
+        };
+
+        match key_event.code {
+            KeyCode::Char(c) => {
+                search.query.push(c);
+                self.jump_to_match(0);
+            }
+            KeyCode::Backspace => {
+                search.query.pop();
+                self.jump_to_match(0);
+            }
+            KeyCode::Enter => search.editing = false,
+            KeyCode::Esc => self.log_search = None,
+            _ => (),
+        }
+    }
+
+    fn edit_patterns(&mut self, field: PatternField) {
+        let patterns = match field {
+            PatternField::Include => &self.settings.include,
+            PatternField::Exclude => &self.settings.exclude,
+        };
+        self.pattern_edit = Some(PatternEdit {
+            field,
+            text: patterns.iter().join(", "),
+        });
+    }
+
+    fn handle_pattern_key_event(&mut self, key_event: KeyEvent) {
+        let Some(edit) = self.pattern_edit.as_mut() else {
+            return;
+        };
+
+        match key_event.code {
+            KeyCode::Char(c) => edit.text.push(c),
+            KeyCode::Backspace => {
+                edit.text.pop();
+            }
+            KeyCode::Enter => {
+                let patterns: Result<Vec<_>> = edit
+                    .text
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|x| !x.is_empty())
+                    .map(|x| Pattern::new(x).map_err(|e| eyre!("Invalid pattern {x:?}: {e}")))
+                    .collect();
+                // Stays open on an invalid pattern so it can be fixed
+                match patterns {
+                    Ok(patterns) => {
+                        match edit.field {
+                            PatternField::Include => self.settings.include = patterns,
+                            PatternField::Exclude => self.settings.exclude = patterns,
+                        }
+                        self.pattern_edit = None;
+                        self.error = None;
+                    }
+                    Err(e) => self.error = Some(e),
+                }
+            }
+            KeyCode::Esc => self.pattern_edit = None,
+            _ => (),
+        }
+    }
+
+    /// Indexes of the matching lines, in the order they are displayed
+    fn log_matches(&self) -> Vec<usize> {
+        let Some(search) = &self.log_search else {
+            return vec![];
+        };
+
+        self.messages
+            .iter()
+            .rev()
+            .positions(|x| search.is_match(x))
+            .collect_vec()
+    }
+
+    fn jump_to_match(&mut self, idx: usize) {
+        let matches = self.log_matches();
+        if let (Some(search), Some(&line)) = (self.log_search.as_mut(), matches.get(idx)) {
+            search.current = idx;
+            self.log_scroll_offset = line as u16;
+        }
+    }
+
+    fn next_match(&mut self) {
+        let len = self.log_matches().len();
+        if let Some(search) = &self.log_search {
+            self.jump_to_match((search.current + 1) % len.max(1));
+        }
+    }
+
+    fn previous_match(&mut self) {
+        let len = self.log_matches().len();
+        if let Some(search) = &self.log_search {
+            self.jump_to_match((search.current + len.saturating_sub(1)) % len.max(1));
+        }
+    }
+
+    fn handle_mouse_event(&mut self, mouse_event: MouseEvent) -> Result<()> {
+        let position = Position::new(mouse_event.column, mouse_event.row);
+        let in_log =
+            (self.running || self.showing_dry_run) && self.bottom_area.get().contains(position);
+
+        match mouse_event.kind {
+            MouseEventKind::ScrollUp if self.showing_help => self.help_scroll_up(),
+            MouseEventKind::ScrollDown if self.showing_help => self.help_scroll_down(),
+            MouseEventKind::ScrollUp if in_log => self.log_scroll_up(),
+            MouseEventKind::ScrollDown if in_log => self.log_scroll_down(),
+            MouseEventKind::Down(MouseButton::Left) if !self.showing_help => {
+                self.handle_click(position)?
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn handle_click(&mut self, position: Position) -> Result<()> {
+        if self.showing_base_dir_explorer {
+            // Same as <Enter>
+            if self.bottom_area.get().contains(position) {
+                self.base_dir_explorer.handle(Input::Right)?;
+            }
+        } else if self.showing_explorer {
+            // Same as <Enter>
+            if self.bottom_area.get().contains(position) {
+                self.selecting_file()?;
+                if self.showing_explorer {
+                    self.file_explorer.handle(Input::Right)?;
+                }
+            }
+        } else if self.running {
+            let track = self.log_scrollbar_area.get();
+            if track.contains(position) {
+                // Jump proportionally to where the track was clicked
+                let max_offset = self.max_scroll_offset();
+                let offset = (position.y - track.y) as u32 * max_offset as u32
+                    / track.height.saturating_sub(1).max(1) as u32;
+                self.log_scroll_offset = min(offset as u16, max_offset);
+            }
+        } else {
+            let hitboxes = &self.setting_hitboxes;
+            if hitboxes.parallel.get().contains(position) {
+                self.settings.parallel = !self.settings.parallel;
+            } else if hitboxes.sort.get().contains(position) {
+                self.settings.sort = !self.settings.sort;
+            } else if hitboxes.fail_fast.get().contains(position) {
+                self.settings.fail_fast = !self.settings.fail_fast;
+            } else if hitboxes.verbose.get().contains(position) {
+                self.toggle_verbose();
+            } else if hitboxes.watch.get().contains(position) {
+                self.settings.watch = !self.settings.watch;
+            } else if hitboxes.preflight.get().contains(position) {
+                self.settings.preflight = !self.settings.preflight;
+            } else if hitboxes.dry_run.get().contains(position) {
+                self.settings.dry_run = !self.settings.dry_run;
+            } else if hitboxes.strict.get().contains(position) {
+                self.settings.strict = !self.settings.strict;
+            }
+        }
+
+        Ok(())
+    }
+
     fn exit(&mut self) {
         self.exit = true;
     }
 
+    /// Cancel the run and give the files being hashed up to `DRAIN_TIMEOUT` to finish before
+    /// exiting, so the results so far are reported
+    fn drain_and_exit(&mut self) {
+        if self.running && !self.completed() {
+            self.cancel();
+            if let Some(message_rx) = &self.message_rx {
+                let deadline = Instant::now() + DRAIN_TIMEOUT;
+                while let Ok(message) = message_rx.recv_deadline(deadline) {
+                    if matches!(message, Message::Completed(_) | Message::Cancelled(_)) {
+                        break;
+                    }
+                }
+            }
+        }
+        self.exit();
+    }
+
+    fn completed(&self) -> bool {
+        self.messages
+            .iter()
+            .any(|x| matches!(x, Message::Completed(_) | Message::Cancelled(_)))
+    }
+
+    /// Progress of the run, for glancing at a background tab
+    fn terminal_title(&self) -> String {
+        if !self.running {
+            return "md5check".into();
+        }
+
+        let outcome = self.messages.iter().find_map(|x| match x {
+            Message::Completed(outcome) => Some(outcome),
+            _ => None,
+        });
+        match outcome {
+            Some(outcome) => {
+                let errors = outcome.incorrect_num
+                    + outcome.missing_num
+                    + outcome.error_num
+                    + outcome.extra_num;
+                let state = if errors == 0 { "Done" } else { "FAILED" };
+                format!("md5check - {state} ({errors} errors)")
+            }
+            None if self.cancelled.load(Ordering::Relaxed) => "md5check - Cancelled".into(),
+            None => {
+                let processed = self.hash_status.read().processed();
+                let percent = (processed * 100).checked_div(self.total_hash).unwrap_or(0);
+                format!("md5check - {percent}% ({processed}/{})", self.total_hash)
+            }
+        }
+    }
+
+    /// Watching for changes after a completed run
+    fn watching(&self) -> bool {
+        self.settings.watch
+            && self
+                .messages
+                .iter()
+                .any(|x| matches!(x, Message::Completed(_)))
+    }
+
+    fn cancel(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        // Paused workers would never see the cancellation
+        if self.pause.is_paused() {
+            self.pause.toggle();
+        }
+    }
+
+    /// Go back to the idle state after a cancelled run
+    fn reset(&mut self) {
+        self.running = false;
+        self.run_mode = RunMode::Verify;
+        self.total_hash = 0;
+        self.messages = vec![Message::Empty];
+        self.log_scroll_offset = 0;
+        self.log_auto_scroll = true;
+        self.log_search = None;
+        self.message_rx = None;
+        self.hash_status = Default::default();
+        self.pause = Default::default();
+        self.cancelled = Default::default();
+        self.cancelled_at = None;
+    }
+
+    fn key_context(&self) -> KeyContext {
+        if self.showing_base_dir_explorer {
+            KeyContext::BaseDirExplorer
+        } else if self.showing_explorer {
+            KeyContext::Explorer
+        } else if self.running {
+            KeyContext::Running
+        } else if self.showing_dry_run {
+            KeyContext::DryRun
+        } else {
+            KeyContext::Idle
+        }
+    }
+
+    fn help_scroll_up(&mut self) {
+        self.help_scroll_offset = self.help_scroll_offset.saturating_sub(1);
+    }
+
+    fn help_scroll_down(&mut self) {
+        let len = bindings_for(self.key_context()).count();
+        self.help_scroll_offset = min(self.help_scroll_offset + 1, len.saturating_sub(1));
+    }
+
     fn log_scroll_up(&mut self) {
         self.log_scroll_offset = self.log_scroll_offset.saturating_sub(1);
     }
 
     fn log_scroll_down(&mut self) {
-        self.log_scroll_offset = min(self.log_scroll_offset.saturating_add(1), self.messages.len() as u16 - 2);
+        self.log_scroll_down_by(1);
+    }
+
+    fn log_scroll_down_by(&mut self, lines: u16) {
+        self.log_scroll_offset = min(
+            self.log_scroll_offset.saturating_add(lines),
+            self.max_scroll_offset(),
+        );
+    }
+
+    /// Keeps at least one message on screen
+    fn max_scroll_offset(&self) -> u16 {
+        (self.messages.len() as u16).saturating_sub(2)
+    }
+
+    /// Number of log lines on screen, without the border and padding
+    fn log_page_size(&self) -> u16 {
+        self.bottom_area.get().height.saturating_sub(4).max(1)
     }
 
     fn increase_block_size(&mut self, step: usize) {
@@ -176,10 +800,50 @@ impl App {
     }
 
     fn decrease_block_size(&mut self, step: usize) {
-        self.settings.block_size = self.settings.block_size.saturating_sub(step);
-        if self.settings.block_size < 1024 {
-            self.settings.block_size = 1024;
-        }
+        // A block size already below the minimum, e.g. from `-b`, isn't raised
+        let min_block_size = min(self.settings.min_block_size, self.settings.block_size);
+        self.settings.block_size = self
+            .settings
+            .block_size
+            .saturating_sub(step)
+            .max(min_block_size);
+    }
+
+    fn increase_threads(&mut self) {
+        self.settings.threads = Some(self.settings.threads.map_or(1, |x| x.saturating_add(1)));
+    }
+
+    fn decrease_threads(&mut self) {
+        // Going below 1 thread goes back to automatic
+        self.settings.threads = self
+            .settings
+            .threads
+            .and_then(|x| x.checked_sub(1))
+            .filter(|&x| x > 0);
+    }
+
+    fn increase_retries(&mut self) {
+        self.settings.retries = self.settings.retries.saturating_add(1);
+    }
+
+    fn decrease_retries(&mut self) {
+        self.settings.retries = self.settings.retries.saturating_sub(1);
+    }
+
+    /// Correct files are only logged when verbose
+    fn toggle_verbose(&mut self) {
+        self.settings.verbosity = match self.settings.verbosity {
+            Verbosity::Verbose => Verbosity::Normal,
+            _ => Verbosity::Verbose,
+        };
+    }
+
+    fn cycle_algorithm(&mut self) {
+        self.settings.algorithm = match self.settings.algorithm {
+            None => Some(HashAlgorithm::default()),
+            Some(x) if x.next() == HashAlgorithm::default() => None,
+            Some(x) => Some(x.next()),
+        };
     }
 
     fn selecting_file(&mut self) -> Result<()> {
@@ -189,12 +853,85 @@ impl App {
             self.cwd = self.file_explorer.cwd().clone();
             self.selected_list = current.path().clone();
             self.selected_idx = self.file_explorer.selected_idx();
+            self.detected_algorithm = None;
+            self.auto_threads = None;
             self.error = None;
         }
 
         Ok(())
     }
 
+    /// Add the selected file to the queue, without changing the selected hash list
+    fn queue_selected_file(&mut self) {
+        let current = self.file_explorer.current();
+        if !current.is_dir() {
+            self.queue.push_back(current.path().clone());
+        }
+    }
+
+    fn remove_queued(&mut self) {
+        self.queue.remove(self.queue_idx);
+        self.queue_idx = min(self.queue_idx, self.queue.len().saturating_sub(1));
+    }
+
+    fn move_queued_up(&mut self) {
+        if self.queue_idx > 0 && self.queue_idx < self.queue.len() {
+            self.queue.swap(self.queue_idx, self.queue_idx - 1);
+            self.queue_idx -= 1;
+        }
+    }
+
+    fn move_queued_down(&mut self) {
+        if self.queue_idx + 1 < self.queue.len() {
+            self.queue.swap(self.queue_idx, self.queue_idx + 1);
+            self.queue_idx += 1;
+        }
+    }
+
+    /// Select the next queued hash list and verify it, after the current run completed
+    fn run_next_queued(&mut self) -> Result<()> {
+        let Some(path) = self.queue.pop_front() else {
+            return Ok(());
+        };
+        self.queue_idx = self.queue_idx.saturating_sub(1);
+
+        let messages = std::mem::take(&mut self.messages);
+        if let Some(outcome) = messages.iter().find_map(|x| match x {
+            Message::Completed(outcome) => Some(*outcome),
+            _ => None,
+        }) {
+            self.finished.push(FinishedList {
+                path: self.selected_list.clone(),
+                outcome,
+                messages,
+            });
+        }
+        self.reset();
+        self.select_path(path)?;
+        if self.error.is_none() {
+            self.pre_run();
+        }
+        Ok(())
+    }
+
+    /// Starts from the current base directory, or the directory of the hash list
+    fn open_base_dir_explorer(&mut self) -> Result<()> {
+        let dir = self.settings.base_dir.clone().unwrap_or(self.cwd.clone());
+        self.base_dir_explorer.set_cwd(dir)?;
+        self.showing_base_dir_explorer = true;
+        Ok(())
+    }
+
+    fn select_base_dir(&mut self) {
+        self.settings.base_dir = Some(self.base_dir_explorer.cwd().clone());
+        self.showing_base_dir_explorer = false;
+    }
+
+    /// Directory the relative paths of the selected hash list are from
+    fn files_dir(&self) -> &Path {
+        self.settings.base_dir.as_deref().unwrap_or(&self.cwd)
+    }
+
     fn cancel_selection(&mut self) -> Result<()> {
         self.showing_explorer = false;
         self.file_explorer.set_cwd(&self.cwd)?;
@@ -215,8 +952,30 @@ impl App {
             }
         };
 
-        let path = PathBuf::from(text.trim_matches('"'));
-        if !path.is_absolute() {
+        let path = expand_path(text.trim_matches('"'));
+        self.select_path(path)
+    }
+
+    /// `1`-`9` select the first nine recent files, `0` the tenth
+    fn select_recent_file(&mut self, digit: char) -> Result<()> {
+        let idx = match digit.to_digit(10) {
+            Some(0) => 9,
+            Some(x) => x as usize - 1,
+            None => return Ok(()),
+        };
+
+        match self.recent.recent_files.get(idx) {
+            Some(path) => self.select_path(path.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn select_path(&mut self, path: PathBuf) -> Result<()> {
+        if path == Path::new(STDIN_PATH) {
+            self.error = Some(eyre!(
+                "Reading the hash list from stdin is only supported in CLI mode"
+            ));
+        } else if !path.is_absolute() {
             self.error = Some(eyre!("Path is not absolute: {path:?}"));
         } else if !path.is_file() {
             self.error = Some(eyre!("Path is not file: {path:?}"));
@@ -229,38 +988,307 @@ impl App {
             self.file_explorer.set_cwd(&self.cwd)?;
             self.selected_list = path;
             self.selected_idx = 0;
+            self.detected_algorithm = None;
+            self.auto_threads = None;
             self.error = None;
         }
 
         Ok(())
     }
 
+    /// Write the log to a timestamped file in the current directory
+    fn export_log(&self) -> Result<PathBuf> {
+        let file_name = chrono::Local::now()
+            .format("md5check_%Y%m%d_%H%M%S.log")
+            .to_string();
+        let path = std::env::current_dir()?.join(file_name);
+
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for list in &self.finished {
+            writeln!(writer, "{}:", list.path.to_string_lossy())?;
+            write_log(&mut writer, &list.messages)?;
+            writeln!(writer)?;
+        }
+        if !self.finished.is_empty() {
+            writeln!(writer, "{}:", self.selected_list.to_string_lossy())?;
+        }
+        write_log(&mut writer, &self.messages)?;
+        writer.flush()?;
+
+        Ok(path)
+    }
+
+    /// Write the generated hash list, or add the new entries to the hash list when appending
+    fn save_generated_entries(&mut self) {
+        let mut entries = self
+            .messages
+            .iter()
+            .filter_map(|x| match x {
+                Message::Generated(path, hash) => Some((path.clone(), hash.clone())),
+                _ => None,
+            })
+            .collect_vec();
+        let (path, res) = match &self.run_mode {
+            RunMode::Verify => return,
+            RunMode::Generate {
+                output,
+                format,
+                algorithm,
+            } => {
+                let res = write_hash_list_atomic(output, *format, *algorithm, &entries);
+                (output, res)
+            }
+            RunMode::Append {
+                hash_list,
+                format,
+                algorithm,
+            } => {
+                // Hashed in parallel, so in any order
+                entries.sort();
+                let res = append_to_hash_list(hash_list, *format, *algorithm, &entries);
+                (hash_list, res)
+            }
+        };
+
+        if let Err(e) = res {
+            self.messages.push(Message::Error {
+                file_path: path.to_string_lossy().into_owned(),
+                error: e,
+            });
+        }
+    }
+
+    fn pre_generate(&mut self) -> Result<()> {
+        let dir = self.file_explorer.cwd().clone();
+        let algorithm = self.settings.generation_algorithm();
+        let output = dir.join(format!("{}SUMS", algorithm.bsd_tag()));
+
+        let file_list = match list_files(&dir) {
+            Ok(x) => x.into_iter().filter(|x| *x != output).collect_vec(),
+            Err(e) => {
+                self.error = Some(e);
+                return Ok(());
+            }
+        };
+        if file_list.is_empty() {
+            self.error = Some(eyre!("No files in directory: {dir:?}"));
+            return Ok(());
+        }
+
+        self.showing_explorer = false;
+        self.cwd = dir.clone();
+        self.error = None;
+        self.running = true;
+        self.completed_duration = None;
+        self.run_mode = RunMode::Generate {
+            output,
+            format: self.settings.hash_list_format,
+            algorithm,
+        };
+        self.total_hash = file_list.len();
+        self.start_time = Instant::now();
+
+        let status_clone = Arc::clone(&self.hash_status);
+        let settings = self.settings.clone();
+        let (tx, rx) = channel::unbounded();
+        self.message_rx = Some(rx);
+
+        let pause = Arc::clone(&self.pause);
+        let cancelled = Arc::clone(&self.cancelled);
+
+        thread::spawn(move || {
+            generate_hash_list(
+                &dir,
+                file_list,
+                &settings,
+                StatusWrapper::Status(status_clone),
+                tx,
+                &pause,
+                &cancelled,
+            )
+        });
+
+        Ok(())
+    }
+
     fn pre_run(&mut self) {
-        let res = hash_list_parser(&self.selected_list);
+        // Left over from the last dry run or failed parse
+        self.messages = vec![Message::Empty];
+        self.log_scroll_offset = 0;
+        self.log_auto_scroll = true;
+
+        let res = hash_list_parser(
+            &self.selected_list,
+            self.settings.algorithm,
+            self.settings.duplicate_policy,
+            self.settings.strict,
+        );
         let hash_list = match res {
             Ok(x) => x,
             Err(e) => {
-                self.error = Some(e);
+                if matches!(e, HashError::EmptyHashList) {
+                    self.detected_algorithm = Some(DetectedAlgorithm::Unknown);
+                }
+                self.error = Some(e.into());
                 return;
             }
         };
+        self.detected_algorithm = Some(DetectedAlgorithm::Known(hash_list.algorithm));
+        for warning in &hash_list.warnings {
+            self.messages.push(Message::Warning(warning.to_string()));
+        }
 
-        if let Err(e) = std::env::set_current_dir(&self.cwd) {
+        if let Err(e) = std::env::set_current_dir(self.files_dir()) {
             self.error = Some(e.into());
             return;
         }
+        self.auto_threads = self.settings.auto_threads(self.files_dir());
+
+        if self.append_selected {
+            self.start_append(hash_list);
+            return;
+        }
+        let hash_list = hash_list.pairs;
+
+        if self.settings.dry_run {
+            self.report_dry_run(&hash_list);
+            return;
+        }
+        if self.settings.preflight && !self.settings.ignore_missing {
+            let missing = find_missing_files(&hash_list, &self.settings);
+            if !missing.is_empty() {
+                self.pending_run = Some(PendingRun { hash_list, missing });
+                return;
+            }
+        }
+
+        self.start_run(hash_list);
+    }
+
+    /// Log the missing and unreadable files of `hash_list` and how much a run would read, without
+    /// starting it
+    fn report_dry_run(&mut self, hash_list: &[HashPair]) {
+        let report = dry_run_hash_list(hash_list, &self.settings);
+        self.messages
+            .extend(report.missing.into_iter().map(Message::Missing));
+        self.messages.extend(
+            report
+                .unreadable
+                .into_iter()
+                .map(|(file_path, error)| Message::Error { file_path, error }),
+        );
+        self.messages.push(Message::DryRun {
+            verifiable_num: report.verifiable_num,
+            total_bytes: report.total_bytes,
+        });
+        self.showing_dry_run = true;
+    }
+
+    /// Hash the files in the directory of the hash list (or the base directory) that aren't in it,
+    /// see `RunMode::Append`
+    fn start_append(&mut self, hash_list: HashList) {
+        let file_list =
+            match find_unlisted_files(&hash_list.pairs, &self.selected_list, self.files_dir()) {
+                Ok(x) => x,
+                Err(e) => {
+                    self.error = Some(e);
+                    return;
+                }
+            };
+
+        self.error = None;
         self.running = true;
+        self.completed_duration = None;
+        self.run_mode = RunMode::Append {
+            hash_list: self.selected_list.clone(),
+            format: hash_list.format,
+            algorithm: hash_list.algorithm,
+        };
+        self.total_hash = file_list.len();
+        self.start_time = Instant::now();
+
+        let status_clone = Arc::clone(&self.hash_status);
+        let mut settings = self.settings.clone();
+        settings.algorithm = Some(hash_list.algorithm);
+        let (tx, rx) = channel::unbounded();
+        self.message_rx = Some(rx);
+
+        let pause = Arc::clone(&self.pause);
+        let cancelled = Arc::clone(&self.cancelled);
+        // New entries are relative to the current directory, like the listed ones
+        let root = self.files_dir().to_path_buf();
+        thread::spawn(move || {
+            generate_hash_list(
+                &root,
+                file_list,
+                &settings,
+                StatusWrapper::Status(status_clone),
+                tx,
+                &pause,
+                &cancelled,
+            )
+        });
+    }
+
+    fn start_run(&mut self, hash_list: Vec<HashPair>) {
+        let list_path =
+            std::path::absolute(&self.selected_list).unwrap_or_else(|_| self.selected_list.clone());
+        self.recent.add(&list_path);
+        if let Err(e) = self.recent.save() {
+            self.messages.push(Message::Warning(format!(
+                "Failed to save recent files: {e}"
+            )));
+        }
+
+        self.running = true;
+        self.completed_duration = None;
         self.total_hash = hash_list.len();
+        self.start_time = Instant::now();
 
         let status_clone = Arc::clone(&self.hash_status);
-        let settings = self.settings;
+        let settings = self.settings.clone();
         let (tx, rx) = channel::unbounded();
         self.message_rx = Some(rx);
 
-        thread::spawn(move || prepare_hashing(hash_list, &settings, StatusWrapper::Status(status_clone), tx));
+        let pause = Arc::clone(&self.pause);
+        let cancelled = Arc::clone(&self.cancelled);
+        thread::spawn(move || {
+            prepare_hashing(
+                hash_list,
+                &settings,
+                StatusWrapper::Status(status_clone),
+                tx,
+                &pause,
+                &cancelled,
+                &list_path,
+            )
+        });
     }
 }
 
+/// Write the messages of a run like the log shows them, with the outcome at the end
+fn write_log(writer: &mut impl Write, messages: &[Message]) -> std::io::Result<()> {
+    let mut outcome_line = None;
+    for message in messages {
+        match message {
+            Message::Completed(outcome) | Message::Cancelled(outcome) => {
+                let state = match message {
+                    Message::Cancelled(_) => "Cancelled",
+                    _ if outcome.aborted => "Aborted",
+                    _ => "Completed",
+                };
+                outcome_line = Some(format!("{state} in {:?}: {outcome}", outcome.elapsed));
+            }
+            Message::Empty => {}
+            _ => writeln!(writer, "{}", widgets::message_line(message))?,
+        }
+    }
+    if let Some(line) = outcome_line {
+        writeln!(writer, "{line}")?;
+    }
+    Ok(())
+}
+
 fn vert_center(area: Rect, height: u16) -> Rect {
     let [_, vert_centered_area, _] = Layout::vertical([
         Constraint::Fill(1),
@@ -273,6 +1301,31 @@ fn vert_center(area: Rect, height: u16) -> Rect {
     vert_centered_area
 }
 
+impl App {
+    fn timing(&self) -> widgets::Timing {
+        let completed_in = self.messages.iter().find_map(|x| match x {
+            Message::Completed(outcome) => Some(outcome.elapsed),
+            _ => None,
+        });
+
+        widgets::Timing {
+            start_time: self.start_time,
+            paused: self.pause.paused_duration(),
+            completed_in,
+        }
+    }
+
+    fn status_overlay(&self) -> Option<&'static str> {
+        if self.cancelled_at.is_some() {
+            Some("CANCELLED")
+        } else if self.pause.is_paused() {
+            Some("PAUSED")
+        } else {
+            None
+        }
+    }
+}
+
 impl Widget for &App {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let [top_area, bottom_area] = Layout::new(
@@ -280,6 +1333,7 @@ impl Widget for &App {
             [Constraint::Percentage(50), Constraint::Fill(1)],
         )
         .areas(area);
+        self.bottom_area.set(bottom_area);
 
         let [left_area, right_area] =
             Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]).areas(top_area);
@@ -287,22 +1341,80 @@ impl Widget for &App {
         // Right window (Hash status)
         widgets::Status::new(
             self.hash_status.clone(),
+            &self.run_mode,
             self.running,
+            self.status_overlay(),
             self.total_hash,
             self.entered_empty,
+            self.timing(),
         )
+        .watching(self.watching())
+        .completed_in(self.completed_duration)
+        .append_selected(self.append_selected)
+        .rate_limit(self.settings.rate_limit)
         .render(right_area, buf);
 
         // Bottom window (Navigator, prompter, log)
-        if self.showing_explorer {
+        if self.showing_base_dir_explorer {
+            self.base_dir_explorer.widget().render(bottom_area, buf);
+        } else if self.showing_explorer {
             self.file_explorer.widget().render(bottom_area, buf);
-        } else if self.running {
-            widgets::Log::new(&self.messages, self.log_scroll_offset).render(bottom_area, buf);
+        } else if self.running || self.showing_dry_run {
+            widgets::Log::new(
+                &self.messages,
+                self.log_scroll_offset,
+                self.log_search.as_ref(),
+                &self.log_scrollbar_area,
+            )
+            .auto_scroll(self.log_auto_scroll)
+            .render(bottom_area, buf);
         } else {
-            widgets::HashListPrompt::new(&self.selected_list, &self.error).render(bottom_area, buf);
+            widgets::HashListPrompt::new(
+                &self.selected_list,
+                &self.error,
+                &self.recent.recent_files,
+            )
+            .render(bottom_area, buf);
         }
 
-        // Upper-left window (Setting)
-        widgets::Setting::new(&self.settings).render(left_area, buf);
+        if let Some((result, _)) = &self.export_notification {
+            let text = match result {
+                Ok(path) => Line::from(format!("Log written to {}", path.to_string_lossy())),
+                Err(e) => Line::from(format!("Failed to export the log: {e}")).light_red(),
+            };
+            widgets::Notification::new("Export", text).render(bottom_area, buf);
+        }
+
+        // Upper-left window (Setting, and the queue below it)
+        let setting_area = if self.queue.is_empty() && self.finished.is_empty() {
+            left_area
+        } else {
+            let queue_height = min(
+                widgets::Queue::height(&self.queue, &self.finished),
+                left_area.height / 2,
+            );
+            let [setting_area, queue_area] =
+                Layout::vertical([Constraint::Fill(1), Constraint::Length(queue_height)])
+                    .areas(left_area);
+            widgets::Queue::new(&self.queue, &self.finished, self.queue_idx)
+                .render(queue_area, buf);
+            setting_area
+        };
+        widgets::Setting::new(
+            &self.settings,
+            &self.setting_hitboxes,
+            self.pattern_edit.as_ref(),
+            self.detected_algorithm,
+            self.auto_threads,
+        )
+        .render(setting_area, buf);
+
+        if let Some(pending_run) = &self.pending_run {
+            widgets::Preflight::new(&pending_run.missing).render(area, buf);
+        }
+
+        if self.showing_help {
+            widgets::Help::new(self.key_context(), self.help_scroll_offset).render(area, buf);
+        }
     }
 }
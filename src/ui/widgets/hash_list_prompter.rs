@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use color_eyre::Report;
 use crate::ui::vert_center;
 use ratatui::prelude::*;
@@ -7,13 +7,19 @@ use ratatui::widgets::{Block, Padding, Paragraph, Wrap};
 pub struct HashListPrompt<'a> {
     selected_list: &'a Path,
     error: &'a Option<Report>,
+    recent_files: &'a [PathBuf],
 }
 
 impl<'a> HashListPrompt<'a> {
-    pub fn new(selected_list: &'a Path, error: &'a Option<Report>) -> Self {
+    pub fn new(
+        selected_list: &'a Path,
+        error: &'a Option<Report>,
+        recent_files: &'a [PathBuf],
+    ) -> Self {
         Self {
             selected_list,
             error,
+            recent_files,
         }
     }
 }
@@ -21,12 +27,12 @@ impl<'a> HashListPrompt<'a> {
 impl Widget for HashListPrompt<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let hash_list_block = Block::bordered()
-            .title("MD5 hash list file")
+            .title("Hash list file")
             .title_bottom(
                 Line::from(vec![
                     "Press <n> to select file, or <v> to get ".into(),
                     "absolute".bold(),
-                    " path from clipboard".into(),
+                    " path from clipboard. Press <?> for help".into(),
                 ])
                 .centered(),
             )
@@ -35,14 +41,23 @@ impl Widget for HashListPrompt<'_> {
         let path_str = self.selected_list.to_string_lossy();
 
         let mut lines = vec![Line::from(vec![
-            "MD5 hash list: ".into(),
-            if path_str != "" {
+            "Hash list: ".into(),
+            if !path_str.is_empty() {
                 path_str.into()
             } else {
                 "Not selected yet".bold()
             },
         ])];
 
+        if !self.recent_files.is_empty() {
+            lines.push("".into());
+            lines.push("Recent (press the number to select):".bold().into());
+            for (idx, path) in self.recent_files.iter().enumerate() {
+                let key = (idx + 1) % 10;
+                lines.push(format!("<{key}> {}", path.to_string_lossy()).into());
+            }
+        }
+
         if let Some(e) = &self.error {
             lines.push("".into());
             lines.push(Line::from(vec![
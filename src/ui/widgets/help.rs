@@ -0,0 +1,36 @@
+use crate::ui::keybindings::{bindings_for, KeyContext};
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Clear, Padding, Row, Table};
+
+pub struct Help {
+    context: KeyContext,
+    scroll_offset: usize,
+}
+
+impl Help {
+    pub fn new(context: KeyContext, scroll_offset: usize) -> Self {
+        Self {
+            context,
+            scroll_offset,
+        }
+    }
+}
+
+impl Widget for Help {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let help_block = Block::bordered()
+            .title("Key bindings")
+            .title_bottom("Press <?/Esc> to close, <↑/↓> to scroll")
+            .padding(Padding::uniform(1));
+
+        let rows = bindings_for(self.context)
+            .skip(self.scroll_offset)
+            .map(|x| Row::new(vec![Span::from(x.key).bold(), x.description.into()]));
+
+        Clear.render(area, buf);
+        let table = Table::new(rows, [Constraint::Length(12), Constraint::Fill(1)])
+            .header(Row::new(vec!["Key", "Action"]).underlined())
+            .block(help_block);
+        Widget::render(table, area, buf);
+    }
+}
@@ -1,58 +1,223 @@
-use crate::Message;
 use itertools::Itertools;
+use md5check::hash::HashError;
+use md5check::Message;
 use ratatui::prelude::*;
-use ratatui::widgets::{Block, Padding, Paragraph};
+use ratatui::widgets::{
+    Block, Padding, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState,
+};
+use std::cell::Cell;
+
+/// Search bar state of the log
+#[derive(Debug, Default)]
+pub struct LogSearch {
+    pub query: String,
+    /// Typing into the search bar, otherwise <n/N> cycle through the matches
+    pub editing: bool,
+    /// Index of the selected match among all matches
+    pub current: usize,
+}
+
+impl LogSearch {
+    /// Case-insensitive match against the displayed line
+    pub fn is_match(&self, message: &Message) -> bool {
+        !self.query.is_empty()
+            && message_line(message)
+                .to_string()
+                .to_lowercase()
+                .contains(&self.query.to_lowercase())
+    }
+}
+
+/// Orange for permission errors, yellow for missing files and red for the rest
+fn error_color(error: &HashError) -> Color {
+    match error {
+        HashError::PermissionDenied { .. } => Color::Rgb(255, 165, 0),
+        HashError::FileNotFound { .. } => Color::Yellow,
+        _ => Color::LightRed,
+    }
+}
+
+pub fn message_line(message: &Message) -> Line<'_> {
+    match message {
+        Message::Correct { file_path, .. } => Line::from(vec![
+            Span::from("OK: ").style(Color::LightGreen),
+            file_path.into(),
+        ]),
+        Message::Incorrect { file_path, .. } => Line::from(vec![
+            Span::from("Incorrect: ").style(Color::Yellow),
+            file_path.into(),
+        ]),
+        Message::Warning(s) => Line::from(vec![
+            Span::from("Warning: ").style(Color::Magenta),
+            s.into(),
+        ]),
+        Message::Generated(s, _) => Line::from(vec![
+            Span::from("Generated: ").style(Color::LightGreen),
+            s.into(),
+        ]),
+        Message::Error { file_path, error } => Line::from(vec![
+            Span::from("Error: ").style(error_color(error)),
+            format!("{file_path}: {error}").into(),
+        ]),
+        Message::Missing(s) => {
+            Line::from(vec![Span::from("Missing: ").style(Color::Yellow), s.into()])
+        }
+        Message::Skipped(s) => Line::from(vec![
+            Span::from("Skipped: ").style(Color::DarkGray),
+            s.into(),
+        ]),
+        Message::Completed(outcome) => format!(
+            "{} in {:?}! Press <Enter> to go back or <Ctrl+c> to close",
+            if outcome.aborted {
+                "Aborted"
+            } else {
+                "Completed"
+            },
+            outcome.elapsed
+        )
+        .bold()
+        .into(),
+        Message::Extra(s) => Line::from(vec![Span::from("Extra: ").style(Color::Cyan), s.into()]),
+        Message::CaseCorrected { listed, found } => Line::from(vec![
+            Span::from("Case corrected: ").style(Color::LightYellow),
+            format!("{listed} -> {found}").into(),
+        ]),
+        Message::Modified(s) => Line::from(vec![
+            Span::from("Modified: ").style(Color::LightCyan),
+            s.into(),
+        ]),
+        Message::Retrying(s, attempt) => Line::from(vec![
+            Span::from(format!("Retrying ({attempt}): ")).style(Color::Magenta),
+            s.into(),
+        ]),
+        Message::DryRun {
+            verifiable_num,
+            total_bytes,
+        } => format!(
+            "Dry run: {verifiable_num} files can be verified, {} to read. Press <Enter> to go back",
+            size::Size::from_bytes(*total_bytes)
+        )
+        .bold()
+        .into(),
+        Message::Cancelled(_) => "Cancelled!".bold().into(),
+        Message::Empty => "".into(),
+    }
+}
 
 pub struct Log<'a> {
     messages: &'a [Message],
     scroll_offset: u16,
+    search: Option<&'a LogSearch>,
+    /// Where the scrollbar was last rendered, empty if hidden. Used for mouse clicks
+    scrollbar_hitbox: &'a Cell<Rect>,
+    auto_scroll: bool,
 }
 
 impl<'a> Log<'a> {
-    pub fn new<T>(messages: &'a [Message], scroll_offset: T) -> Self
+    pub fn new<T>(
+        messages: &'a [Message],
+        scroll_offset: T,
+        search: Option<&'a LogSearch>,
+        scrollbar_hitbox: &'a Cell<Rect>,
+    ) -> Self
     where
         T: Into<u16>,
     {
         Self {
             messages,
             scroll_offset: scroll_offset.into(),
+            search,
+            scrollbar_hitbox,
+            auto_scroll: false,
         }
     }
+
+    /// Show the "AUTO" badge while sticking to the newest message
+    pub fn auto_scroll(mut self, auto_scroll: bool) -> Self {
+        self.auto_scroll = auto_scroll;
+        self
+    }
 }
 
 impl Widget for Log<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        // TODO: Add scroll instructions
-        let log_block = Block::bordered()
+        let mut log_block = Block::bordered()
             .padding(Padding::uniform(1))
             .title("Log")
-            .title_bottom("Press <↑/↓> to scroll through logs");
+            .title_bottom(
+                "Press <↑/↓> to scroll through logs, </> to search, <e> to export, \
+                 <Space> to pause/resume, <q> to cancel",
+            );
+        if self.auto_scroll {
+            log_block =
+                log_block.title_bottom(Line::from(" AUTO ".bold().reversed()).right_aligned());
+        }
 
+        let mut match_idx = 0;
         let logs = self
             .messages
             .iter()
             .rev()
-            .map(|x| match x {
-                Message::Incorrect(s) => Line::from(vec![
-                    Span::from("Incorrect: ").style(Color::Yellow),
-                    s.into(),
-                ]),
-                Message::Error(e) => Line::from(vec![
-                    Span::from("Error: ").style(Color::LightRed),
-                    e.to_string().into(),
-                ]),
-                Message::Completed(duration) => {
-                    format!("Completed in {duration:?}! Please close with <Ctrl+c>")
-                        .bold()
-                        .into()
+            .map(|x| {
+                let line = message_line(x);
+                match self.search {
+                    Some(search) if search.is_match(x) => {
+                        let style = if match_idx == search.current {
+                            Style::new().black().on_yellow()
+                        } else {
+                            Style::new().on_dark_gray()
+                        };
+                        match_idx += 1;
+                        line.patch_style(style)
+                    }
+                    _ => line,
                 }
-                Message::Empty => "".into(),
             })
             .collect_vec();
 
+        let inner_area = log_block.inner(area);
+        log_block.render(area, buf);
+
+        let [log_area, search_area] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(self.search.is_some().into()),
+        ])
+        .areas(inner_area);
+
+        let line_num = logs.len();
         Paragraph::new(logs)
             .scroll((self.scroll_offset, 0))
-            .block(log_block)
-            .render(area, buf);
+            .render(log_area, buf);
+
+        // On the right border, only when the log doesn't fit
+        if line_num > log_area.height as usize {
+            let scrollbar_area = area.inner(Margin::new(0, 1));
+            let mut scrollbar_state =
+                ScrollbarState::new(line_num).position(self.scroll_offset as usize);
+            Scrollbar::new(ScrollbarOrientation::VerticalRight).render(
+                scrollbar_area,
+                buf,
+                &mut scrollbar_state,
+            );
+            self.scrollbar_hitbox.set(Rect {
+                x: scrollbar_area.right().saturating_sub(1),
+                width: 1,
+                ..scrollbar_area
+            });
+        } else {
+            self.scrollbar_hitbox.set(Rect::default());
+        }
+
+        if let Some(search) = self.search {
+            let mut spans = vec!["/".bold(), search.query.as_str().into()];
+            if search.editing {
+                spans.push(" ".reversed());
+            } else if match_idx == 0 {
+                spans.push(" (no matches)".italic());
+            } else {
+                spans.push(format!(" ({}/{match_idx})", search.current + 1).italic());
+            }
+            Line::from(spans).render(search_area, buf);
+        }
     }
 }
@@ -1,9 +1,17 @@
 mod setting;
 mod hash_list_prompter;
+mod help;
 mod log;
+mod notification;
+mod preflight;
+mod queue;
 mod status;
 
 pub use setting::*;
 pub use hash_list_prompter::*;
+pub use help::*;
 pub use log::*;
+pub use notification::*;
+pub use preflight::*;
+pub use queue::*;
 pub use status::*;
\ No newline at end of file
@@ -0,0 +1,35 @@
+use crate::ui::vert_center;
+use ratatui::layout::Flex;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Clear, Padding, Paragraph, Wrap};
+
+/// Small popup centered in the area
+pub struct Notification<'a> {
+    title: &'a str,
+    text: Line<'a>,
+}
+
+impl<'a> Notification<'a> {
+    pub fn new(title: &'a str, text: Line<'a>) -> Self {
+        Self { title, text }
+    }
+}
+
+impl Widget for Notification<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [popup_area] = Layout::horizontal([Constraint::Percentage(60)])
+            .flex(Flex::Center)
+            .areas(vert_center(area, 5));
+
+        Clear.render(popup_area, buf);
+        Paragraph::new(self.text)
+            .centered()
+            .wrap(Wrap { trim: true })
+            .block(
+                Block::bordered()
+                    .title(self.title)
+                    .padding(Padding::horizontal(1)),
+            )
+            .render(popup_area, buf);
+    }
+}
@@ -0,0 +1,41 @@
+use crate::ui::vert_center;
+use ratatui::layout::Flex;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Clear, Padding, Paragraph};
+
+/// Popup listing the files the preflight found missing, before the run starts
+pub struct Preflight<'a> {
+    missing: &'a [String],
+}
+
+impl<'a> Preflight<'a> {
+    pub fn new(missing: &'a [String]) -> Self {
+        Self { missing }
+    }
+}
+
+impl Widget for Preflight<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // Border and padding take 4 lines
+        let height = (self.missing.len() as u16).saturating_add(4);
+        let [popup_area] = Layout::horizontal([Constraint::Percentage(60)])
+            .flex(Flex::Center)
+            .areas(vert_center(area, height.min(area.height)));
+
+        let lines = self
+            .missing
+            .iter()
+            .map(|x| Line::from(vec![Span::from("Missing: ").yellow(), x.into()]))
+            .collect::<Vec<_>>();
+
+        Clear.render(popup_area, buf);
+        Paragraph::new(lines)
+            .block(
+                Block::bordered()
+                    .title(format!("Preflight: {} missing files", self.missing.len()))
+                    .title_bottom("Press <Enter> to continue anyway, <Esc> to abort")
+                    .padding(Padding::uniform(1)),
+            )
+            .render(popup_area, buf);
+    }
+}
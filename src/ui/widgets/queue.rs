@@ -0,0 +1,74 @@
+use crate::ui::FinishedList;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Padding, Paragraph};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+/// Hash lists verified one after another once the current run completes, below the ones already
+/// verified
+pub struct Queue<'a> {
+    queue: &'a VecDeque<PathBuf>,
+    finished: &'a [FinishedList],
+    selected: usize,
+}
+
+impl<'a> Queue<'a> {
+    pub fn new(
+        queue: &'a VecDeque<PathBuf>,
+        finished: &'a [FinishedList],
+        selected: usize,
+    ) -> Self {
+        Self {
+            queue,
+            finished,
+            selected,
+        }
+    }
+
+    /// Lines needed to show every finished and queued hash list, with the border
+    pub fn height(queue: &VecDeque<PathBuf>, finished: &[FinishedList]) -> u16 {
+        ((queue.len() + finished.len()) as u16).saturating_add(2)
+    }
+}
+
+impl Widget for Queue<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let finished_lines = self.finished.iter().map(|list| {
+            let outcome = &list.outcome;
+            let failed =
+                outcome.incorrect_num + outcome.missing_num + outcome.error_num + outcome.extra_num
+                    > 0;
+            Line::from(vec![
+                if failed {
+                    Span::from("Failed: ").light_red()
+                } else {
+                    Span::from("Done: ").light_green()
+                },
+                list.path.to_string_lossy().into_owned().into(),
+                format!(" ({outcome})").dark_gray(),
+            ])
+        });
+        let lines = finished_lines
+            .chain(self.queue.iter().enumerate().map(|(idx, path)| {
+                let line = Line::from(vec![
+                    Span::from(format!("{}. ", idx + 1)).light_blue(),
+                    path.to_string_lossy().into_owned().into(),
+                ]);
+                if idx == self.selected {
+                    line.reversed()
+                } else {
+                    line
+                }
+            }))
+            .collect::<Vec<_>>();
+
+        Paragraph::new(lines)
+            .block(
+                Block::bordered()
+                    .title(format!("Queue ({})", self.queue.len()))
+                    .title_bottom("<Del> to remove, <Alt+↑/↓> to reorder")
+                    .padding(Padding::horizontal(1)),
+            )
+            .render(area, buf);
+    }
+}
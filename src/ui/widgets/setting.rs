@@ -1,6 +1,9 @@
-use crate::Setting as SettingStorage;
+use itertools::Itertools;
+use md5check::system::AutoThreads;
+use md5check::{HashAlgorithm, Setting as SettingStorage, Verbosity};
 use ratatui::prelude::*;
 use ratatui::widgets::{Block, Padding, Paragraph};
+use std::cell::Cell;
 
 fn boolean_str_color(x: bool) -> Span<'static> {
     if x {
@@ -9,13 +12,135 @@ fn boolean_str_color(x: bool) -> Span<'static> {
         Span::from("false".to_string()).fg(Color::LightRed)
     }
 }
+/// Where the toggles were last rendered, used for mouse clicks
+#[derive(Debug, Default)]
+pub struct SettingHitboxes {
+    pub parallel: Cell<Rect>,
+    pub sort: Cell<Rect>,
+    pub fail_fast: Cell<Rect>,
+    pub verbose: Cell<Rect>,
+    pub watch: Cell<Rect>,
+    pub preflight: Cell<Rect>,
+    pub dry_run: Cell<Rect>,
+    pub strict: Cell<Rect>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatternField {
+    Include,
+    Exclude,
+}
+
+/// Text field for the include or exclude patterns, separated by commas
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternEdit {
+    pub field: PatternField,
+    pub text: String,
+}
+
+/// Algorithm of the last parsed hash list, shown when no algorithm is configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectedAlgorithm {
+    Known(HashAlgorithm),
+    /// No line had a digest of a known length
+    Unknown,
+}
+
 pub struct Setting<'a> {
     settings: &'a SettingStorage,
+    hitboxes: &'a SettingHitboxes,
+    pattern_edit: Option<&'a PatternEdit>,
+    detected_algorithm: Option<DetectedAlgorithm>,
+    auto_threads: Option<AutoThreads>,
 }
 
 impl<'a> Setting<'a> {
-    pub fn new(settings: &'a SettingStorage) -> Self {
-        Self { settings }
+    pub fn new(
+        settings: &'a SettingStorage,
+        hitboxes: &'a SettingHitboxes,
+        pattern_edit: Option<&'a PatternEdit>,
+        detected_algorithm: Option<DetectedAlgorithm>,
+        auto_threads: Option<AutoThreads>,
+    ) -> Self {
+        Self {
+            settings,
+            hitboxes,
+            pattern_edit,
+            detected_algorithm,
+            auto_threads,
+        }
+    }
+
+    /// Threads picked by `Setting::parallel_auto` are muted, like a detected algorithm
+    fn threads_line(&self) -> Line<'static> {
+        let value = match (self.settings.threads, self.auto_threads) {
+            (Some(x), _) => x.to_string().into(),
+            (None, Some(x)) => {
+                let disk = match x.rotational {
+                    Some(true) => ", HDD",
+                    Some(false) => ", SSD",
+                    None => "",
+                };
+                format!("{} (auto{disk})", x.threads).dark_gray()
+            }
+            (None, None) => "auto".italic(),
+        };
+        Line::from(vec!["Threads: ".into(), value])
+    }
+
+    /// A configured algorithm is shown as is, a detected one is muted
+    fn algorithm_line(&self) -> Line<'static> {
+        let value = match (self.settings.algorithm, self.detected_algorithm) {
+            (Some(x), _) => x.to_string().into(),
+            (None, Some(DetectedAlgorithm::Known(x))) => format!("{x} (auto)").dark_gray(),
+            (None, Some(DetectedAlgorithm::Unknown)) => "(unknown algorithm)".red(),
+            (None, None) => "auto".italic(),
+        };
+        Line::from(vec!["Algorithm: ".into(), value])
+    }
+
+    /// Yellow when below `min_block_size`, as small reads are slow
+    fn block_size_line(&self) -> Line<'static> {
+        let line = Line::from(format!(
+            "Block size: {} ({})",
+            self.settings.block_size,
+            size::Size::from_bytes(self.settings.block_size)
+        ));
+        if self.settings.block_size < self.settings.min_block_size {
+            line.yellow()
+        } else {
+            line
+        }
+    }
+
+    fn pattern_line(&self, field: PatternField) -> Line<'static> {
+        let (name, patterns, empty) = match field {
+            PatternField::Include => ("Include: ", &self.settings.include, "all"),
+            PatternField::Exclude => ("Exclude: ", &self.settings.exclude, "none"),
+        };
+
+        match self.pattern_edit.filter(|x| x.field == field) {
+            Some(edit) => Line::from(vec![
+                name.into(),
+                format!("{}_", edit.text).fg(Color::LightCyan),
+            ]),
+            None if patterns.is_empty() => Line::from(vec![name.into(), empty.italic()]),
+            None => format!("{name}{}", patterns.iter().join(", ")).into(),
+        }
+    }
+
+    /// Only the directory name, as full paths would push the tooltips away
+    fn base_dir_line(&self) -> Line<'static> {
+        let value = match &self.settings.base_dir {
+            Some(x) => x
+                .file_name()
+                .unwrap_or(x.as_os_str())
+                .to_string_lossy()
+                .into_owned()
+                .into(),
+            None => "hash list directory".italic(),
+        };
+        Line::from(vec!["Base directory: ".into(), value])
     }
 }
 
@@ -31,26 +156,63 @@ impl Widget for Setting<'_> {
                 boolean_str_color(self.settings.parallel),
             ]),
             Line::from(vec!["Sort: ".into(), boolean_str_color(self.settings.sort)]),
-            format!(
-                "Block size: {} ({})",
-                self.settings.block_size,
-                size::Size::from_bytes(self.settings.block_size)
-            )
-            .into(),
+            Line::from(vec![
+                "Fail fast: ".into(),
+                boolean_str_color(self.settings.fail_fast),
+            ]),
+            Line::from(vec![
+                "Verbose: ".into(),
+                boolean_str_color(self.settings.verbosity == Verbosity::Verbose),
+            ]),
+            Line::from(vec![
+                "Watch: ".into(),
+                boolean_str_color(self.settings.watch),
+            ]),
+            Line::from(vec![
+                "Preflight: ".into(),
+                boolean_str_color(self.settings.preflight),
+            ]),
+            Line::from(vec![
+                "Dry run: ".into(),
+                boolean_str_color(self.settings.dry_run),
+            ]),
+            Line::from(vec![
+                "Strict: ".into(),
+                boolean_str_color(self.settings.strict),
+            ]),
+            self.algorithm_line(),
+            self.threads_line(),
+            format!("Retries: {}", self.settings.retries).into(),
+            self.block_size_line(),
+            self.pattern_line(PatternField::Include),
+            self.pattern_line(PatternField::Exclude),
+            self.base_dir_line(),
         ];
 
         let tooltip_lines = vec![
             "Press <p> to toggle".into(),
             "      <s>          ".into(),
+            "      <f>          ".into(),
+            "      <V>          ".into(),
+            "      <w>          ".into(),
+            "      <P>          ".into(),
+            "      <d>          ".into(),
+            "      <S>          ".into(),
+            "Press <a> to cycle".into(),
+            "Press <+/-> to increase/decrease".into(),
+            "Press <r/R> to increase/decrease".into(),
             "Press <←/→> to decrease/increase".into(),
             "Press <Ctrl/Shift> for 1 MiB/GiB".into(),
+            "Press <i> to edit, comma separated".into(),
+            "      <x>                         ".into(),
+            "Press <b> to select".into(),
         ];
 
         let setting_max_len = setting_lines
             .iter()
             .map(|x| x.width())
             .max()
-            .expect("setting_lines is at least len 3 as set in code. so will have a max");
+            .expect("setting_lines is at least len 10 as set in code. so will have a max");
 
         let [setting_area, tooltip_area] = Layout::horizontal([
             Constraint::Length(setting_max_len as u16 + 2),
@@ -58,6 +220,19 @@ impl Widget for Setting<'_> {
         ])
         .areas(setting_block.inner(area));
 
+        let line_area = |idx: u16| {
+            Rect::new(setting_area.x, setting_area.y + idx, setting_area.width, 1)
+                .intersection(setting_area)
+        };
+        self.hitboxes.parallel.set(line_area(0));
+        self.hitboxes.sort.set(line_area(1));
+        self.hitboxes.fail_fast.set(line_area(2));
+        self.hitboxes.verbose.set(line_area(3));
+        self.hitboxes.watch.set(line_area(4));
+        self.hitboxes.preflight.set(line_area(5));
+        self.hitboxes.dry_run.set(line_area(6));
+        self.hitboxes.strict.set(line_area(7));
+
         Paragraph::new(setting_lines).render(setting_area, buf);
 
         Paragraph::new(tooltip_lines).render(tooltip_area, buf);
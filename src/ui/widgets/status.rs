@@ -1,63 +1,232 @@
-use crate::ui::vert_center;
-use crate::Status as StatusStorage;
+use crate::ui::{vert_center, RunMode};
+use md5check::{Severity, Status as StatusStorage};
 use parking_lot::RwLock;
+use ratatui::layout::Flex;
 use ratatui::prelude::*;
-use ratatui::widgets::{Block, Gauge, Padding, Paragraph};
+use ratatui::widgets::{Block, Clear, Gauge, Padding, Paragraph};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-pub struct Status {
+/// Timing of the current run
+pub struct Timing {
+    pub start_time: Instant,
+    /// Time spent paused, not counted as elapsed
+    pub paused: Duration,
+    /// Set once `Message::Completed` is received
+    pub completed_in: Option<Duration>,
+}
+
+impl Timing {
+    fn elapsed(&self) -> Duration {
+        self.completed_in
+            .unwrap_or_else(|| self.start_time.elapsed().saturating_sub(self.paused))
+    }
+}
+
+/// `rate_limit` is in MB/s, see `Setting::rate_limit`
+fn throughput_lines(status: &StatusStorage, rate_limit: Option<f64>) -> Vec<Line<'static>> {
+    let current_file_bytes = status.current_file_bytes.load(Ordering::Relaxed);
+    let throughput = format!(
+        "Throughput: {}/s",
+        size::Size::from_bytes(status.throughput())
+    );
+    let throughput = match rate_limit {
+        Some(x) => Line::from(vec![
+            throughput.into(),
+            format!(" (limit: {}/s)", size::Size::from_bytes(x * 1_000_000.0)).dark_gray(),
+        ]),
+        None => throughput.into(),
+    };
+
+    vec![
+        throughput,
+        format!(
+            "Current file: {} read",
+            size::Size::from_bytes(current_file_bytes)
+        )
+        .into(),
+    ]
+}
+
+/// Formats as `hh:mm:ss`
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+pub struct Status<'a> {
+    run_mode: &'a RunMode,
     running: bool,
+    /// Text shown on top of the status, e.g. when paused
+    overlay: Option<&'a str>,
     entered_empty: bool,
     hash_status: Arc<RwLock<StatusStorage>>,
     total_hash: usize,
+    timing: Timing,
+    watching: bool,
+    completed_in: Option<Duration>,
+    append_selected: bool,
+    rate_limit: Option<f64>,
 }
 
-impl Status {
+impl<'a> Status<'a> {
     pub fn new(
         status: Arc<RwLock<StatusStorage>>,
+        run_mode: &'a RunMode,
         running: bool,
+        overlay: Option<&'a str>,
         total_hash: usize,
         entered_empty: bool,
+        timing: Timing,
     ) -> Self {
         Self {
+            run_mode,
             running,
+            overlay,
             hash_status: status,
             total_hash,
             entered_empty,
+            timing,
+            watching: false,
+            completed_in: None,
+            append_selected: false,
+            rate_limit: None,
+        }
+    }
+
+    /// Show a badge while watching for changes after the run
+    pub fn watching(mut self, watching: bool) -> Self {
+        self.watching = watching;
+        self
+    }
+
+    /// Duration of the last run, shown when stopped
+    pub fn completed_in(mut self, completed_in: Option<Duration>) -> Self {
+        self.completed_in = completed_in;
+        self
+    }
+
+    /// Shown next to the throughput, in MB/s
+    pub fn rate_limit(mut self, rate_limit: Option<f64>) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Run mode shown when stopped, appending instead of verifying
+    pub fn append_selected(mut self, append_selected: bool) -> Self {
+        self.append_selected = append_selected;
+        self
+    }
+
+    /// Green while every file is correct, yellow with incorrect files and red with errors or
+    /// missing files
+    fn gauge_color(status: &StatusStorage) -> Color {
+        match status.severity() {
+            Severity::Correct => Color::LightGreen,
+            Severity::Incorrect => Color::Yellow,
+            Severity::Error => Color::LightRed,
         }
     }
 
     fn render_running(self, area: Rect, buf: &mut Buffer) {
         let status = { self.hash_status.read().clone() };
+        let throughput_lines = throughput_lines(&status, self.rate_limit);
+        let gauge_color = Self::gauge_color(&status);
+        let processed = status.processed();
 
         let [stat_area, progress_area] =
             Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(area);
 
-        let colored_hash = if status.file_hash == status.expected_hash {
-            Span::styled(status.file_hash, Style::default().fg(Color::LightGreen))
-        } else {
-            Span::styled(status.file_hash, Style::default().fg(Color::LightRed))
+        let mut status_line = match self.run_mode {
+            RunMode::Verify => {
+                let colored_hash = if status.file_hash.eq_ignore_ascii_case(&status.expected_hash) {
+                    Span::styled(status.file_hash, Style::default().fg(Color::LightGreen))
+                } else {
+                    Span::styled(status.file_hash, Style::default().fg(Color::LightRed))
+                };
+
+                let mut lines = vec![
+                    format!("File name: {}", status.filename).into(),
+                    Line::from(vec!["File hash: ".into(), colored_hash]),
+                    format!("Expected hash: {}", status.expected_hash).into(),
+                    format!("Correct: {}", status.correct_num).into(),
+                    format!("Incorrect: {}", status.incorrect_num).into(),
+                    format!("Missing: {}", status.missing_num).into(),
+                    format!("Error: {}", status.error_num).into(),
+                    format!("Skipped: {}", status.skipped_num).into(),
+                ];
+                // Only found at the end of a run with `check_extra`
+                if status.extra_num > 0 {
+                    lines.push(format!("Extra: {}", status.extra_num).into());
+                }
+                lines
+            }
+            RunMode::Generate { output, .. } => vec![
+                format!("Output: {}", output.to_string_lossy()).into(),
+                format!("File name: {}", status.filename).into(),
+                format!("File hash: {}", status.file_hash).into(),
+                format!("Hashed: {}", status.correct_num).into(),
+                format!("Error: {}", status.error_num).into(),
+            ],
+            RunMode::Append { hash_list, .. } => vec![
+                format!("Appending to: {}", hash_list.to_string_lossy()).into(),
+                format!("File name: {}", status.filename).into(),
+                format!("File hash: {}", status.file_hash).into(),
+                format!("Appended: {}", status.correct_num).into(),
+                format!("Error: {}", status.error_num).into(),
+            ],
         };
 
-        let status_line = vec![
-            format!("File name: {}", status.filename).into(),
-            Line::from(vec!["File hash: ".into(), colored_hash]),
-            format!("Expected hash: {}", status.expected_hash).into(),
-            format!("Correct: {}", status.correct_num).into(),
-            format!("Incorrect: {}", status.incorrect_num).into(),
-            format!("Error: {}", status.error_num).into(),
-        ];
+        status_line.extend(self.timing_lines(processed));
+        status_line.extend(throughput_lines);
 
         Paragraph::new(status_line).render(stat_area, buf);
 
         Gauge::default()
             .use_unicode(true)
-            .ratio((status.correct_num + status.incorrect_num + status.error_num) as f64 / self.total_hash as f64)
+            .gauge_style(gauge_color)
+            // Files verified again while watching are counted too
+            .ratio((processed as f64 / self.total_hash as f64).min(1.0))
             .render(progress_area, buf);
+
+        if let Some(text) = self.overlay {
+            Self::render_overlay(text, area, buf);
+        }
+    }
+
+    fn timing_lines(&self, processed: usize) -> Vec<Line<'static>> {
+        let elapsed = self.timing.elapsed();
+        let last_line = if let Some(completed_in) = self.timing.completed_in {
+            format!("Completed in {:.1}s", completed_in.as_secs_f64())
+        } else if processed == 0 {
+            "ETA: --:--:--".to_string()
+        } else {
+            let remaining = self.total_hash.saturating_sub(processed) as u32;
+            let eta = elapsed / processed as u32 * remaining;
+            format!("ETA: {}", format_duration(eta))
+        };
+
+        vec![
+            format!("Elapsed: {}", format_duration(elapsed)).into(),
+            last_line.into(),
+        ]
+    }
+
+    fn render_overlay(text: &str, area: Rect, buf: &mut Buffer) {
+        let [overlay_area] = Layout::horizontal([Constraint::Length(text.len() as u16 + 6)])
+            .flex(Flex::Center)
+            .areas(vert_center(area, 3));
+
+        Clear.render(overlay_area, buf);
+        Paragraph::new(text.bold())
+            .centered()
+            .block(Block::bordered())
+            .render(overlay_area, buf);
     }
 
     fn render_stopped(self, area: Rect, buf: &mut Buffer) {
-        let lines = vec![
+        let mut lines = vec![
             "Hasher not running".bold().into(),
             Line::from(vec![
                 "Press <Enter> ".into(),
@@ -68,7 +237,24 @@ impl Status {
                 },
                 " a hash list to run".into(),
             ]),
+            Line::from(vec![
+                "Run mode: ".into(),
+                if self.append_selected {
+                    "Append".light_cyan()
+                } else {
+                    "Verify".into()
+                },
+                " (press <A> to toggle)".into(),
+            ]),
         ];
+        if let Some(completed_in) = self.completed_in {
+            lines.insert(
+                1,
+                format!("Completed in {:.2}s", completed_in.as_secs_f64())
+                    .light_green()
+                    .into(),
+            );
+        }
 
         let vert_cen_area = vert_center(area, lines.len() as u16);
 
@@ -76,11 +262,14 @@ impl Status {
     }
 }
 
-impl Widget for Status {
+impl Widget for Status<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let status_block = Block::bordered()
+        let mut status_block = Block::bordered()
             .title("Status")
             .padding(Padding::uniform(1));
+        if self.watching {
+            status_block = status_block.title(" Watching ".black().on_light_cyan());
+        }
 
         let inner_area = status_block.inner(area);
 
@@ -0,0 +1,44 @@
+//! Helpers for paths typed by the user
+
+use std::ffi::OsString;
+use std::path::{PathBuf, MAIN_SEPARATOR};
+
+/// Expand a leading `~` to the home directory, and `$VAR` or `${VAR}` to environment variables,
+/// like a shell would. Unset variables, and `~` without a home directory, are kept as written
+pub fn expand_path(s: &str) -> PathBuf {
+    let mut expanded = OsString::new();
+    let mut rest = match (s.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', MAIN_SEPARATOR]) => {
+            expanded.push(home);
+            rest
+        }
+        _ => s,
+    };
+
+    while let Some(idx) = rest.find('$') {
+        expanded.push(&rest[..idx]);
+        let after = &rest[idx + 1..];
+        // Length of the name, with the braces
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+
+        match std::env::var_os(name) {
+            Some(value) if !name.is_empty() => expanded.push(value),
+            _ => expanded.push(&rest[idx..=idx + len]),
+        }
+        rest = &after[len..];
+    }
+    expanded.push(rest);
+
+    PathBuf::from(expanded)
+}
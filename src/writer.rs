@@ -0,0 +1,74 @@
+//! Generated hash lists written entry by entry, in any `HashListFormat`
+
+use crate::hash::{HashAlgorithm, HashListFormat};
+use crate::manifest::{manifest_json, ManifestEntry};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes the entries of a hash list as they are generated. Line formats are written right away,
+/// while a JSON manifest is a single array written by `HashListWriter::finish`
+#[derive(Debug)]
+pub struct HashListWriter<W: Write> {
+    writer: W,
+    format: HashListFormat,
+    algorithm: HashAlgorithm,
+    /// Directory the paths are relative to, for the metadata of JSON manifest entries
+    root: PathBuf,
+    manifest: Vec<ManifestEntry>,
+    header_written: bool,
+}
+
+impl<W: Write> HashListWriter<W> {
+    /// Entries hashed with `algorithm`, relative to `root`, written to `writer` in `format`
+    pub fn new(writer: W, format: HashListFormat, algorithm: HashAlgorithm, root: &Path) -> Self {
+        Self {
+            writer,
+            format,
+            algorithm,
+            root: root.to_path_buf(),
+            manifest: Vec::new(),
+            header_written: false,
+        }
+    }
+
+    /// Write the entry of the file listed as `path`
+    pub fn write_entry(&mut self, path: &str, hash: &str) -> io::Result<()> {
+        match self.format {
+            HashListFormat::JsonManifest => {
+                let file_path = self.root.join(path);
+                let entry =
+                    ManifestEntry::new(path.into(), hash.into(), self.algorithm, &file_path)?;
+                self.manifest.push(entry);
+                Ok(())
+            }
+            HashListFormat::Csv if !self.header_written => {
+                self.header_written = true;
+                writeln!(self.writer, "path,hash")?;
+                self.write_entry(path, hash)
+            }
+            format => writeln!(self.writer, "{}", format.entry(self.algorithm, path, hash)),
+        }
+    }
+
+    /// Write what's left, like the JSON manifest array or the CSV header of an empty hash list,
+    /// and flush. Called once, after the last entry
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self.format {
+            HashListFormat::JsonManifest => {
+                writeln!(self.writer, "{}", manifest_json(&self.manifest))?;
+                self.manifest.clear();
+            }
+            HashListFormat::Csv if !self.header_written => {
+                self.header_written = true;
+                writeln!(self.writer, "path,hash")?;
+            }
+            _ => {}
+        }
+        self.writer.flush()
+    }
+
+    /// The underlying writer, once finished
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
@@ -0,0 +1,1784 @@
+use crossbeam::channel;
+use glob::Pattern;
+use indicatif::ProgressBar;
+use md5check::audit::AuditFormat;
+use md5check::benchmark::{
+    profile_block_sizes, recommend_block_size, BenchmarkSummary, FileStats, PROFILE_BLOCK_SIZES,
+};
+use md5check::cache::{cache_path, IncrementalCache, InodeCache};
+use md5check::compression::Compression;
+use md5check::hash::{
+    append_to_hash_list, auto_find_hash_list, compressed_hash_list, cross_check_hash_lists,
+    detect_renames, diff_hash_lists, dry_run_hash_list, extract_hash_list, find_case_insensitive,
+    find_duplicate_files, find_hash_lists, find_unlisted_files, merge_hash_lists, update_hash_list,
+    write_hash_list_atomic, CrossCheckConflict, DiffKind, HashListFormat, Pause, StatusWrapper,
+};
+use md5check::manifest::{manifest_parser, write_manifest_atomic, ManifestEntry};
+use md5check::notification::notification_body;
+use md5check::reader::{HashListReader, ParseError};
+use md5check::signature::{sign_hash_list, verify_signature, SIGNATURE_PREFIX};
+use md5check::system::detect_threads;
+use md5check::util::expand_path;
+use md5check::writer::HashListWriter;
+use md5check::{
+    hash_file, hash_list_parser, prepare_hashing, ColorMode, DuplicatePolicy, HashAlgorithm,
+    HashError, HashVerifier, MergePolicy, Message, ParseWarning, Setting, SettingBuilder,
+    SettingError, Severity, Status, VerificationOutcome, VerificationStatus,
+};
+use parking_lot::RwLock;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[test]
+fn verify_generated_hash_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let correct = dir.path().join("correct.txt");
+    let incorrect = dir.path().join("incorrect.txt");
+    let missing = dir.path().join("missing.txt");
+    fs::write(&correct, "hello\n").unwrap();
+    fs::write(&incorrect, "world\n").unwrap();
+
+    let setting = Setting::default();
+    let status = StatusWrapper::Status(Arc::new(RwLock::new(Status::default())));
+    let hash = hash_file(&correct, HashAlgorithm::Md5, &setting, &status).unwrap();
+    assert_eq!(hash, "b1946ac92492d2347c6235b4d2611184");
+
+    let list = format!(
+        "{hash}  {}\n{hash}  {}\n{hash}  {}\n",
+        correct.display(),
+        incorrect.display(),
+        missing.display()
+    );
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(&list_path, list).unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert_eq!(hash_list.pairs.len(), 3);
+    assert!(hash_list.warnings.is_empty());
+
+    let status = Arc::new(RwLock::new(Status::default()));
+    let (tx, rx) = channel::unbounded();
+    let summary = prepare_hashing(
+        hash_list.pairs,
+        &setting,
+        StatusWrapper::Status(Arc::clone(&status)),
+        tx,
+        &Pause::default(),
+        &AtomicBool::new(false),
+        &list_path,
+    );
+
+    let status = status.read();
+    assert_eq!(status.correct_num, 1);
+    assert_eq!(status.incorrect_num, 1);
+    assert_eq!(status.missing_num, 1);
+    assert_eq!(status.error_num, 0);
+
+    let outcome = rx
+        .try_iter()
+        .find_map(|x| match x {
+            Message::Completed(outcome) => Some(outcome),
+            _ => None,
+        })
+        .expect("A completed message is sent");
+    assert_eq!(outcome.correct_num, 1);
+    assert_eq!(outcome.incorrect_num, 1);
+    assert_eq!(outcome.missing_num, 1);
+    assert!(!outcome.aborted);
+
+    assert_eq!(summary.outcome.incorrect_num, 1);
+    assert_eq!(
+        summary.incorrect_files,
+        [incorrect.to_string_lossy().into_owned()]
+    );
+}
+
+#[test]
+fn iterate_hash_verifier() {
+    let dir = tempfile::tempdir().unwrap();
+    let correct = dir.path().join("correct.txt");
+    let missing = dir.path().join("missing.txt");
+    fs::write(&correct, "hello\n").unwrap();
+
+    let list = format!(
+        "b1946ac92492d2347c6235b4d2611184  {}\nb1946ac92492d2347c6235b4d2611184  {}\n",
+        correct.display(),
+        missing.display()
+    );
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(&list_path, list).unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    let results: Vec<_> = HashVerifier::new(hash_list.clone(), Setting::default()).collect();
+    assert_eq!(results.len(), 2);
+    assert!(matches!(results[0].status, VerificationStatus::Correct));
+    assert_eq!(
+        results[0].actual.as_deref(),
+        Some("b1946ac92492d2347c6235b4d2611184")
+    );
+    assert!(matches!(results[1].status, VerificationStatus::Missing));
+    assert_eq!(results[1].actual, None);
+
+    let correct_num = HashVerifier::new(hash_list, Setting::default())
+        .par_iter()
+        .filter(|x| matches!(x.status, VerificationStatus::Correct))
+        .count();
+    assert_eq!(correct_num, 1);
+}
+
+#[test]
+fn verify_uppercase_hash_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("file.txt");
+    fs::write(&file, "hello\n").unwrap();
+
+    let list = format!("B1946AC92492D2347C6235B4D2611184  {}\n", file.display());
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(&list_path, list).unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert!(hash_list.warnings.is_empty());
+
+    let results: Vec<_> = HashVerifier::new(hash_list.clone(), Setting::default()).collect();
+    assert!(matches!(results[0].status, VerificationStatus::Correct));
+
+    let setting = Setting {
+        case_sensitive: true,
+        ..Setting::default()
+    };
+    let results: Vec<_> = HashVerifier::new(hash_list, setting).collect();
+    assert!(matches!(results[0].status, VerificationStatus::Incorrect));
+}
+
+#[test]
+fn report_listed_hash_of_correct_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("file.txt"), "hello\n").unwrap();
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(&list_path, "B1946AC92492D2347C6235B4D2611184  file.txt\n").unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_md5check"))
+        .arg("-f")
+        .arg(&list_path)
+        .args(["--format", "json", "--verbose"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let entry: serde_json::Value = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|x| serde_json::from_str(x).unwrap())
+        .find(|x: &serde_json::Value| x["type"] == "correct")
+        .unwrap();
+    assert_eq!(entry["expected_hash"], "B1946AC92492D2347C6235B4D2611184");
+    assert_eq!(entry["actual_hash"], "b1946ac92492d2347c6235b4d2611184");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_md5check"))
+        .arg("-f")
+        .arg(&list_path)
+        .args(["--format", "csv", "--verbose"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let row = "file.txt,B1946AC92492D2347C6235B4D2611184,b1946ac92492d2347c6235b4d2611184,ok,";
+    assert!(stdout.lines().any(|x| x == row));
+}
+
+#[test]
+fn parse_crlf_hash_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("first.txt");
+    let second = dir.path().join("second.txt");
+    fs::write(&first, "hello\n").unwrap();
+    fs::write(&second, "hello\n").unwrap();
+
+    // No line break after the last `\r`, like a file cut short after a transfer
+    let list = format!(
+        "b1946ac92492d2347c6235b4d2611184  {}\r\nb1946ac92492d2347c6235b4d2611184  {}\r",
+        first.display(),
+        second.display()
+    );
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(&list_path, list).unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert!(hash_list.warnings.is_empty());
+    let paths: Vec<_> = hash_list.pairs.iter().map(|x| x.file_path()).collect();
+    assert_eq!(paths, [first.to_str().unwrap(), second.to_str().unwrap()]);
+
+    let all_correct = HashVerifier::new(hash_list, Setting::default())
+        .all(|x| matches!(x.status, VerificationStatus::Correct));
+    assert!(all_correct);
+}
+
+#[test]
+fn parse_comments_and_malformed_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let list = "# Generated by md5sum\n\
+                \n\
+                b1946ac92492d2347c6235b4d2611184  a.txt\n\
+                # not a file deadbeef\n\
+                not a hash line\n";
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(&list_path, list).unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert_eq!(hash_list.pairs.len(), 1);
+    assert_eq!(hash_list.comment_num, 2);
+    assert_eq!(hash_list.malformed_num(), 1);
+    assert_eq!(
+        hash_list.warnings,
+        [ParseWarning::Malformed {
+            line: 5,
+            content: "not a hash line".to_string(),
+        }]
+    );
+
+    // Comments and blank lines are still skipped
+    let err = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, true).unwrap_err();
+    assert!(matches!(
+        err,
+        HashError::MalformedHashList { line: 5, ref content } if content == "not a hash line"
+    ));
+}
+
+#[test]
+fn parse_duplicate_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let list = "b1946ac92492d2347c6235b4d2611184  a.txt\n\
+                591785b794601e212b260e25925636fd  b.txt\n\
+                d41d8cd98f00b204e9800998ecf8427e  a.txt\n";
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(&list_path, list).unwrap();
+
+    let parse = |policy| hash_list_parser(&list_path, None, policy, false);
+
+    let hash_list = parse(DuplicatePolicy::Warn).unwrap();
+    assert_eq!(hash_list.pairs.len(), 3);
+    assert_eq!(
+        hash_list.warnings,
+        [ParseWarning::Duplicate {
+            line: 3,
+            file_path: "a.txt".to_string(),
+        }]
+    );
+
+    let hash_list = parse(DuplicatePolicy::TakeFirst).unwrap();
+    assert_eq!(hash_list.pairs.len(), 2);
+    assert_eq!(
+        hash_list.pairs[0],
+        parse(DuplicatePolicy::Warn).unwrap().pairs[0]
+    );
+
+    let hash_list = parse(DuplicatePolicy::TakeLast).unwrap();
+    assert_eq!(hash_list.pairs.len(), 2);
+    assert_eq!(
+        hash_list.pairs[0],
+        parse(DuplicatePolicy::Warn).unwrap().pairs[2]
+    );
+
+    assert!(matches!(
+        parse(DuplicatePolicy::Error),
+        Err(HashError::DuplicateEntry { line: 3, .. })
+    ));
+}
+
+#[test]
+fn parse_mixed_path_types() {
+    let dir = tempfile::tempdir().unwrap();
+    let absolute = dir.path().join("a.txt");
+    let list_path = dir.path().join("MD5SUMS");
+
+    let list = format!(
+        "b1946ac92492d2347c6235b4d2611184  {}\n\
+         591785b794601e212b260e25925636fd  b.txt\n",
+        absolute.display()
+    );
+    fs::write(&list_path, list).unwrap();
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert_eq!(hash_list.pairs[0].file_path(), absolute.to_str().unwrap());
+    assert_eq!(hash_list.warnings, [ParseWarning::MixedPathTypes]);
+
+    let list = format!("b1946ac92492d2347c6235b4d2611184  {}\n", absolute.display());
+    fs::write(&list_path, list).unwrap();
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert!(hash_list.warnings.is_empty());
+}
+
+#[test]
+fn parse_compressed_hash_lists() {
+    let dir = tempfile::tempdir().unwrap();
+    let entries = [
+        (
+            "a.txt".to_string(),
+            "b1946ac92492d2347c6235b4d2611184".to_string(),
+        ),
+        (
+            "b.txt".to_string(),
+            "591785b794601e212b260e25925636fd".to_string(),
+        ),
+    ];
+
+    for compression in [Compression::Gzip, Compression::Zstd] {
+        let bytes = compressed_hash_list(&entries, compression).unwrap();
+        assert_eq!(Compression::from_magic(&bytes), Some(compression));
+        // Detected from the extension and from the magic bytes
+        for name in ["list.md5.gz", "list.md5.zst", "list.md5"] {
+            let file_path = dir.path().join(name);
+            fs::write(&file_path, &bytes).unwrap();
+            if Compression::from_extension(&file_path).is_some_and(|x| x != compression) {
+                continue;
+            }
+            let hash_list =
+                hash_list_parser(&file_path, None, DuplicatePolicy::Warn, false).unwrap();
+            let paths: Vec<_> = hash_list.pairs.iter().map(|x| x.file_path()).collect();
+            assert_eq!(paths, ["a.txt", "b.txt"]);
+        }
+    }
+    assert_eq!(Compression::from_magic(b"b1946ac9"), None);
+}
+
+#[test]
+fn parse_hash_list_with_bom() {
+    let dir = tempfile::tempdir().unwrap();
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(
+        &list_path,
+        b"\xEF\xBB\xBFb1946ac92492d2347c6235b4d2611184  a.txt\n",
+    )
+    .unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert!(hash_list.warnings.is_empty());
+    assert_eq!(hash_list.pairs.len(), 1);
+    assert_eq!(hash_list.pairs[0].file_path(), "a.txt");
+}
+
+#[test]
+fn hash_list_reader_auto_detect() {
+    let hash = "0cc175b9c0f1b6a831c399e269772661";
+    let detect =
+        |content: &str| <dyn HashListReader>::auto_detect(content.as_bytes(), None).unwrap();
+
+    let mut reader = detect(&format!(
+        "# comment\nMD5 (a.txt) = {hash}\n{hash}  b.txt\nbad\n"
+    ));
+    assert_eq!(reader.format(), HashListFormat::Bsd);
+    let entries: Vec<_> = reader.entries().collect();
+    assert_eq!(entries.len(), 3);
+    let pair = entries[1].as_ref().unwrap();
+    assert_eq!((pair.file_path(), pair.expected_hash()), ("b.txt", hash));
+    assert_eq!(
+        entries[2],
+        Err(ParseError::Malformed {
+            line: 4,
+            content: "bad".into()
+        })
+    );
+    assert_eq!(reader.comment_num(), 1);
+
+    // Not mistaken for a JSON manifest
+    let mut reader = detect("; comment\n[Group] file.mkv E8B7BE43\n");
+    assert_eq!(reader.format(), HashListFormat::Sfv);
+    let pair = reader.entries().next().unwrap().unwrap();
+    assert_eq!(pair.file_path(), "[Group] file.mkv");
+    assert_eq!(pair.algorithm(), HashAlgorithm::Crc32);
+
+    let manifest = format!(
+        r#"[{{"path": "a.txt", "hash": "{hash}", "algorithm": "md5", "size": 1,
+            "mtime": "2024-01-31T12:00:00Z", "mode": "644"}}]"#
+    );
+    let mut reader = detect(&manifest);
+    assert_eq!(reader.format(), HashListFormat::JsonManifest);
+    assert_eq!(
+        reader.entries().next().unwrap().unwrap().file_path(),
+        "a.txt"
+    );
+
+    let mut reader = detect("[{");
+    assert!(matches!(
+        reader.entries().next(),
+        Some(Err(ParseError::Invalid { .. }))
+    ));
+}
+
+#[test]
+fn hash_list_parser_reads_json_manifest() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("a.txt");
+    fs::write(&file_path, "a").unwrap();
+    let entry = ManifestEntry::new(
+        "a.txt".into(),
+        "0cc175b9c0f1b6a831c399e269772661".into(),
+        HashAlgorithm::Md5,
+        &file_path,
+    )
+    .unwrap();
+    let list_path = dir.path().join("list.json");
+    write_manifest_atomic(&list_path, &[entry]).unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert_eq!(hash_list.pairs.len(), 1);
+    assert_eq!(hash_list.algorithm, HashAlgorithm::Md5);
+    assert_eq!(hash_list.format, HashListFormat::JsonManifest);
+
+    // Entries aren't lines, so the manifest is left as is
+    let content = fs::read(&list_path).unwrap();
+    let new_hashes = HashMap::from([("a.txt".to_string(), "0".repeat(32))]);
+    let deleted = HashSet::from(["a.txt".to_string()]);
+    assert!(matches!(
+        update_hash_list(&list_path, &hash_list.pairs, &new_hashes, &HashSet::new()),
+        Err(HashError::ManifestNotEditable)
+    ));
+    assert!(matches!(
+        update_hash_list(&list_path, &hash_list.pairs, &HashMap::new(), &deleted),
+        Err(HashError::ManifestNotEditable)
+    ));
+    let entries = [("b.txt".to_string(), "0".repeat(32))];
+    assert!(matches!(
+        append_to_hash_list(&list_path, hash_list.format, hash_list.algorithm, &entries),
+        Err(HashError::ManifestNotEditable)
+    ));
+    assert!(matches!(
+        sign_hash_list(&list_path, &[0; 32]),
+        Err(HashError::ManifestNotEditable)
+    ));
+    assert_eq!(fs::read(&list_path).unwrap(), content);
+}
+
+#[test]
+fn hash_list_writer_formats() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a,b.txt"), "a").unwrap();
+    let hash = "0cc175b9c0f1b6a831c399e269772661";
+    let write = |format| {
+        let mut writer = HashListWriter::new(Vec::new(), format, HashAlgorithm::Md5, dir.path());
+        writer.write_entry("a,b.txt", hash).unwrap();
+        writer.finish().unwrap();
+        String::from_utf8(writer.into_inner()).unwrap()
+    };
+
+    assert_eq!(write(HashListFormat::Gnu), format!("{hash}  a,b.txt\n"));
+    assert_eq!(
+        write(HashListFormat::Bsd),
+        format!("MD5 (a,b.txt) = {hash}\n")
+    );
+    assert_eq!(
+        write(HashListFormat::Sfv),
+        format!("a,b.txt {}\n", hash.to_ascii_uppercase())
+    );
+    assert_eq!(
+        write(HashListFormat::Csv),
+        format!("path,hash\n\"a,b.txt\",{hash}\n")
+    );
+
+    let manifest: Vec<ManifestEntry> =
+        serde_json::from_str(&write(HashListFormat::JsonManifest)).unwrap();
+    assert_eq!(manifest.len(), 1);
+    assert_eq!((manifest[0].hash.as_str(), manifest[0].size), (hash, 1));
+
+    // Only the header of an empty CSV hash list
+    let mut writer = HashListWriter::new(
+        Vec::new(),
+        HashListFormat::Csv,
+        HashAlgorithm::Md5,
+        dir.path(),
+    );
+    writer.finish().unwrap();
+    assert_eq!(writer.into_inner(), b"path,hash\n");
+}
+
+#[test]
+fn write_hash_list_in_selected_format() {
+    let dir = tempfile::tempdir().unwrap();
+    let list_path = dir.path().join("SUMS");
+    let entries = [("a.txt".to_string(), "e8b7be43".to_string())];
+
+    for format in [
+        HashListFormat::Gnu,
+        HashListFormat::Bsd,
+        HashListFormat::Sfv,
+    ] {
+        write_hash_list_atomic(&list_path, format, HashAlgorithm::Crc32, &entries).unwrap();
+        let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+        assert_eq!(hash_list.format, format);
+        assert_eq!(hash_list.algorithm, HashAlgorithm::Crc32);
+        assert_eq!(hash_list.pairs[0].expected_hash(), "e8b7be43");
+    }
+
+    // SFV hash lists are always CRC-32
+    let setting = SettingBuilder::new()
+        .hash_list_format(HashListFormat::Sfv)
+        .build()
+        .unwrap();
+    assert_eq!(setting.generation_algorithm(), HashAlgorithm::Crc32);
+    assert_eq!(
+        SettingBuilder::new()
+            .hash_list_format(HashListFormat::Sfv)
+            .algorithm(HashAlgorithm::Md5)
+            .build()
+            .unwrap_err(),
+        SettingError::SfvAlgorithm(HashAlgorithm::Md5)
+    );
+}
+
+#[test]
+fn parse_windows_1252_hash_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let list_path = dir.path().join("MD5SUMS");
+    // "café.txt" with é as 0xE9, which isn't valid UTF-8
+    fs::write(
+        &list_path,
+        b"b1946ac92492d2347c6235b4d2611184  caf\xE9.txt\n",
+    )
+    .unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert_eq!(hash_list.warnings, [ParseWarning::EncodingFallback]);
+    assert_eq!(hash_list.pairs[0].file_path(), "café.txt");
+}
+
+#[test]
+fn hash_1mib_file_with_each_algorithm() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("golden.bin");
+    let content: Vec<u8> = (0..1 << 20).map(|x| (x % 251) as u8).collect();
+    fs::write(&file_path, content).unwrap();
+
+    let cases = [
+        (HashAlgorithm::Md5, "8f293a2f6c19b345152f7a49bb4c643c"),
+        (
+            HashAlgorithm::Sha1,
+            "c2fc4cb20f1301a6b0dd211c19e69a13925dbe40",
+        ),
+        (
+            HashAlgorithm::Sha224,
+            "5ea506c312141d911fb8909367ae6ecd22513c9fe337046ec36f3dde",
+        ),
+        (
+            HashAlgorithm::Sha256,
+            "631b84027d6b9e52b539c4e8373622d23032dfadc64d60af87339c9037e4f769",
+        ),
+        (
+            HashAlgorithm::Sha384,
+            "c0b5633796816dc370458921d148550ed3e490ca635324e1\
+             9b86a26c500577c623a37ecc5de698c222fba72e08e0ee5b",
+        ),
+        (
+            HashAlgorithm::Sha512,
+            "67dad569eefc986a3b2424f5516d5a0284bb53d7b52d75f5ed881a6830a95765\
+             ccc82bc48752fb693422579f11dc9a400561ec1885af9eeef703dbbd312d4fd0",
+        ),
+        (
+            HashAlgorithm::Blake2b(32),
+            "8a5a7a9dc3cf203ed374b0a1eea930601ad2acbfe2b4bc62cf83de4ee536528b",
+        ),
+        (
+            HashAlgorithm::Blake2b(64),
+            "797c6241704933d0c62cea0793db1dd5c65ffd258f8340d394d2cd26b7bf5370\
+             46ebb5914fb1fae7635ce1f379fb819abc57ad509c015bb4dba4bc981bb1c446",
+        ),
+        (
+            HashAlgorithm::Blake3,
+            "74cb441fd087764ca9c3694da742ebe30cbeb3060a17009ca81825c7a8d10343",
+        ),
+        (HashAlgorithm::Crc32, "ef0e6054"),
+    ];
+    let setting = Setting::default();
+    let status = StatusWrapper::Status(Arc::new(RwLock::new(Status::default())));
+    for (algorithm, golden) in cases {
+        let hash = hash_file(&file_path, algorithm, &setting, &status).unwrap();
+        assert_eq!(hash, golden, "{algorithm}");
+    }
+}
+
+#[test]
+fn mmap_and_buffered_reads_match() {
+    let dir = tempfile::tempdir().unwrap();
+    let threshold = 64 * 1024;
+    let below = dir.path().join("below.bin");
+    let above = dir.path().join("above.bin");
+    let content: Vec<u8> = (0..4 * threshold).map(|x| (x % 251) as u8).collect();
+    fs::write(&below, &content[..threshold as usize / 2]).unwrap();
+    fs::write(&above, &content).unwrap();
+
+    let buffered = SettingBuilder::new()
+        .mmap_threshold(u64::MAX)
+        .build()
+        .unwrap();
+    let by_threshold = SettingBuilder::new()
+        .mmap_threshold(threshold)
+        .build()
+        .unwrap();
+    let forced = SettingBuilder::new()
+        .mmap(true)
+        .mmap_threshold(threshold)
+        .build()
+        .unwrap();
+    let status = StatusWrapper::Status(Arc::new(RwLock::new(Status::default())));
+    let hash = |file_path: &Path, setting: &Setting| {
+        hash_file(file_path, HashAlgorithm::Sha256, setting, &status).unwrap()
+    };
+
+    let above_hash = "31a1f9dea0169551092d05e8bf4a446228c8c3eb4c9b713c66adcb7fd53c89be";
+    let below_hash = "09fed9cbfb98b6ab0f3e8ff63b7b1f9b0e07d58b225295c78fdc023cc4985a72";
+    assert_eq!(hash(&above, &buffered), above_hash);
+    // Memory mapped because of the threshold
+    assert_eq!(hash(&above, &by_threshold), above_hash);
+    assert_eq!(hash(&below, &buffered), below_hash);
+    // Only memory mapped with `--mmap`
+    assert_eq!(hash(&below, &forced), below_hash);
+}
+
+#[test]
+fn detect_hash_list_algorithm() {
+    let dir = tempfile::tempdir().unwrap();
+    let list_path = dir.path().join("SUMS");
+    let digest = |len| "0".repeat(len);
+
+    let cases = [
+        (digest(32), HashAlgorithm::Md5),
+        (digest(40), HashAlgorithm::Sha1),
+        (digest(56), HashAlgorithm::Sha224),
+        (digest(64), HashAlgorithm::Sha256),
+        (digest(96), HashAlgorithm::Sha384),
+        (digest(128), HashAlgorithm::Sha512),
+    ];
+    for (hash, algorithm) in cases {
+        fs::write(&list_path, format!("{hash}  a.txt\n")).unwrap();
+        let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+        assert_eq!(hash_list.algorithm, algorithm);
+    }
+
+    fs::write(
+        &list_path,
+        format!("# Algorithm: blake3\n{}  a.txt\n", digest(64)),
+    )
+    .unwrap();
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert_eq!(hash_list.algorithm, HashAlgorithm::Blake3);
+
+    // A plain `sha512sum` list verifies without `--algorithm`
+    let file_path = dir.path().join("a.txt");
+    fs::write(&file_path, "hello\n").unwrap();
+    let hash = "e7c22b994c59d9cf2b48e549b1e24666636045930d3da7c1acb299d1c3b7f931\
+                f94aae41edda2c2b207a36e10f8bcb8d45223e54878f5b316e7ce3b6bc019629";
+    fs::write(&list_path, format!("{hash}  {}\n", file_path.display())).unwrap();
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    let results: Vec<_> = HashVerifier::new(hash_list, Setting::default()).collect();
+    assert!(matches!(results[0].status, VerificationStatus::Correct));
+}
+
+#[test]
+fn append_unlisted_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_path = dir.path().canonicalize().unwrap();
+    let listed = dir_path.join("listed.txt");
+    let new = dir_path.join("new.txt");
+    fs::write(&listed, "hello\n").unwrap();
+    fs::write(&new, "hello\n").unwrap();
+
+    // No line break at the end
+    let list = format!(
+        "MD5 ({}) = b1946ac92492d2347c6235b4d2611184",
+        listed.display()
+    );
+    let list_path = dir_path.join("MD5SUMS");
+    fs::write(&list_path, list).unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert_eq!(hash_list.format, HashListFormat::Bsd);
+    let unlisted = find_unlisted_files(&hash_list.pairs, &list_path, &dir_path).unwrap();
+    assert_eq!(unlisted, [new.as_path()]);
+
+    let entries = [(
+        new.to_string_lossy().into_owned(),
+        "b1946ac92492d2347c6235b4d2611184".to_string(),
+    )];
+    append_to_hash_list(&list_path, hash_list.format, hash_list.algorithm, &entries).unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert!(hash_list.warnings.is_empty());
+    assert_eq!(hash_list.pairs.len(), 2);
+    assert_eq!(hash_list.pairs[1].file_path(), new.to_string_lossy());
+}
+
+#[test]
+fn update_and_delete_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let changed = dir.path().join("changed.txt");
+    let same = dir.path().join("same.txt");
+    let gone = dir.path().join("gone.txt");
+    fs::write(&changed, "world\n").unwrap();
+    fs::write(&same, "hello\n").unwrap();
+
+    let list = format!(
+        "# Keep me\nMD5 ({}) = b1946ac92492d2347c6235b4d2611184\nb1946ac92492d2347c6235b4d2611184  {}\nb1946ac92492d2347c6235b4d2611184  {}\n",
+        changed.display(),
+        gone.display(),
+        same.display()
+    );
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(&list_path, list).unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    let new_hashes = HashMap::from([
+        (
+            changed.to_string_lossy().into_owned(),
+            "591785b794601e212b260e25925636fd".to_string(),
+        ),
+        (
+            same.to_string_lossy().into_owned(),
+            "b1946ac92492d2347c6235b4d2611184".to_string(),
+        ),
+    ]);
+    let deleted = HashSet::from([gone.to_string_lossy().into_owned()]);
+    let updated = update_hash_list(&list_path, &hash_list.pairs, &new_hashes, &deleted).unwrap();
+    assert_eq!(updated, 1);
+
+    let expected = format!(
+        "# Keep me\nMD5 ({}) = 591785b794601e212b260e25925636fd\nb1946ac92492d2347c6235b4d2611184  {}\n",
+        changed.display(),
+        same.display()
+    );
+    assert_eq!(fs::read_to_string(&list_path).unwrap(), expected);
+}
+
+#[test]
+fn merge_conflicting_hash_lists() {
+    let dir = tempfile::tempdir().unwrap();
+    let first_path = dir.path().join("first.md5");
+    let second_path = dir.path().join("second.md5");
+    fs::write(
+        &first_path,
+        "MD5 (a.txt) = b1946ac92492d2347c6235b4d2611184\nMD5 (b.txt) = b1946ac92492d2347c6235b4d2611184\n",
+    )
+    .unwrap();
+    fs::write(
+        &second_path,
+        "591785b794601e212b260e25925636fd  b.txt\n591785b794601e212b260e25925636fd  c.txt\n",
+    )
+    .unwrap();
+    let parse = |x| hash_list_parser(x, None, DuplicatePolicy::Warn, false).unwrap();
+
+    let merged = merge_hash_lists(
+        vec![parse(&first_path), parse(&second_path)],
+        MergePolicy::Last,
+    );
+    assert_eq!(merged.format, HashListFormat::Bsd);
+    let paths: Vec<_> = merged.pairs.iter().map(|x| x.file_path()).collect();
+    assert_eq!(paths, ["a.txt", "b.txt", "c.txt"]);
+    assert_eq!(
+        merged.warnings,
+        [ParseWarning::MergeConflict {
+            file_path: "b.txt".to_string(),
+            kept_hash: "591785b794601e212b260e25925636fd".to_string(),
+            dropped_hash: "b1946ac92492d2347c6235b4d2611184".to_string(),
+        }]
+    );
+
+    let merged = merge_hash_lists(
+        vec![parse(&first_path), parse(&second_path)],
+        MergePolicy::First,
+    );
+    assert!(matches!(
+        &merged.warnings[0],
+        ParseWarning::MergeConflict { kept_hash, .. } if kept_hash == "b1946ac92492d2347c6235b4d2611184"
+    ));
+}
+
+#[test]
+fn extract_entries_by_glob() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("list.md5");
+    fs::write(
+        &file_path,
+        "MD5 (src/main.rs) = b1946ac92492d2347c6235b4d2611184\nMD5 (src/ui/mod.rs) = 591785b794601e212b260e25925636fd\nMD5 (README.md) = b1946ac92492d2347c6235b4d2611184\n",
+    )
+    .unwrap();
+    let hash_list = hash_list_parser(&file_path, None, DuplicatePolicy::Warn, false).unwrap();
+
+    let extracted = extract_hash_list(hash_list.clone(), &Pattern::new("src/**/*.rs").unwrap());
+    assert_eq!(
+        extracted.lines().collect::<Vec<_>>(),
+        [
+            "MD5 (src/main.rs) = b1946ac92492d2347c6235b4d2611184",
+            "MD5 (src/ui/mod.rs) = 591785b794601e212b260e25925636fd"
+        ]
+    );
+
+    let extracted = extract_hash_list(hash_list, &Pattern::new("*.txt").unwrap());
+    assert!(extracted.pairs.is_empty());
+}
+
+#[test]
+fn cross_check_conflicting_hash_lists() {
+    let dir = tempfile::tempdir().unwrap();
+    let first_path = dir.path().join("first.md5");
+    let second_path = dir.path().join("second.md5");
+    fs::write(
+        &first_path,
+        "b1946ac92492d2347c6235b4d2611184  a.txt\nb1946ac92492d2347c6235b4d2611184  b.txt\n",
+    )
+    .unwrap();
+    fs::write(
+        &second_path,
+        "B1946AC92492D2347C6235B4D2611184  a.txt\n591785b794601e212b260e25925636fd  b.txt\n591785b794601e212b260e25925636fd  c.txt\n",
+    )
+    .unwrap();
+    let parse = |x| hash_list_parser(x, None, DuplicatePolicy::Warn, false).unwrap();
+
+    let conflicts = cross_check_hash_lists(&parse(&first_path), &parse(&second_path));
+    assert_eq!(
+        conflicts,
+        [CrossCheckConflict {
+            path: "b.txt".to_string(),
+            hash1: "b1946ac92492d2347c6235b4d2611184".to_string(),
+            hash2: "591785b794601e212b260e25925636fd".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn sign_and_verify_hash_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("list.md5");
+    fs::write(&file_path, "b1946ac92492d2347c6235b4d2611184  a.txt").unwrap();
+    let key = [7; 32];
+
+    let signature = sign_hash_list(&file_path, &key).unwrap();
+    let content = fs::read_to_string(&file_path).unwrap();
+    assert_eq!(
+        content,
+        format!("b1946ac92492d2347c6235b4d2611184  a.txt\n{SIGNATURE_PREFIX}{signature}\n")
+    );
+    verify_signature(&file_path, &key).unwrap();
+    // Still parsed as usual
+    let hash_list = hash_list_parser(&file_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert_eq!(hash_list.pairs.len(), 1);
+    assert!(hash_list.warnings.is_empty());
+
+    // Signing again replaces the signature
+    assert_eq!(sign_hash_list(&file_path, &key).unwrap(), signature);
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), content);
+
+    assert!(matches!(
+        verify_signature(&file_path, &[8; 32]),
+        Err(HashError::InvalidSignature)
+    ));
+    fs::write(&file_path, content.replace("a.txt", "b.txt")).unwrap();
+    assert!(matches!(
+        verify_signature(&file_path, &key),
+        Err(HashError::InvalidSignature)
+    ));
+    fs::write(&file_path, "b1946ac92492d2347c6235b4d2611184  a.txt\n").unwrap();
+    assert!(matches!(
+        verify_signature(&file_path, &key),
+        Err(HashError::InvalidSignature)
+    ));
+}
+
+#[test]
+fn diff_two_hash_lists() {
+    let dir = tempfile::tempdir().unwrap();
+    let old_path = dir.path().join("old.md5");
+    let new_path = dir.path().join("new.md5");
+    fs::write(
+        &old_path,
+        "b1946ac92492d2347c6235b4d2611184  removed.txt\n\
+         b1946ac92492d2347c6235b4d2611184  changed.txt\n\
+         b1946ac92492d2347c6235b4d2611184  same.txt\n",
+    )
+    .unwrap();
+    fs::write(
+        &new_path,
+        "591785b794601e212b260e25925636fd  changed.txt\n\
+         B1946AC92492D2347C6235B4D2611184  same.txt\n\
+         591785b794601e212b260e25925636fd  added.txt\n",
+    )
+    .unwrap();
+    let parse = |x| {
+        hash_list_parser(x, None, DuplicatePolicy::Warn, false)
+            .unwrap()
+            .pairs
+    };
+
+    let diff: Vec<_> = diff_hash_lists(&parse(&old_path), &parse(&new_path))
+        .into_iter()
+        .map(|x| (x.file_path, x.kind))
+        .collect();
+    assert_eq!(
+        diff,
+        [
+            ("added.txt".to_string(), DiffKind::Added),
+            ("changed.txt".to_string(), DiffKind::Changed),
+            ("removed.txt".to_string(), DiffKind::Removed),
+            ("same.txt".to_string(), DiffKind::Unchanged),
+        ]
+    );
+}
+
+#[test]
+fn diff_detect_renames_and_copies() {
+    let dir = tempfile::tempdir().unwrap();
+    let old_path = dir.path().join("old.md5");
+    let new_path = dir.path().join("new.md5");
+    fs::write(
+        &old_path,
+        "b1946ac92492d2347c6235b4d2611184  old_name.txt\n\
+         591785b794601e212b260e25925636fd  kept.txt\n",
+    )
+    .unwrap();
+    fs::write(
+        &new_path,
+        "b1946ac92492d2347c6235b4d2611184  new_name.txt\n\
+         591785b794601e212b260e25925636fd  kept.txt\n\
+         591785b794601e212b260e25925636fd  kept_copy.txt\n",
+    )
+    .unwrap();
+    let parse = |x| {
+        hash_list_parser(x, None, DuplicatePolicy::Warn, false)
+            .unwrap()
+            .pairs
+    };
+
+    let diff: Vec<_> = detect_renames(diff_hash_lists(&parse(&old_path), &parse(&new_path)))
+        .into_iter()
+        .map(|x| (x.file_path, x.kind))
+        .collect();
+    assert_eq!(
+        diff,
+        [
+            ("kept.txt".to_string(), DiffKind::Unchanged),
+            (
+                "kept_copy.txt".to_string(),
+                DiffKind::Copied {
+                    from: "kept.txt".to_string()
+                }
+            ),
+            (
+                "new_name.txt".to_string(),
+                DiffKind::Renamed {
+                    from: "old_name.txt".to_string()
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn find_duplicates_by_hash() {
+    let dir = tempfile::tempdir().unwrap();
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(
+        &list_path,
+        "b1946ac92492d2347c6235b4d2611184  a.txt\n\
+         591785b794601e212b260e25925636fd  unique.txt\n\
+         B1946AC92492D2347C6235B4D2611184  b.txt\n\
+         b1946ac92492d2347c6235b4d2611184  a.txt\n",
+    )
+    .unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    assert_eq!(
+        find_duplicate_files(&hash_list.pairs),
+        [(
+            "b1946ac92492d2347c6235b4d2611184".to_string(),
+            vec!["a.txt".to_string(), "b.txt".to_string()]
+        )]
+    );
+}
+
+#[test]
+fn json_manifest_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("a.txt");
+    fs::write(&file_path, "hello\n").unwrap();
+    let hash = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03".to_string();
+
+    let entry =
+        ManifestEntry::new("a.txt".to_string(), hash, HashAlgorithm::Sha256, &file_path).unwrap();
+    assert_eq!(entry.algorithm, "sha256");
+    assert_eq!(entry.size, 6);
+    assert!(entry.mtime.ends_with('Z'));
+
+    let manifest_path = dir.path().join("manifest.json");
+    write_manifest_atomic(&manifest_path, &[entry]).unwrap();
+    let hash_list = manifest_parser(&manifest_path).unwrap();
+    assert_eq!(hash_list.algorithm, HashAlgorithm::Sha256);
+    assert_eq!(hash_list.pairs.len(), 1);
+    assert_eq!(hash_list.pairs[0].file_path(), "a.txt");
+}
+
+#[test]
+fn base_dir_overrides_hash_list_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let checksums_dir = dir.path().join("checksums");
+    fs::create_dir(&checksums_dir).unwrap();
+    let list_path = checksums_dir.join("MD5SUMS");
+
+    assert_eq!(
+        Setting::default().files_dir(&list_path).unwrap(),
+        checksums_dir
+    );
+    let setting = SettingBuilder::new()
+        .base_dir(checksums_dir.join(".."))
+        .build()
+        .unwrap();
+    assert_eq!(
+        setting.files_dir(&list_path).unwrap(),
+        dir.path().canonicalize().unwrap()
+    );
+}
+
+#[test]
+fn find_file_with_other_case() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("Sub")).unwrap();
+    fs::write(dir.path().join("Sub/File.txt"), "a").unwrap();
+    fs::write(dir.path().join("Sub/FILE.TXT"), "b").unwrap();
+
+    // The closest match wins
+    assert_eq!(
+        find_case_insensitive(&dir.path().join("sub/file.txt")),
+        Some(dir.path().join("Sub/File.txt"))
+    );
+    assert_eq!(
+        find_case_insensitive(&dir.path().join("sub/other.txt")),
+        None
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn parse_windows_path_separators() {
+    let dir = tempfile::tempdir().unwrap();
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(
+        &list_path,
+        "b1946ac92492d2347c6235b4d2611184  sub\\dir\\a.txt\r\n\
+         MD5 (sub\\b.txt) = 591785b794601e212b260e25925636fd\r\n",
+    )
+    .unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    let paths: Vec<_> = hash_list.pairs.iter().map(|x| x.file_path()).collect();
+    assert_eq!(paths, ["sub/dir/a.txt", "sub/b.txt"]);
+}
+
+#[test]
+fn expand_home_and_variables() {
+    std::env::set_var("MD5CHECK_TEST_DIR", "/data");
+    std::env::remove_var("MD5CHECK_UNSET_DIR");
+
+    assert_eq!(expand_path("$MD5CHECK_TEST_DIR/a"), Path::new("/data/a"));
+    assert_eq!(expand_path("${MD5CHECK_TEST_DIR}b"), Path::new("/datab"));
+    assert_eq!(
+        expand_path("$MD5CHECK_UNSET_DIR/a"),
+        Path::new("$MD5CHECK_UNSET_DIR/a")
+    );
+    assert_eq!(expand_path("a~/${b"), Path::new("a~/${b"));
+    if let Some(home) = dirs::home_dir() {
+        assert_eq!(expand_path("~/a"), home.join("a"));
+    }
+}
+
+#[test]
+fn rate_limit_slows_reads() {
+    assert_eq!(
+        SettingBuilder::new().rate_limit(0.0).build().unwrap_err(),
+        SettingError::InvalidRateLimit
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("a.bin");
+    fs::write(&file_path, vec![0u8; 2_000_000]).unwrap();
+    let setting = SettingBuilder::new()
+        .parallel(false)
+        .rate_limit(10.0)
+        .build()
+        .unwrap();
+    let status = StatusWrapper::Status(Arc::new(RwLock::new(Status::default())));
+
+    // 2 MB at 10 MB/s
+    let start = Instant::now();
+    hash_file(&file_path, HashAlgorithm::Md5, &setting, &status).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(190));
+}
+
+#[test]
+fn cpu_limit_must_be_a_fraction() {
+    for cpu_limit in [0.0, 1.5, f32::NAN] {
+        assert_eq!(
+            SettingBuilder::new()
+                .cpu_limit(cpu_limit)
+                .build()
+                .unwrap_err(),
+            SettingError::InvalidCpuLimit
+        );
+    }
+    assert!(SettingBuilder::new().cpu_limit(1.0).build().is_ok());
+}
+
+#[test]
+fn ignore_or_fail_empty_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let empty = dir.path().join("empty.txt");
+    fs::write(&empty, "").unwrap();
+    let empty_hash = HashAlgorithm::Md5.empty_hash();
+    assert_eq!(empty_hash, "d41d8cd98f00b204e9800998ecf8427e");
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(&list_path, format!("{empty_hash}  {}\n", empty.display())).unwrap();
+
+    let run = |setting: Setting| {
+        let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+        let status = Arc::new(RwLock::new(Status::default()));
+        let (tx, _rx) = channel::unbounded();
+        prepare_hashing(
+            hash_list.pairs,
+            &setting,
+            StatusWrapper::Status(Arc::clone(&status)),
+            tx,
+            &Pause::default(),
+            &AtomicBool::new(false),
+            &list_path,
+        );
+        let status = status.read().clone();
+        (status.correct_num, status.skipped_num, status.error_num)
+    };
+
+    assert_eq!(run(Setting::default()), (1, 0, 0));
+    let ignore_zero = SettingBuilder::new().ignore_zero(true).build().unwrap();
+    assert_eq!(run(ignore_zero), (0, 1, 0));
+    let strict_zero = SettingBuilder::new().strict_zero(true).build().unwrap();
+    assert_eq!(run(strict_zero), (0, 0, 1));
+}
+
+#[test]
+fn audit_log_appends_results() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("good.txt"), "hello\n").unwrap();
+    fs::write(dir.path().join("bad.txt"), "hello\n").unwrap();
+    let list_path = dir.path().join("MD5SUMS");
+    let list = format!(
+        "b1946ac92492d2347c6235b4d2611184  {}\n\
+         00000000000000000000000000000000  {}\n\
+         00000000000000000000000000000000  {}\n",
+        dir.path().join("good.txt").display(),
+        dir.path().join("bad.txt").display(),
+        dir.path().join("missing.txt").display(),
+    );
+    fs::write(&list_path, list).unwrap();
+
+    let run = |audit_log: &Path, format: AuditFormat| {
+        let setting = SettingBuilder::new()
+            .parallel(false)
+            .audit_log(audit_log.to_path_buf())
+            .audit_format(format)
+            .build()
+            .unwrap();
+        let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+        let status = StatusWrapper::Status(Arc::new(RwLock::new(Status::default())));
+        let (tx, _rx) = channel::unbounded();
+        prepare_hashing(
+            hash_list.pairs,
+            &setting,
+            status,
+            tx,
+            &Pause::default(),
+            &AtomicBool::new(false),
+            &list_path,
+        );
+    };
+
+    let json_log = dir.path().join("audit.jsonl");
+    run(&json_log, AuditFormat::Json);
+    run(&json_log, AuditFormat::Json);
+    let entries: Vec<serde_json::Value> = fs::read_to_string(&json_log)
+        .unwrap()
+        .lines()
+        .map(|x| serde_json::from_str(x).unwrap())
+        .collect();
+    let results: Vec<_> = entries
+        .iter()
+        .map(|x| x["result"].as_str().unwrap())
+        .collect();
+    assert_eq!(results, ["ok", "fail", "error", "ok", "fail", "error"]);
+    assert_eq!(entries[0]["actual"], "b1946ac92492d2347c6235b4d2611184");
+    assert_eq!(entries[2]["actual"], "");
+    assert_eq!(entries[0]["pid"], std::process::id());
+
+    let csv_log = dir.path().join("audit.csv");
+    run(&csv_log, AuditFormat::Csv);
+    run(&csv_log, AuditFormat::Csv);
+    let csv = fs::read_to_string(&csv_log).unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines.len(), 7);
+    assert_eq!(
+        lines[0],
+        "timestamp,pid,user,file,expected,actual,result,duration_ms"
+    );
+    assert!(lines[1].contains(",b1946ac92492d2347c6235b4d2611184,ok,"));
+}
+
+#[test]
+fn resume_from_checkpoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let good = dir.path().join("good.txt");
+    let bad = dir.path().join("bad.txt");
+    fs::write(&good, "hello\n").unwrap();
+    fs::write(&bad, "hello\n").unwrap();
+    let list_path = dir.path().join("MD5SUMS");
+    let list = format!(
+        "00000000000000000000000000000000  {}\n\
+         b1946ac92492d2347c6235b4d2611184  {}\n",
+        bad.display(),
+        good.display(),
+    );
+    fs::write(&list_path, list).unwrap();
+    let checkpoint = dir.path().join(".md5check_progress");
+
+    let run = |fail_fast: bool| {
+        let setting = SettingBuilder::new()
+            .parallel(false)
+            .fail_fast(fail_fast)
+            .checkpoint_file(checkpoint.clone())
+            .resume(true)
+            .build()
+            .unwrap();
+        let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+        let status = StatusWrapper::Status(Arc::new(RwLock::new(Status::default())));
+        let (tx, rx) = channel::unbounded();
+        let summary = prepare_hashing(
+            hash_list.pairs,
+            &setting,
+            status,
+            tx,
+            &Pause::default(),
+            &AtomicBool::new(false),
+            &list_path,
+        );
+        let warnings: Vec<_> = rx
+            .try_iter()
+            .filter_map(|x| match x {
+                Message::Warning(x) => Some(x),
+                _ => None,
+            })
+            .collect();
+        (summary.outcome, warnings)
+    };
+
+    // Saved with the results so far when the run stops early
+    let (outcome, _) = run(true);
+    assert!(outcome.aborted);
+    let saved: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&checkpoint).unwrap()).unwrap();
+    assert_eq!(saved[0]["path"], bad.to_str().unwrap());
+    assert_eq!(saved[0]["result"], "incorrect");
+
+    // The incorrect file isn't hashed again, and the checkpoint is deleted at the end
+    let (outcome, warnings) = run(false);
+    assert_eq!((outcome.correct_num, outcome.incorrect_num), (1, 1));
+    assert!(warnings[0].contains("skipping 1 verified files"));
+    assert!(!checkpoint.exists());
+
+    fs::write(&checkpoint, "{ not json").unwrap();
+    let (outcome, warnings) = run(false);
+    assert_eq!((outcome.correct_num, outcome.incorrect_num), (1, 1));
+    assert!(warnings[0].starts_with("Corrupted checkpoint file"));
+    assert!(!checkpoint.exists());
+}
+
+#[test]
+fn incremental_cache_checks_algorithm() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("a.txt");
+    fs::write(&file_path, "a").unwrap();
+    let file_path = file_path.to_str().unwrap();
+    let cache_path = cache_path(&dir.path().join("SUMS"));
+    let hash = "0".repeat(128);
+
+    let cache = IncrementalCache::open(cache_path.clone(), false);
+    cache.update(file_path, HashAlgorithm::Blake2b(64), Some(&hash));
+    cache.save().unwrap();
+
+    // SHA-512 digests have the same length, but aren't the same
+    let cache = IncrementalCache::open(cache_path, false);
+    assert_eq!(cache.lookup(file_path, HashAlgorithm::Sha512), None);
+    assert_eq!(
+        cache.lookup(file_path, HashAlgorithm::Blake2b(64)),
+        Some(hash)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn inode_cache_checks_algorithm() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("a.txt");
+    let link_path = dir.path().join("b.txt");
+    fs::write(&file_path, "a").unwrap();
+    fs::hard_link(&file_path, &link_path).unwrap();
+    let hash = "0".repeat(128);
+
+    let cache = InodeCache::default();
+    cache.insert(
+        file_path.to_str().unwrap(),
+        HashAlgorithm::Blake2b(64),
+        &hash,
+    );
+    let link_path = link_path.to_str().unwrap();
+    assert_eq!(cache.lookup(link_path, HashAlgorithm::Sha512), None);
+    assert_eq!(
+        cache.lookup(link_path, HashAlgorithm::Blake2b(64)),
+        Some(hash)
+    );
+}
+
+#[test]
+fn benchmark_collects_file_stats() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut list = String::new();
+    for (idx, len) in [1000, 0, 5000].into_iter().enumerate() {
+        let file_path = dir.path().join(format!("{idx}.bin"));
+        fs::write(&file_path, vec![b'x'; len]).unwrap();
+        let status = StatusWrapper::Status(Arc::new(RwLock::new(Status::default())));
+        let hash = hash_file(&file_path, HashAlgorithm::Md5, &Setting::default(), &status).unwrap();
+        list.push_str(&format!("{hash}  {}\n", file_path.display()));
+    }
+    list.push_str(&format!(
+        "{}  missing.bin\n",
+        HashAlgorithm::Md5.empty_hash()
+    ));
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(&list_path, list).unwrap();
+
+    let setting = SettingBuilder::new().benchmark(true).build().unwrap();
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    let status = StatusWrapper::Status(Arc::new(RwLock::new(Status::default())));
+    let (tx, _rx) = channel::unbounded();
+    let summary = prepare_hashing(
+        hash_list.pairs,
+        &setting,
+        status,
+        tx,
+        &Pause::default(),
+        &AtomicBool::new(false),
+        &list_path,
+    );
+    let mut bytes: Vec<_> = summary.file_stats.iter().map(|x| x.bytes).collect();
+    bytes.sort();
+    assert_eq!(bytes, [0, 1000, 5000]);
+
+    let stats = |bytes, millis| FileStats {
+        path: format!("{bytes}"),
+        bytes,
+        duration: Duration::from_millis(millis),
+    };
+    let benchmark = BenchmarkSummary::new(&[
+        stats(1_000_000, 1000),
+        stats(3_000_000, 1000),
+        stats(2_000_000, 500),
+        stats(0, 2000),
+    ]);
+    assert_eq!(benchmark.file_num, 4);
+    assert_eq!(benchmark.total_bytes, 6_000_000);
+    assert_eq!(benchmark.median_mb_per_s, 3.0);
+    assert_eq!(benchmark.p95_mb_per_s, 4.0);
+    assert_eq!(benchmark.slowest[0].bytes, 0);
+    let fastest: Vec<_> = benchmark.fastest.iter().map(|x| x.bytes).collect();
+    assert_eq!(fastest, [2_000_000, 3_000_000, 1_000_000]);
+}
+
+#[test]
+fn profile_and_recommend_block_size() {
+    let dir = tempfile::tempdir().unwrap();
+    let sample = dir.path().join("sample.bin");
+    fs::write(&sample, vec![0u8; 1024 * 1024]).unwrap();
+    let profile = profile_block_sizes(&sample, HashAlgorithm::Md5, &Setting::default()).unwrap();
+    let block_sizes: Vec<_> = profile.iter().map(|x| x.0).collect();
+    assert_eq!(block_sizes, PROFILE_BLOCK_SIZES);
+    assert!(profile.iter().all(|x| x.1 > 0.0));
+
+    // Within 5% of the fastest, so the smaller one is recommended
+    let profile = [(4096, 100.0), (8192, 196.0), (16384, 200.0), (32768, 150.0)];
+    assert_eq!(recommend_block_size(&profile), Some(8192));
+    assert_eq!(recommend_block_size(&[]), None);
+}
+
+#[test]
+fn auto_threads_unless_set() {
+    let dir = tempfile::tempdir().unwrap();
+    let detected = detect_threads(dir.path());
+    assert!(detected.threads >= 1);
+    if detected.rotational == Some(true) {
+        assert_eq!(detected.threads, 1);
+    }
+
+    assert_eq!(Setting::default().auto_threads(dir.path()), Some(detected));
+    let manual = SettingBuilder::new().threads(3).build().unwrap();
+    assert_eq!(manual.auto_threads(dir.path()), None);
+    let sequential = SettingBuilder::new().parallel(false).build().unwrap();
+    assert_eq!(sequential.auto_threads(dir.path()), None);
+    let disabled = SettingBuilder::new().parallel_auto(false).build().unwrap();
+    assert_eq!(disabled.auto_threads(dir.path()), None);
+}
+
+#[test]
+fn dry_run_checks_files_without_hashing() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "hello\n").unwrap();
+    fs::write(dir.path().join("b.txt"), "hello world\n").unwrap();
+    fs::create_dir(dir.path().join("dir")).unwrap();
+    let list_path = dir.path().join("MD5SUMS");
+    let list: String = ["a.txt", "b.txt", "dir", "missing.txt", "excluded.txt"]
+        .iter()
+        .map(|x| {
+            format!(
+                "00000000000000000000000000000000  {}\n",
+                dir.path().join(x).display()
+            )
+        })
+        .collect();
+    fs::write(&list_path, list).unwrap();
+
+    let setting = SettingBuilder::new()
+        .exclude(Pattern::new("*excluded*").unwrap())
+        .build()
+        .unwrap();
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    let report = dry_run_hash_list(&hash_list.pairs, &setting);
+    assert_eq!(report.verifiable_num, 2);
+    assert_eq!(report.total_bytes, 18);
+    assert_eq!(report.missing.len(), 1);
+    assert!(report.missing[0].ends_with("missing.txt"));
+    assert_eq!(report.unreadable.len(), 1);
+    assert!(report.unreadable[0].0.ends_with("dir"));
+}
+
+#[test]
+fn discover_hash_lists() {
+    let dir = tempfile::tempdir().unwrap();
+    assert_eq!(auto_find_hash_list(dir.path()), None);
+
+    fs::write(dir.path().join("SHA256SUMS"), "").unwrap();
+    fs::write(dir.path().join("notes.txt"), "").unwrap();
+    fs::create_dir(dir.path().join("MD5SUMS.d")).unwrap();
+    assert_eq!(
+        auto_find_hash_list(dir.path()),
+        Some(dir.path().join("SHA256SUMS"))
+    );
+
+    fs::write(dir.path().join("MD5SUMS"), "").unwrap();
+    assert_eq!(auto_find_hash_list(dir.path()), None);
+    assert_eq!(
+        find_hash_lists(dir.path()).unwrap(),
+        [dir.path().join("MD5SUMS"), dir.path().join("SHA256SUMS")]
+    );
+}
+
+#[test]
+fn hash_pair_fields() {
+    let dir = tempfile::tempdir().unwrap();
+    let list_path = dir.path().join("SHA256SUMS");
+    let sha256 = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+    fs::write(&list_path, format!("SHA256 (hello.txt) = {sha256}\n")).unwrap();
+
+    let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+    let pair = &hash_list.pairs[0];
+    assert_eq!(pair.file_path(), "hello.txt");
+    assert_eq!(pair.expected_hash(), sha256);
+    assert_eq!(pair.algorithm(), HashAlgorithm::Sha256);
+}
+
+#[test]
+fn add_outcomes_of_several_hash_lists() {
+    let correct = VerificationOutcome {
+        correct_num: 3,
+        elapsed: Duration::from_secs(1),
+        ..Default::default()
+    };
+    let incorrect = VerificationOutcome {
+        correct_num: 1,
+        incorrect_num: 2,
+        elapsed: Duration::from_secs(2),
+        ..Default::default()
+    };
+    let missing = VerificationOutcome {
+        missing_num: 1,
+        aborted: true,
+        ..Default::default()
+    };
+
+    let mut outcome = correct;
+    outcome += incorrect;
+    assert_eq!((outcome.correct_num, outcome.incorrect_num), (4, 2));
+    assert_eq!(outcome.elapsed, Duration::from_secs(3));
+    assert_eq!(outcome.exit_code(false), incorrect.exit_code(false));
+    outcome += missing;
+    assert!(outcome.aborted);
+    assert_eq!(outcome.exit_code(false), missing.exit_code(false));
+}
+
+#[test]
+fn summarize_each_hash_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("a.txt");
+    fs::write(&file_path, "a").unwrap();
+    let summarize = |name: &str, hash: &str| {
+        let list_path = dir.path().join(name);
+        fs::write(&list_path, format!("{hash}  {}\n", file_path.display())).unwrap();
+        let hash_list = hash_list_parser(&list_path, None, DuplicatePolicy::Warn, false).unwrap();
+        HashVerifier::new(hash_list, Setting::default()).summary(&list_path)
+    };
+
+    let mut summary = summarize("correct.md5", "0cc175b9c0f1b6a831c399e269772661");
+    summary += summarize("incorrect.md5", &"0".repeat(32));
+    assert_eq!(
+        (summary.outcome.correct_num, summary.outcome.incorrect_num),
+        (1, 1)
+    );
+    assert_eq!(summary.incorrect_files.len(), 1);
+
+    let [(correct_path, correct), (incorrect_path, incorrect)] = &summary.hash_lists[..] else {
+        panic!("A summary per hash list");
+    };
+    assert!(correct_path.ends_with("correct.md5"));
+    assert_eq!(
+        (correct.outcome.correct_num, correct.outcome.incorrect_num),
+        (1, 0)
+    );
+    assert!(incorrect_path.ends_with("incorrect.md5"));
+    assert_eq!(incorrect.outcome.incorrect_num, 1);
+    assert_eq!(incorrect.incorrect_files, summary.incorrect_files);
+}
+
+#[test]
+fn notification_body_counts_failures() {
+    let correct = VerificationOutcome {
+        correct_num: 3,
+        ..Default::default()
+    };
+    assert_eq!(notification_body(&correct), "All 3 files OK");
+
+    let failed = VerificationOutcome {
+        correct_num: 3,
+        incorrect_num: 2,
+        missing_num: 1,
+        error_num: 1,
+        ..Default::default()
+    };
+    assert_eq!(notification_body(&failed), "FAILED: 2 mismatches, 2 errors");
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_handling() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("target.txt");
+    let link = dir.path().join("link.txt");
+    fs::write(&target, "hello\n").unwrap();
+    std::os::unix::fs::symlink("target.txt", &link).unwrap();
+    let status = StatusWrapper::Status(Arc::new(RwLock::new(Status::default())));
+    let hash = |setting: &Setting| hash_file(&link, HashAlgorithm::Md5, setting, &status);
+
+    let follow = Setting::default();
+    assert_eq!(hash(&follow).unwrap(), "b1946ac92492d2347c6235b4d2611184");
+    let no_follow = SettingBuilder::new()
+        .follow_symlinks(false)
+        .build()
+        .unwrap();
+    assert!(matches!(hash(&no_follow), Err(HashError::IsSymlink { .. })));
+    let link_target = SettingBuilder::new()
+        .hash_symlink_target(true)
+        .build()
+        .unwrap();
+    // MD5 of "target.txt"
+    assert_eq!(
+        hash(&link_target).unwrap(),
+        "4d6f333d2bc24ffddcca34414a0cb12d"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn bash_completions_are_valid() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("md5check.bash");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_md5check"))
+        .args(["--generate-completions", "bash"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let completions = String::from_utf8(output.stdout).unwrap();
+    assert!(completions.contains("--file-path"));
+    fs::write(&script, completions).unwrap();
+
+    let status = std::process::Command::new("bash")
+        .arg("-n")
+        .arg(&script)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn sigterm_saves_checkpoint_and_exits_130() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut list = String::new();
+    for idx in 0..5 {
+        let file_path = dir.path().join(format!("{idx}.bin"));
+        fs::write(&file_path, vec![idx as u8; 4_000_000]).unwrap();
+        let status = StatusWrapper::Status(Arc::new(RwLock::new(Status::default())));
+        let hash = hash_file(&file_path, HashAlgorithm::Md5, &Setting::default(), &status).unwrap();
+        list.push_str(&format!("{hash}  {idx}.bin\n"));
+    }
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(&list_path, list).unwrap();
+    let checkpoint = dir.path().join(".md5check_progress");
+
+    // About a second per file
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_md5check"))
+        .arg("-f")
+        .arg(&list_path)
+        .args(["--rate-limit", "4", "--checkpoint-file"])
+        .arg(&checkpoint)
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(1500));
+    let killed = std::process::Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+
+    let status = child.wait().unwrap();
+    assert_eq!(status.code(), Some(130));
+    let saved: Vec<serde_json::Value> =
+        serde_json::from_str(&fs::read_to_string(&checkpoint).unwrap()).unwrap();
+    assert!(!saved.is_empty() && saved.len() < 5);
+    assert!(saved.iter().all(|x| x["result"] == "correct"));
+}
+
+#[cfg(unix)]
+#[test]
+fn sigterm_saves_checkpoint_when_files_hang() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "a").unwrap();
+    // Reading it never finishes, like a file on a hung network share
+    let made = std::process::Command::new("mkfifo")
+        .arg(dir.path().join("pipe"))
+        .status()
+        .unwrap();
+    assert!(made.success());
+    let list_path = dir.path().join("MD5SUMS");
+    fs::write(
+        &list_path,
+        "0cc175b9c0f1b6a831c399e269772661  a.txt\n00000000000000000000000000000000  pipe\n",
+    )
+    .unwrap();
+    let checkpoint = dir.path().join(".md5check_progress");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_md5check"))
+        .arg("-f")
+        .arg(&list_path)
+        .arg("--checkpoint-file")
+        .arg(&checkpoint)
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    let killed = std::process::Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+
+    let status = child.wait().unwrap();
+    assert_eq!(status.code(), Some(130));
+    let saved: Vec<serde_json::Value> =
+        serde_json::from_str(&fs::read_to_string(&checkpoint).unwrap()).unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0]["path"], "a.txt");
+}
+
+#[test]
+fn status_only_sets_exit_code() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "a").unwrap();
+    let list_path = dir.path().join("MD5SUMS");
+    let run = || {
+        std::process::Command::new(env!("CARGO_BIN_EXE_md5check"))
+            .arg("-f")
+            .arg(&list_path)
+            .arg("--status")
+            .output()
+            .unwrap()
+    };
+
+    fs::write(&list_path, "0cc175b9c0f1b6a831c399e269772661  a.txt\n").unwrap();
+    let output = run();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+
+    fs::write(&list_path, "00000000000000000000000000000000  a.txt\n").unwrap();
+    let output = run();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+
+    fs::write(
+        &list_path,
+        "0cc175b9c0f1b6a831c399e269772661  missing.txt\n",
+    )
+    .unwrap();
+    let output = run();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+}
+
+#[test]
+fn status_severity_of_results() {
+    let mut status = Status::default();
+    status.correct_num = 3;
+    status.skipped_num = 1;
+    assert_eq!(status.severity(), Severity::Correct);
+
+    status.extra_num = 1;
+    assert_eq!(status.severity(), Severity::Incorrect);
+    status.extra_num = 0;
+    status.incorrect_num = 1;
+    assert_eq!(status.severity(), Severity::Incorrect);
+
+    status.missing_num = 1;
+    assert_eq!(status.severity(), Severity::Error);
+    status.missing_num = 0;
+    status.error_num = 1;
+    assert_eq!(status.severity(), Severity::Error);
+}
+
+#[test]
+fn progress_bar_counts_bytes_read() {
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("a.bin");
+    fs::write(&file_path, vec![0u8; 300_000]).unwrap();
+    let bytes_read = Arc::new(AtomicU64::new(0));
+    let status = StatusWrapper::ProgressBar(ProgressBar::hidden(), Arc::clone(&bytes_read));
+
+    for _ in 0..2 {
+        hash_file(&file_path, HashAlgorithm::Md5, &Setting::default(), &status).unwrap();
+    }
+    assert_eq!(bytes_read.load(Ordering::Relaxed), 600_000);
+}
+
+#[test]
+fn color_mode_overrides_terminal_detection() {
+    assert!(ColorMode::Always.enabled());
+    assert!(!ColorMode::Never.enabled());
+    assert_eq!(Setting::default().color, ColorMode::Auto);
+}